@@ -0,0 +1,1537 @@
+// Abstract syntax tree lowered from the concrete parse tree
+use std::fmt;
+
+use crate::cfg::{CfgTerm, ParseNode};
+use crate::lex::{number::NumLiteral, Span};
+
+/// Binary operators, in the order of the grammar rules that parse them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    /// two factors written side by side, as in `2x`, with
+    /// [`crate::ParseOptions::implicit_multiplication`] set. It multiplies
+    /// like [`BinOp::Mul`] but binds tighter than it.
+    ImplicitMul,
+    Div,
+    /// remainder
+    Rem,
+    /// integer division, `//`
+    IntDiv,
+    Pow,
+}
+
+impl BinOp {
+    /// binding strength, operators with a higher precedence bind tighter
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 4,
+            BinOp::Mul | BinOp::Div | BinOp::Rem | BinOp::IntDiv => 5,
+            BinOp::ImplicitMul => 6,
+            BinOp::Pow => 8,
+        }
+    }
+
+    /// `^` groups to the right, every other operator to the left
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinOp::Pow)
+    }
+
+    /// the operator as written, empty for [`BinOp::ImplicitMul`]
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::ImplicitMul => "",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::IntDiv => "//",
+            BinOp::Pow => "^",
+        }
+    }
+
+    fn from_term(term: &CfgTerm) -> Option<BinOp> {
+        match term {
+            CfgTerm::TermPlus => Some(BinOp::Add),
+            CfgTerm::TermMinus => Some(BinOp::Sub),
+            CfgTerm::TermMultiply => Some(BinOp::Mul),
+            CfgTerm::TermImplicitMultiply => Some(BinOp::ImplicitMul),
+            CfgTerm::TermDivide => Some(BinOp::Div),
+            CfgTerm::TermModulo => Some(BinOp::Rem),
+            CfgTerm::TermIntDivide => Some(BinOp::IntDiv),
+            CfgTerm::TermPower => Some(BinOp::Pow),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// Comparison operators, which take two values and give a boolean
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// binding strength, looser than arithmetic and tighter than '&&'
+    pub fn precedence(&self) -> u8 {
+        3
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    fn from_term(term: &CfgTerm) -> Option<CompareOp> {
+        match term {
+            CfgTerm::TermEqual => Some(CompareOp::Eq),
+            CfgTerm::TermNotEqual => Some(CompareOp::Ne),
+            CfgTerm::TermLess => Some(CompareOp::Lt),
+            CfgTerm::TermLessEqual => Some(CompareOp::Le),
+            CfgTerm::TermGreater => Some(CompareOp::Gt),
+            CfgTerm::TermGreaterEqual => Some(CompareOp::Ge),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// Boolean operators, which only evaluate their right operand when the left
+/// one does not decide the result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogicOp {
+    And,
+    Or,
+}
+
+impl LogicOp {
+    /// binding strength, '&&' binds tighter than '||'
+    pub fn precedence(&self) -> u8 {
+        match self {
+            LogicOp::Or => 1,
+            LogicOp::And => 2,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LogicOp::And => "&&",
+            LogicOp::Or => "||",
+        }
+    }
+}
+
+impl fmt::Display for LogicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// Postfix operators, which bind tighter than every other operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PostfixOp {
+    /// `n!`
+    Factorial,
+    /// `x%`, which is `x / 100`
+    Percent,
+}
+
+impl PostfixOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PostfixOp::Factorial => "!",
+            PostfixOp::Percent => "%",
+        }
+    }
+}
+
+impl fmt::Display for PostfixOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// binding strength of a prefix '-' or '!', tighter than every binary
+/// operator but '^', so that -2^2 is -(2^2)
+const UNARY_PRECEDENCE: u8 = 7;
+
+/// binding strength of a postfix '!' or '%', tighter than '^', so that
+/// 2^3! is 2^(3!)
+const POSTFIX_PRECEDENCE: u8 = 9;
+
+/// How a printed expression starts or ends, as far as printing an implicit
+/// product needs to know: two factors written side by side must not run
+/// together into one token, or into a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    /// a name, starting (or ending) with the character
+    Name(char),
+    /// a number, `plain` unless it has a letter in it, such as `0xff` or
+    /// `1e5`
+    Number { plain: bool },
+    /// a postfix '%', which an operand after it would make a remainder
+    Percent,
+    /// a '|' opening an absolute value
+    Bar,
+    /// a '(', or a ')', '|' or '!' ending an operand
+    Bracket,
+    /// a string or a prefix operator
+    Other,
+}
+
+/// binding strength of a conditional, looser than every operator
+const CONDITIONAL_PRECEDENCE: u8 = 0;
+
+/// An expression with the grammar scaffolding (start rule, precedence
+/// levels, parentheses) of the parse tree stripped away. Every node keeps the
+/// span of the input it was lowered from.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Expr {
+    Num {
+        value: NumLiteral,
+        span: Span,
+    },
+    /// a string literal, only functions take and return strings
+    Str {
+        value: String,
+        span: Span,
+    },
+    /// `true` or `false`
+    Bool {
+        value: bool,
+        span: Span,
+    },
+    /// a variable, looked up in the environment when evaluated
+    Var {
+        name: String,
+        span: Span,
+    },
+    /// a function call, `name_span` covers the function name
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        name_span: Span,
+        span: Span,
+    },
+    /// negation of the operand, a unary '+' is dropped while lowering
+    Neg {
+        operand: Box<Expr>,
+        span: Span,
+    },
+    /// `!operand`, the boolean opposite of the operand
+    Not {
+        operand: Box<Expr>,
+        span: Span,
+    },
+    /// `operand!` or `operand%`
+    Postfix {
+        op: PostfixOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    /// `|operand|`, the absolute value of the operand
+    Abs {
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    Compare {
+        op: CompareOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    Logic {
+        op: LogicOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    /// `cond ? then_branch : else_branch`, also written as
+    /// `if cond then then_branch else else_branch`. Only the chosen branch
+    /// is evaluated.
+    Conditional {
+        cond: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+        span: Span,
+    },
+}
+
+impl Expr {
+    /// the part of the input this expression was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Num { span, .. }
+            | Expr::Str { span, .. }
+            | Expr::Bool { span, .. }
+            | Expr::Var { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Neg { span, .. }
+            | Expr::Not { span, .. }
+            | Expr::Postfix { span, .. }
+            | Expr::Abs { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Compare { span, .. }
+            | Expr::Logic { span, .. }
+            | Expr::Conditional { span, .. } => *span,
+        }
+    }
+
+    /// names of the variables the expression refers to, each listed once in
+    /// the order they first appear in the input
+    pub fn free_variables(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in self.variables() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// every use of a variable, with its span, in input order. The
+    /// expressions left to visit are kept on a stack rather than recursed
+    /// into, as a long chain such as `1 + 2 + ... + 1` nests as deeply as it
+    /// is long.
+    pub(crate) fn variables(&self) -> Vec<(&str, Span)> {
+        let mut variables = Vec::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expr::Num { .. } | Expr::Str { .. } | Expr::Bool { .. } => {}
+                Expr::Var { name, span } => variables.push((name.as_str(), *span)),
+                Expr::Call { args, .. } => pending.extend(args.iter().rev()),
+                Expr::Neg { operand, .. }
+                | Expr::Not { operand, .. }
+                | Expr::Postfix { operand, .. }
+                | Expr::Abs { operand, .. } => pending.push(operand),
+                Expr::Binary { lhs, rhs, .. }
+                | Expr::Compare { lhs, rhs, .. }
+                | Expr::Logic { lhs, rhs, .. } => {
+                    pending.push(rhs);
+                    pending.push(lhs);
+                }
+                Expr::Conditional {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    pending.push(else_branch);
+                    pending.push(then_branch);
+                    pending.push(cond);
+                }
+            }
+        }
+        variables
+    }
+
+    /// the operands and symbol of a binary operator, a comparison or a
+    /// boolean operator, and whether each operand needs parentheses when
+    /// printed. The symbol of an implicit product is what separates its
+    /// factors, nothing or a space.
+    fn binary_operands(&self) -> Option<(&Expr, &'static str, &Expr, (bool, bool))> {
+        let (lhs, symbol, rhs, needs_parens) = match self {
+            Expr::Binary {
+                op: BinOp::ImplicitMul,
+                lhs,
+                rhs,
+                ..
+            } => {
+                let (symbol, needs_parens) = implicit_product_layout(lhs, rhs);
+                (lhs, symbol, rhs, needs_parens)
+            }
+            Expr::Binary { op, lhs, rhs, .. } => {
+                // an operand on the side the operator does not associate
+                // towards needs parentheses at equal precedence too
+                let needs_parens = if op.is_right_associative() {
+                    // the exponent is parsed as a unary_expr, so a negation
+                    // there needs no parentheses
+                    (
+                        lhs.precedence() <= op.precedence(),
+                        rhs.precedence() < UNARY_PRECEDENCE,
+                    )
+                } else {
+                    (
+                        lhs.precedence() < op.precedence(),
+                        rhs.precedence() <= op.precedence(),
+                    )
+                };
+                (lhs, op.symbol(), rhs, needs_parens)
+            }
+            // comparisons do not chain, so an operand that is itself a
+            // comparison needs parentheses on either side
+            Expr::Compare { op, lhs, rhs, .. } => (
+                lhs,
+                op.symbol(),
+                rhs,
+                (
+                    lhs.precedence() <= op.precedence(),
+                    rhs.precedence() <= op.precedence(),
+                ),
+            ),
+            Expr::Logic { op, lhs, rhs, .. } => (
+                lhs,
+                op.symbol(),
+                rhs,
+                (
+                    lhs.precedence() < op.precedence(),
+                    rhs.precedence() <= op.precedence(),
+                ),
+            ),
+            _ => return None,
+        };
+        Some((lhs, symbol, rhs, needs_parens))
+    }
+
+    /// how the printed expression starts
+    fn first_edge(&self) -> Edge {
+        let mut expr = self;
+        loop {
+            expr = match expr {
+                Expr::Num { value, .. } => return Edge::number(value),
+                Expr::Var { name, .. } | Expr::Call { name, .. } => {
+                    return Edge::Name(name.chars().next().unwrap_or('_'))
+                }
+                Expr::Bool { value, .. } => return Edge::Name(if *value { 't' } else { 'f' }),
+                Expr::Abs { .. } => return Edge::Bar,
+                Expr::Str { .. } | Expr::Neg { .. } | Expr::Not { .. } => return Edge::Other,
+                Expr::Postfix { operand, .. } if operand.precedence() >= POSTFIX_PRECEDENCE => {
+                    operand
+                }
+                Expr::Conditional { cond, .. } if cond.precedence() > CONDITIONAL_PRECEDENCE => {
+                    cond
+                }
+                Expr::Postfix { .. } | Expr::Conditional { .. } => return Edge::Bracket,
+                _ => match expr.binary_operands() {
+                    Some((lhs, _, _, (false, _))) => lhs,
+                    _ => return Edge::Bracket,
+                },
+            };
+        }
+    }
+
+    /// how the printed expression ends
+    fn last_edge(&self) -> Edge {
+        let mut expr = self;
+        loop {
+            expr = match expr {
+                Expr::Num { value, .. } => return Edge::number(value),
+                Expr::Var { name, .. } => return Edge::Name(name.chars().last().unwrap_or('_')),
+                Expr::Bool { .. } => return Edge::Name('e'),
+                Expr::Call { .. } | Expr::Abs { .. } => return Edge::Bracket,
+                Expr::Str { .. } => return Edge::Other,
+                Expr::Postfix {
+                    op: PostfixOp::Percent,
+                    ..
+                } => return Edge::Percent,
+                Expr::Postfix { .. } => return Edge::Bracket,
+                Expr::Neg { operand, .. } | Expr::Not { operand, .. }
+                    if operand.precedence() >= UNARY_PRECEDENCE =>
+                {
+                    operand
+                }
+                Expr::Neg { .. } | Expr::Not { .. } => return Edge::Bracket,
+                Expr::Conditional { else_branch, .. } => else_branch,
+                _ => match expr.binary_operands() {
+                    Some((_, _, rhs, (_, false))) => rhs,
+                    _ => return Edge::Bracket,
+                },
+            };
+        }
+    }
+
+    /// binding strength of the expression, used to decide where printing
+    /// needs parentheses
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Num { .. }
+            | Expr::Str { .. }
+            | Expr::Bool { .. }
+            | Expr::Var { .. }
+            | Expr::Call { .. }
+            | Expr::Abs { .. } => u8::MAX,
+            Expr::Neg { .. } | Expr::Not { .. } => UNARY_PRECEDENCE,
+            Expr::Postfix { .. } => POSTFIX_PRECEDENCE,
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Compare { op, .. } => op.precedence(),
+            Expr::Logic { op, .. } => op.precedence(),
+            Expr::Conditional { .. } => CONDITIONAL_PRECEDENCE,
+        }
+    }
+}
+
+impl Edge {
+    fn number(value: &NumLiteral) -> Edge {
+        Edge::Number {
+            plain: value
+                .text()
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '.' || c == '_'),
+        }
+    }
+}
+
+/// what separates the factors of the implicit product `lhs rhs` when
+/// printed, and whether each needs parentheses. The right factor is parsed
+/// as a power_expr. It is kept from running into the left one, so that `x y`
+/// keeps its space and `2x` needs none, and from being taken for the
+/// arguments of a call, the operand of a remainder, or the '|' closing an
+/// absolute value.
+fn implicit_product_layout(lhs: &Expr, rhs: &Expr) -> (&'static str, (bool, bool)) {
+    let mut lhs_needs_parens = lhs.precedence() < BinOp::ImplicitMul.precedence();
+    let mut rhs_needs_parens = rhs.precedence() < BinOp::Pow.precedence();
+    let lhs_edge = if lhs_needs_parens {
+        Edge::Bracket
+    } else {
+        lhs.last_edge()
+    };
+    if !rhs_needs_parens {
+        rhs_needs_parens = match rhs.first_edge() {
+            // a number right after another one is not a product
+            Edge::Number { .. } => matches!(lhs_edge, Edge::Number { .. }),
+            Edge::Name(_) => false,
+            _ => true,
+        };
+    }
+    if matches!(lhs_edge, Edge::Percent) || (rhs_needs_parens && matches!(lhs_edge, Edge::Name(_)))
+    {
+        lhs_needs_parens = true;
+    }
+    let lhs_edge = if lhs_needs_parens {
+        Edge::Bracket
+    } else {
+        lhs_edge
+    };
+    let rhs_edge = if rhs_needs_parens {
+        Edge::Bracket
+    } else {
+        rhs.first_edge()
+    };
+    let separator = match (lhs_edge, rhs_edge) {
+        // 2x, but not 2 e1 or 2 _x
+        (Edge::Number { plain: true }, Edge::Name(c)) if !matches!(c, 'e' | 'E' | '_') => "",
+        (Edge::Name(_) | Edge::Number { .. }, Edge::Name(_) | Edge::Number { .. }) => " ",
+        _ => "",
+    };
+    (separator, (lhs_needs_parens, rhs_needs_parens))
+}
+
+/// prints `operand`, in parentheses if it needs them
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expr, needs_parens: bool) -> fmt::Result {
+    if needs_parens {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+/// prints `lhs symbol rhs`, with each operand in parentheses if it needs
+/// them. A left operand printed without parentheses that is an operator
+/// itself is printed in the same loop rather than recursed into, so that a
+/// long chain such as `1 + 2 + ... + 1` prints in constant stack space.
+fn write_binary(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+    // the operators from the outermost one in, with their right operands
+    let mut chain = Vec::new();
+    let mut lhs = expr;
+    let mut lhs_needs_parens = false;
+    while !lhs_needs_parens {
+        let Some((inner_lhs, symbol, rhs, needs_parens)) = lhs.binary_operands() else {
+            break;
+        };
+        chain.push((symbol, rhs, needs_parens.1));
+        lhs = inner_lhs;
+        lhs_needs_parens = needs_parens.0;
+    }
+    write_operand(f, lhs, lhs_needs_parens)?;
+    for (symbol, rhs, rhs_needs_parens) in chain.into_iter().rev() {
+        // the factors of an implicit product are separated by a space at
+        // most
+        if symbol.trim().is_empty() {
+            write!(f, "{}", symbol)?;
+        } else {
+            write!(f, " {} ", symbol)?;
+        }
+        write_operand(f, rhs, rhs_needs_parens)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Expr {
+    /// prints the expression with only the parentheses needed to keep its
+    /// shape
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Num { value, .. } => write!(f, "{}", value),
+            Expr::Str { value, .. } => write!(f, "{}", crate::lex::quote(value)),
+            Expr::Bool { value, .. } => write!(f, "{}", value),
+            Expr::Var { name, .. } => write!(f, "{}", name),
+            Expr::Call { name, args, .. } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Neg { operand, .. } => {
+                if operand.precedence() < UNARY_PRECEDENCE {
+                    write!(f, "-({})", operand)
+                } else {
+                    write!(f, "-{}", operand)
+                }
+            }
+            Expr::Not { operand, .. } => {
+                if operand.precedence() < UNARY_PRECEDENCE {
+                    write!(f, "!({})", operand)
+                } else {
+                    write!(f, "!{}", operand)
+                }
+            }
+            Expr::Postfix { .. } => {
+                // a chain such as `3!!` is printed in a loop, as it may be
+                // long
+                let mut ops = Vec::new();
+                let mut operand = self;
+                while let Expr::Postfix {
+                    op, operand: inner, ..
+                } = operand
+                {
+                    ops.push(op);
+                    operand = inner;
+                }
+                write_operand(f, operand, operand.precedence() < POSTFIX_PRECEDENCE)?;
+                for op in ops.into_iter().rev() {
+                    write!(f, "{}", op)?;
+                }
+                Ok(())
+            }
+            Expr::Abs { operand, .. } => write!(f, "|{}|", operand),
+            Expr::Binary { .. } | Expr::Compare { .. } | Expr::Logic { .. } => {
+                write_binary(f, self)
+            }
+            // conditionals group to the right, so only a condition that is
+            // itself a conditional needs parentheses
+            Expr::Conditional {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if matches!(**cond, Expr::Conditional { .. }) {
+                    write!(f, "({})", cond)?;
+                } else {
+                    write!(f, "{}", cond)?;
+                }
+                write!(f, " ? {} : {}", then_branch, else_branch)
+            }
+        }
+    }
+}
+
+/// A statement of a program
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Statement {
+    /// `name = value`, binds the variable for the statements after it
+    Assign {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    /// `name(params) = body`, defines a function for the statements after
+    /// it. Variables in the body other than the parameters are looked up
+    /// when the function is called.
+    Define {
+        name: String,
+        params: Vec<String>,
+        body: Expr,
+        span: Span,
+    },
+    Expr(Expr),
+}
+
+impl Statement {
+    /// the part of the input this statement was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Assign { span, .. } | Statement::Define { span, .. } => *span,
+            Statement::Expr(expr) => expr.span(),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign { name, value, .. } => write!(f, "{} = {}", name, value),
+            Statement::Define {
+                name, params, body, ..
+            } => write!(f, "{}({}) = {}", name, params.join(", "), body),
+            Statement::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// A list of statements run in order, its value is the value of the last
+/// statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+impl Program {
+    /// names of the variables the program needs a value for, each listed
+    /// once in the order they first appear in the input: those it uses
+    /// before assigning them, and those a function body uses that are
+    /// neither its parameters nor assigned anywhere in the program
+    pub fn free_variables(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let mut assigned: Vec<&str> = Vec::new();
+        for statement in self.statements.iter() {
+            let (expr, params) = match statement {
+                Statement::Assign { value, .. } => (value, &[][..]),
+                Statement::Define { params, body, .. } => (body, params.as_slice()),
+                Statement::Expr(expr) => (expr, &[][..]),
+            };
+            for (name, _) in expr.variables() {
+                let needed = match statement {
+                    Statement::Define { .. } => {
+                        !params.iter().any(|param| param == name) && !self.assigns(name)
+                    }
+                    _ => !assigned.contains(&name),
+                };
+                if needed && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            if let Statement::Assign { name, .. } = statement {
+                assigned.push(name);
+            }
+        }
+        names
+    }
+
+    /// whether a statement of the program assigns `name`
+    fn assigns(&self, name: &str) -> bool {
+        self.statements
+            .iter()
+            .any(|statement| matches!(statement, Statement::Assign { name: assigned, .. } if assigned == name))
+    }
+
+    /// every use of a variable outside function bodies before a statement
+    /// assigns it, with its span, in input order. The variables of a body
+    /// are only looked up when the function is called.
+    pub(crate) fn unassigned_variables(&self) -> Vec<(&str, Span)> {
+        let mut assigned: Vec<&str> = Vec::new();
+        let mut variables = Vec::new();
+        for statement in self.statements.iter() {
+            let expr = match statement {
+                Statement::Assign { value, .. } => value,
+                Statement::Define { .. } => continue,
+                Statement::Expr(expr) => expr,
+            };
+            variables.extend(
+                expr.variables()
+                    .into_iter()
+                    .filter(|(name, _)| !assigned.contains(name)),
+            );
+            if let Statement::Assign { name, .. } = statement {
+                assigned.push(name);
+            }
+        }
+        variables
+    }
+}
+
+impl fmt::Display for Program {
+    /// prints the statements separated by "; "
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parse tree that does not have the shape the parser produces
+#[derive(Debug, PartialEq)]
+pub struct LowerError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl LowerError {
+    fn new(node: &ParseNode, message: &str) -> Self {
+        LowerError {
+            message: format!("{}: {}", node.kind(), message),
+            span: node.span(),
+        }
+    }
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed parse tree at {}", self.message)
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+impl TryFrom<&ParseNode> for Expr {
+    type Error = LowerError;
+
+    /// lowers the node by the kind of node it is. Each kind is lowered by a
+    /// function of its own, which keeps this function's stack frame, which
+    /// is on the stack once for every level of nesting, small.
+    fn try_from(node: &ParseNode) -> Result<Self, Self::Error> {
+        match node.kind() {
+            CfgTerm::NonTermStartRule => match node.children() {
+                [statement] => Expr::try_from(statement),
+                _ => Err(LowerError::new(node, "expected a single expression")),
+            },
+            CfgTerm::NonTermAssignment => Err(LowerError::new(
+                node,
+                "expected an expression, found an assignment",
+            )),
+            CfgTerm::NonTermDefinition => Err(LowerError::new(
+                node,
+                "expected an expression, found a function definition",
+            )),
+            CfgTerm::NonTermExpr
+            | CfgTerm::NonTermMultiDivExpr
+            | CfgTerm::NonTermAndExpr
+            | CfgTerm::NonTermOrExpr
+            | CfgTerm::NonTermPostfixExpr => lower_left_chain(node),
+            CfgTerm::NonTermPowerExpr | CfgTerm::NonTermComparisonExpr => match node.children() {
+                [lhs, op, rhs] => lower_operator(node, op, Expr::try_from(lhs)?, Some(rhs)),
+                _ => Err(LowerError::new(node, "expected operand op operand")),
+            },
+            CfgTerm::NonTermConditionalExpr => lower_conditional(node),
+            CfgTerm::NonTermUnaryExpr => lower_unary(node),
+            CfgTerm::NonTermTermExpr => match node.children() {
+                [_, inner, _] => Expr::try_from(inner),
+                _ => Err(LowerError::new(node, "expected ( expr )")),
+            },
+            CfgTerm::NonTermAbsExpr => match node.children() {
+                [_, operand, _] => Ok(Expr::Abs {
+                    operand: Box::new(Expr::try_from(operand)?),
+                    span: node.span(),
+                }),
+                _ => Err(LowerError::new(node, "expected | expr |")),
+            },
+            CfgTerm::NonTermCallExpr => lower_call(node),
+            _ => lower_terminal(node),
+        }
+    }
+}
+
+/// lowers a number, string, boolean or variable
+fn lower_terminal(node: &ParseNode) -> Result<Expr, LowerError> {
+    match node.kind() {
+        CfgTerm::TermNumber(n) => Ok(Expr::Num {
+            value: n.clone(),
+            span: node.span(),
+        }),
+        CfgTerm::TermString(s) => Ok(Expr::Str {
+            value: s.clone(),
+            span: node.span(),
+        }),
+        CfgTerm::TermBool(b) => Ok(Expr::Bool {
+            value: *b,
+            span: node.span(),
+        }),
+        CfgTerm::TermVariable(name) => Ok(Expr::Var {
+            name: name.clone(),
+            span: node.span(),
+        }),
+        _ => Err(LowerError::new(node, "unexpected node")),
+    }
+}
+
+/// lowers `cond ? a : b` or `if cond then a else b`
+fn lower_conditional(node: &ParseNode) -> Result<Expr, LowerError> {
+    match node.children() {
+        [cond, _, then_branch, _, else_branch] | [_, cond, _, then_branch, _, else_branch] => {
+            Ok(Expr::Conditional {
+                cond: Box::new(Expr::try_from(cond)?),
+                then_branch: Box::new(Expr::try_from(then_branch)?),
+                else_branch: Box::new(Expr::try_from(else_branch)?),
+                span: node.span(),
+            })
+        }
+        _ => Err(LowerError::new(
+            node,
+            "expected cond '?' expr ':' expr or 'if' cond 'then' expr 'else' expr",
+        )),
+    }
+}
+
+/// lowers a prefix '-', '+' or '!'
+fn lower_unary(node: &ParseNode) -> Result<Expr, LowerError> {
+    match node.children() {
+        [op, operand] => match op.kind() {
+            CfgTerm::TermUnaryMinus => Ok(Expr::Neg {
+                operand: Box::new(Expr::try_from(operand)?),
+                span: node.span(),
+            }),
+            CfgTerm::TermNot => Ok(Expr::Not {
+                operand: Box::new(Expr::try_from(operand)?),
+                span: node.span(),
+            }),
+            CfgTerm::TermUnaryPlus => Expr::try_from(operand),
+            _ => Err(LowerError::new(op, "expected a unary operator")),
+        },
+        _ => Err(LowerError::new(node, "expected op operand")),
+    }
+}
+
+/// lowers a call, TermFunction '(' (expr (',' expr)*)? ')'
+fn lower_call(node: &ParseNode) -> Result<Expr, LowerError> {
+    match node.children() {
+        [function, _, rest @ .., _] => {
+            let CfgTerm::TermFunction(name) = function.kind() else {
+                return Err(LowerError::new(function, "expected a function name"));
+            };
+            let args = rest
+                .iter()
+                .step_by(2)
+                .map(Expr::try_from)
+                .collect::<Result<_, _>>()?;
+            Ok(Expr::Call {
+                name: name.clone(),
+                args,
+                name_span: function.span(),
+                span: node.span(),
+            })
+        }
+        _ => Err(LowerError::new(node, "expected name ( args )")),
+    }
+}
+
+/// lowers `node`, the operator `op` applied to `lhs`, the already lowered
+/// left operand, and to `rhs` unless it is a postfix operator
+fn lower_operator(
+    node: &ParseNode,
+    op: &ParseNode,
+    lhs: Expr,
+    rhs: Option<&ParseNode>,
+) -> Result<Expr, LowerError> {
+    let lhs = Box::new(lhs);
+    let span = node.span();
+    let Some(rhs) = rhs else {
+        let op = match op.kind() {
+            CfgTerm::TermFactorial => PostfixOp::Factorial,
+            CfgTerm::TermPercent => PostfixOp::Percent,
+            _ => return Err(LowerError::new(op, "expected a postfix operator")),
+        };
+        return Ok(Expr::Postfix {
+            op,
+            operand: lhs,
+            span,
+        });
+    };
+    let rhs = Box::new(Expr::try_from(rhs)?);
+    match (node.kind(), op.kind()) {
+        (CfgTerm::NonTermComparisonExpr, op_kind) => Ok(Expr::Compare {
+            op: CompareOp::from_term(op_kind)
+                .ok_or_else(|| LowerError::new(op, "expected a comparison operator"))?,
+            lhs,
+            rhs,
+            span,
+        }),
+        (CfgTerm::NonTermAndExpr | CfgTerm::NonTermOrExpr, CfgTerm::TermAnd) => Ok(Expr::Logic {
+            op: LogicOp::And,
+            lhs,
+            rhs,
+            span,
+        }),
+        (CfgTerm::NonTermAndExpr | CfgTerm::NonTermOrExpr, CfgTerm::TermOr) => Ok(Expr::Logic {
+            op: LogicOp::Or,
+            lhs,
+            rhs,
+            span,
+        }),
+        (CfgTerm::NonTermAndExpr | CfgTerm::NonTermOrExpr, _) => {
+            Err(LowerError::new(op, "expected '&&' or '||'"))
+        }
+        (_, op_kind) => Ok(Expr::Binary {
+            op: BinOp::from_term(op_kind)
+                .ok_or_else(|| LowerError::new(op, "expected an operator"))?,
+            lhs,
+            rhs,
+            span,
+        }),
+    }
+}
+
+/// lowers a chain of left associative operators, such as `1 + 2 + 3` or
+/// `3!!`. The parse tree of a chain nests once for each operator, so the
+/// chain is walked down in a loop to its first operand, which is the only
+/// part lowered by recursion.
+fn lower_left_chain(node: &ParseNode) -> Result<Expr, LowerError> {
+    let mut chain = Vec::new();
+    let mut first = node;
+    loop {
+        match (first.kind(), first.children()) {
+            (CfgTerm::NonTermExpr | CfgTerm::NonTermMultiDivExpr, [operand]) => first = operand,
+            (
+                CfgTerm::NonTermExpr
+                | CfgTerm::NonTermMultiDivExpr
+                | CfgTerm::NonTermAndExpr
+                | CfgTerm::NonTermOrExpr,
+                [lhs, op, rhs],
+            ) => {
+                chain.push((first, op, Some(rhs)));
+                first = lhs;
+            }
+            (CfgTerm::NonTermPostfixExpr, [operand, op]) => {
+                chain.push((first, op, None));
+                first = operand;
+            }
+            (
+                CfgTerm::NonTermExpr | CfgTerm::NonTermMultiDivExpr | CfgTerm::NonTermPostfixExpr,
+                _,
+            ) => return Err(LowerError::new(first, "expected operand (op operand)")),
+            (CfgTerm::NonTermAndExpr | CfgTerm::NonTermOrExpr, _) => {
+                return Err(LowerError::new(first, "expected operand op operand"));
+            }
+            _ => break,
+        }
+    }
+    let mut expr = Expr::try_from(first)?;
+    for (node, op, rhs) in chain.into_iter().rev() {
+        expr = lower_operator(node, op, expr, rhs)?;
+    }
+    Ok(expr)
+}
+
+impl TryFrom<&ParseNode> for Statement {
+    type Error = LowerError;
+
+    fn try_from(node: &ParseNode) -> Result<Self, Self::Error> {
+        match (node.kind(), node.children()) {
+            (CfgTerm::NonTermAssignment, [target, _, value]) => match target.kind() {
+                CfgTerm::TermVariable(name) => Ok(Statement::Assign {
+                    name: name.clone(),
+                    value: Expr::try_from(value)?,
+                    span: node.span(),
+                }),
+                _ => Err(LowerError::new(target, "expected a variable")),
+            },
+            (CfgTerm::NonTermAssignment, _) => {
+                Err(LowerError::new(node, "expected VARIABLE '=' expr"))
+            }
+            // definition: TermFunction '(' (VARIABLE (',' VARIABLE)*)? ')' '=' expr
+            (CfgTerm::NonTermDefinition, [function, _, rest @ .., _, _, body]) => {
+                let CfgTerm::TermFunction(name) = function.kind() else {
+                    return Err(LowerError::new(function, "expected a function name"));
+                };
+                let params = rest
+                    .iter()
+                    .step_by(2)
+                    .map(|param| match param.kind() {
+                        CfgTerm::TermVariable(param) => Ok(param.clone()),
+                        _ => Err(LowerError::new(param, "expected a parameter")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Statement::Define {
+                    name: name.clone(),
+                    params,
+                    body: Expr::try_from(body)?,
+                    span: node.span(),
+                })
+            }
+            (CfgTerm::NonTermDefinition, _) => {
+                Err(LowerError::new(node, "expected name ( params ) '=' expr"))
+            }
+            _ => Ok(Statement::Expr(Expr::try_from(node)?)),
+        }
+    }
+}
+
+impl TryFrom<&ParseNode> for Program {
+    type Error = LowerError;
+
+    fn try_from(node: &ParseNode) -> Result<Self, Self::Error> {
+        if node.kind() != &CfgTerm::NonTermStartRule || node.children().is_empty() {
+            return Err(LowerError::new(node, "expected statement (sep statement)*"));
+        }
+        let statements = node
+            .children()
+            .iter()
+            .map(Statement::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(Program { statements })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ParseOptions;
+    use crate::{parse_expr, parse_expr_with};
+
+    #[test]
+    fn test_lower_strips_scaffolding() {
+        let expr = parse_expr("(2)").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Num {
+                value: NumLiteral::new("2"),
+                span: Span::new(1, 2, 1, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn test_lower_binary() {
+        let expr = parse_expr("8 - 2 * 3").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Binary {
+                op: BinOp::Sub,
+                lhs: Box::new(Expr::Num {
+                    value: NumLiteral::new("8"),
+                    span: Span::new(0, 1, 1, 1)
+                }),
+                rhs: Box::new(Expr::Binary {
+                    op: BinOp::Mul,
+                    lhs: Box::new(Expr::Num {
+                        value: NumLiteral::new("2"),
+                        span: Span::new(4, 5, 1, 5)
+                    }),
+                    rhs: Box::new(Expr::Num {
+                        value: NumLiteral::new("3"),
+                        span: Span::new(8, 9, 1, 9)
+                    }),
+                    span: Span::new(4, 9, 1, 5),
+                }),
+                span: Span::new(0, 9, 1, 1),
+            }
+        );
+    }
+
+    #[test]
+    fn test_lower_keeps_left_associativity() {
+        let expr = parse_expr("8 - 2 - 1").unwrap();
+        let Expr::Binary { op, lhs, rhs, .. } = expr else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(op, BinOp::Sub);
+        assert!(matches!(*rhs, Expr::Num { ref value, .. } if value.text() == "1"));
+        assert!(matches!(*lhs, Expr::Binary { op: BinOp::Sub, .. }));
+    }
+
+    #[test]
+    fn test_lower_parens_span() {
+        let s = "(1 + 2) * 3";
+        let expr = parse_expr(s).unwrap();
+        assert_eq!(expr.span(), Span::new(0, 11, 1, 1));
+        let Expr::Binary { lhs, .. } = expr else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(&s[lhs.span().start..lhs.span().end], "1 + 2");
+    }
+
+    #[test]
+    fn test_lower_unary() {
+        let expr = parse_expr("-+3").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Neg {
+                operand: Box::new(Expr::Num {
+                    value: NumLiteral::new("3"),
+                    span: Span::new(2, 3, 1, 3)
+                }),
+                span: Span::new(0, 3, 1, 1),
+            }
+        );
+    }
+
+    #[test]
+    fn test_lower_variables() {
+        let expr = parse_expr("price * qty + shipping - qty").unwrap();
+        assert_eq!(expr.free_variables(), vec!["price", "qty", "shipping"]);
+        let Expr::Binary { rhs, .. } = &expr else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(
+            **rhs,
+            Expr::Var {
+                name: String::from("qty"),
+                span: Span::new(25, 28, 1, 26)
+            }
+        );
+        assert!(parse_expr("-(1 + 2)").unwrap().free_variables().is_empty());
+    }
+
+    #[test]
+    fn test_lower_call() {
+        let expr = parse_expr("min(a, 2 * b)").unwrap();
+        let Expr::Call {
+            name,
+            args,
+            name_span,
+            span,
+        } = &expr
+        else {
+            panic!("Expected a call");
+        };
+        assert_eq!(name, "min");
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[1].to_string(), "2 * b");
+        assert_eq!(*name_span, Span::new(0, 3, 1, 1));
+        assert_eq!(*span, Span::new(0, 13, 1, 1));
+        assert_eq!(expr.free_variables(), vec!["a", "b"]);
+
+        let expr = parse_expr("fx(amount, \"EUR\")").unwrap();
+        let Expr::Call { args, .. } = &expr else {
+            panic!("Expected a call");
+        };
+        assert!(matches!(&args[1], Expr::Str { value, span }
+            if value == "EUR" && *span == Span::new(11, 16, 1, 12)));
+        assert_eq!(expr.free_variables(), vec!["amount"]);
+    }
+
+    #[test]
+    fn test_lower_program() {
+        let program = crate::parse_program("x = y + 1\ny = x * x; x").unwrap();
+        assert_eq!(program.statements.len(), 3);
+        let Statement::Assign { name, value, span } = &program.statements[0] else {
+            panic!("Expected an assignment");
+        };
+        assert_eq!(name, "x");
+        assert_eq!(value.to_string(), "y + 1");
+        assert_eq!(*span, Span::new(0, 9, 1, 1));
+        assert!(matches!(
+            program.statements[2],
+            Statement::Expr(Expr::Var { .. })
+        ));
+        // y is used before it is assigned, x never is
+        assert_eq!(program.free_variables(), vec!["y"]);
+        assert_eq!(program.to_string(), "x = y + 1; y = x * x; x");
+    }
+
+    #[test]
+    fn test_lower_definition() {
+        let program = crate::parse_program("area(w, h) = w * h * scale; area(2, 3)").unwrap();
+        let Statement::Define {
+            name,
+            params,
+            body,
+            span,
+        } = &program.statements[0]
+        else {
+            panic!("Expected a definition");
+        };
+        assert_eq!(name, "area");
+        assert_eq!(params, &["w", "h"]);
+        assert_eq!(body.to_string(), "w * h * scale");
+        assert_eq!(*span, Span::new(0, 26, 1, 1));
+        assert_eq!(program.free_variables(), vec!["scale"]);
+        assert_eq!(
+            program.to_string(),
+            "area(w, h) = w * h * scale; area(2, 3)"
+        );
+        assert_eq!(
+            crate::parse_program("pi() = 3.14").unwrap().to_string(),
+            "pi() = 3.14"
+        );
+        // a body needs no value for a variable the program assigns later
+        let later = crate::parse_program("f(x) = x * scale; scale = 2; f(y)").unwrap();
+        assert_eq!(later.free_variables(), vec!["y"]);
+        assert!(matches!(
+            parse_expr("f(x) = x"),
+            Err(crate::Error::Lower(_))
+        ));
+    }
+
+    #[test]
+    fn test_lower_comparisons_and_logic() {
+        let expr = parse_expr("qty > 0 && !done").unwrap();
+        let Expr::Logic { op, lhs, rhs, span } = &expr else {
+            panic!("Expected a logic expression");
+        };
+        assert_eq!(*op, LogicOp::And);
+        assert_eq!(*span, Span::new(0, 16, 1, 1));
+        assert!(matches!(
+            **lhs,
+            Expr::Compare {
+                op: CompareOp::Gt,
+                ..
+            }
+        ));
+        assert_eq!(
+            **rhs,
+            Expr::Not {
+                operand: Box::new(Expr::Var {
+                    name: String::from("done"),
+                    span: Span::new(12, 16, 1, 13)
+                }),
+                span: Span::new(11, 16, 1, 12),
+            }
+        );
+        assert_eq!(expr.free_variables(), vec!["qty", "done"]);
+        assert_eq!(
+            parse_expr("false").unwrap(),
+            Expr::Bool {
+                value: false,
+                span: Span::new(0, 5, 1, 1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_lower_postfix() {
+        let expr = parse_expr("n! * 5%").unwrap();
+        let Expr::Binary { lhs, rhs, .. } = &expr else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(
+            **lhs,
+            Expr::Postfix {
+                op: PostfixOp::Factorial,
+                operand: Box::new(Expr::Var {
+                    name: String::from("n"),
+                    span: Span::new(0, 1, 1, 1)
+                }),
+                span: Span::new(0, 2, 1, 1),
+            }
+        );
+        assert!(matches!(
+            **rhs,
+            Expr::Postfix {
+                op: PostfixOp::Percent,
+                span,
+                ..
+            } if span == Span::new(5, 7, 1, 6)
+        ));
+        assert_eq!(expr.free_variables(), vec!["n"]);
+    }
+
+    #[test]
+    fn test_lower_implicit_multiplication() {
+        let options = ParseOptions::new().implicit_multiplication(true);
+        let expr = parse_expr_with("3x", &options).unwrap();
+        assert_eq!(
+            expr,
+            Expr::Binary {
+                op: BinOp::ImplicitMul,
+                lhs: Box::new(Expr::Num {
+                    value: NumLiteral::new("3"),
+                    span: Span::new(0, 1, 1, 1)
+                }),
+                rhs: Box::new(Expr::Var {
+                    name: String::from("x"),
+                    span: Span::new(1, 2, 1, 2)
+                }),
+                span: Span::new(0, 2, 1, 1),
+            }
+        );
+
+        // printed side by side, with a space or parentheses only where the
+        // factors would otherwise run together
+        let cases = [
+            ("2x", "2x"),
+            ("1/2x", "1 / 2x"),
+            ("(a+b)(a-b)", "(a + b)(a - b)"),
+            ("(1/2)x", "(1 / 2)x"),
+            ("2x*3y", "2x * 3y"),
+            ("(2x)^2", "(2x) ^ 2"),
+            ("2x^2", "2x ^ 2"),
+            ("-2x", "-2x"),
+            ("-(2x)", "-(2x)"),
+            ("x y z", "x y z"),
+            ("(x)(y)", "x y"),
+            ("(x)(a+b)", "(x)(a + b)"),
+            ("x 2", "x 2"),
+            ("2(3)", "2(3)"),
+            ("2(-x)", "2(-x)"),
+            ("2 sqrt(x)", "2sqrt(x)"),
+            ("f(x)(y)", "f(x)y"),
+            ("(50%)x", "(50%)x"),
+            ("3!x", "3!x"),
+            ("2|x|", "2(|x|)"),
+            ("|(a)(|x|)|", "|(a)(|x|)|"),
+            ("(2)(e)", "2 e"),
+            ("0x1f y", "0x1f y"),
+            ("1e3 x", "1e3 x"),
+        ];
+        for (s, printed) in cases {
+            let expr = parse_expr_with(s, &options).unwrap();
+            assert_eq!(expr.to_string(), printed, "{}", s);
+            let reparsed = parse_expr_with(printed, &options).unwrap();
+            assert_eq!(reparsed.to_string(), printed);
+        }
+        // without implicit multiplication the product prints the same way
+        assert_eq!(parse_expr("2*x").unwrap().to_string(), "2 * x");
+    }
+
+    #[test]
+    fn test_lower_abs() {
+        let expr = parse_expr("2 * |x - 1|").unwrap();
+        let Expr::Binary { rhs, .. } = &expr else {
+            panic!("Expected a binary expression");
+        };
+        let Expr::Abs { operand, span } = &**rhs else {
+            panic!("Expected an absolute value");
+        };
+        assert_eq!(*span, Span::new(4, 11, 1, 5));
+        assert_eq!(operand.span(), Span::new(5, 10, 1, 6));
+        assert_eq!(expr.free_variables(), vec!["x"]);
+    }
+
+    #[test]
+    fn test_lower_conditional() {
+        let expr = parse_expr("if x == 0 then 0 else 1 / x").unwrap();
+        let Expr::Conditional {
+            cond,
+            then_branch,
+            else_branch,
+            span,
+        } = &expr
+        else {
+            panic!("Expected a conditional");
+        };
+        assert!(matches!(
+            **cond,
+            Expr::Compare {
+                op: CompareOp::Eq,
+                ..
+            }
+        ));
+        assert_eq!(
+            **then_branch,
+            Expr::Num {
+                value: NumLiteral::new("0"),
+                span: Span::new(15, 16, 1, 16)
+            }
+        );
+        assert_eq!(else_branch.to_string(), "1 / x");
+        assert_eq!(*span, Span::new(0, 27, 1, 1));
+        assert_eq!(expr.free_variables(), vec!["x"]);
+        // both spellings lower to the same expression
+        assert_eq!(expr.to_string(), "x == 0 ? 0 : 1 / x");
+        assert_eq!(
+            parse_expr("x == 0 ? 0 : 1 / x").unwrap().to_string(),
+            expr.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            ("1+2*3", "1 + 2 * 3"),
+            ("(1+2)*3", "(1 + 2) * 3"),
+            ("8-(2-1)", "8 - (2 - 1)"),
+            ("(8-2)-1", "8 - 2 - 1"),
+            ("8/(4/2)", "8 / (4 / 2)"),
+            ("((((7))))", "7"),
+            ("2*(3/4)", "2 * (3 / 4)"),
+            ("1.50 + .5e3", "1.50 + .5e3"),
+            ("2*-4", "2 * -4"),
+            ("-(1+2)", "-(1 + 2)"),
+            ("--3", "--3"),
+            ("+3 - +4", "3 - 4"),
+            ("1 - -2", "1 - -2"),
+            ("-(2*3)", "-(2 * 3)"),
+            ("2^3^2", "2 ^ 3 ^ 2"),
+            ("(2^3)^2", "(2 ^ 3) ^ 2"),
+            ("-2^2", "-2 ^ 2"),
+            ("(-2)^2", "(-2) ^ 2"),
+            ("2**-1", "2 ^ -1"),
+            ("2^(1+1)", "2 ^ (1 + 1)"),
+            ("2*3^2", "2 * 3 ^ 2"),
+            ("7%(4//2)", "7 % (4 // 2)"),
+            ("7//2%3", "7 // 2 % 3"),
+            ("(price)*-(qty)", "price * -qty"),
+            ("max((1),-x,sqrt(2)^2)", "max(1, -x, sqrt(2) ^ 2)"),
+            ("f()", "f()"),
+            (r#"fx(1,"a\"b\\")"#, r#"fx(1, "a\"b\\")"#),
+            ("a<b+1", "a < b + 1"),
+            ("(a<b)==(c>=d)", "(a < b) == (c >= d)"),
+            ("a==b&&c!=d||e<=f", "a == b && c != d || e <= f"),
+            ("a&&(b||c)", "a && (b || c)"),
+            ("(a&&b)&&c", "a && b && c"),
+            ("a&&(b&&c)", "a && (b && c)"),
+            ("!(a<b)", "!(a < b)"),
+            ("!!true", "!!true"),
+            ("-x>!y", "-x > !y"),
+            ("(!a)^2", "(!a) ^ 2"),
+            ("3!", "3!"),
+            ("50%*200", "50% * 200"),
+            ("2^3!", "2 ^ 3!"),
+            ("(2^3)!", "(2 ^ 3)!"),
+            ("-3!", "-3!"),
+            ("(-3)!", "(-3)!"),
+            ("x!%", "x!%"),
+            ("(50%)-x", "50% - x"),
+            ("7%-x", "7 % -x"),
+            ("(!a)!", "(!a)!"),
+            ("f(x)!", "f(x)!"),
+            ("a?b:c", "a ? b : c"),
+            ("a?b:c?d:e", "a ? b : c ? d : e"),
+            ("(a?b:c)?d:e", "(a ? b : c) ? d : e"),
+            ("a?(b?c:d):e", "a ? b ? c : d : e"),
+            ("a||b?c+1:-d", "a || b ? c + 1 : -d"),
+            ("(a?1:2)*3", "(a ? 1 : 2) * 3"),
+            ("-(if a then 1 else 2)", "-(a ? 1 : 2)"),
+            ("f(if a then 1 else 2)", "f(a ? 1 : 2)"),
+            ("[a+b]*{c}", "(a + b) * c"),
+            ("|x-3|", "|x - 3|"),
+            ("-|x|^2", "-|x| ^ 2"),
+            ("||x|-|y||", "||x| - |y||"),
+            ("|(x)|!", "|x|!"),
+            ("|a?1:-1|", "|a ? 1 : -1|"),
+            ("|7%|x||", "|7 % |x||"),
+            ("|7%|-2||", "|7 % |-2||"),
+            ("|x%| - |y|", "|x%| - |y|"),
+        ];
+        for (s, printed) in cases {
+            assert_eq!(parse_expr(s).unwrap().to_string(), printed);
+            // what is printed parses back to the same expression
+            assert_eq!(parse_expr(printed).unwrap().to_string(), printed);
+        }
+    }
+}
@@ -3,20 +3,80 @@ pub mod mathparser;
 
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+use crate::lex::{number::NumLiteral, simple::LexToken, Span};
+
+/// Non-terminals and terminals of the grammar, one per parse node
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum CfgTerm {
     NonTermStartRule,
+    NonTermAssignment,
+    /// `name(params) = body`, defining a function
+    NonTermDefinition,
+    /// `cond ? a : b` or `if cond then a else b`
+    NonTermConditionalExpr,
+    NonTermOrExpr,
+    NonTermAndExpr,
+    /// two expressions compared with '==', '<' and the like
+    NonTermComparisonExpr,
     NonTermExpr,
     NonTermMultiDivExpr,
-    NonTermDivExpr,
+    NonTermUnaryExpr,
+    NonTermPowerExpr,
+    /// an operand followed by a postfix '!' or '%'
+    NonTermPostfixExpr,
     NonTermTermExpr,
-    TermNumber(u32),
+    /// an expression between '|' bars, its absolute value
+    NonTermAbsExpr,
+    NonTermCallExpr,
+    TermNumber(NumLiteral),
+    TermVariable(String),
+    /// a string literal, without its quotes
+    TermString(String),
+    /// `true` or `false`
+    TermBool(bool),
+    /// the name of the function in a call
+    TermFunction(String),
+    TermAssign,
     TermDivide,
     TermMultiply,
+    /// the multiplication of two factors written side by side, as in `2x`.
+    /// It covers no input, its span is empty and sits where the second
+    /// factor starts.
+    TermImplicitMultiply,
+    TermModulo,
+    TermIntDivide,
     TermPlus,
     TermMinus,
+    TermPower,
+    TermUnaryMinus,
+    TermUnaryPlus,
+    TermEqual,
+    TermNotEqual,
+    TermLess,
+    TermLessEqual,
+    TermGreater,
+    TermGreaterEqual,
+    TermAnd,
+    TermOr,
+    /// a prefix '!'
+    TermNot,
+    /// a postfix '!'
+    TermFactorial,
+    /// a postfix '%'
+    TermPercent,
+    TermQuestion,
+    TermColon,
+    TermIf,
+    TermThen,
+    TermElse,
+    /// a '(', '[' or '{', the three group the same way
     TermLeftParens,
+    /// a ')', ']' or '}'
     TermRightParens,
+    /// a '|' around an absolute value
+    TermBar,
+    TermComma,
 }
 
 impl fmt::Display for CfgTerm {
@@ -25,32 +85,143 @@ impl fmt::Display for CfgTerm {
             Self::NonTermStartRule => {
                 write!(f, "NonTermStartRule::")
             }
+            Self::NonTermAssignment => {
+                write!(f, "NonTermAssignment::")
+            }
+            Self::NonTermDefinition => {
+                write!(f, "NonTermDefinition::")
+            }
+            Self::NonTermConditionalExpr => {
+                write!(f, "NonTermConditional::")
+            }
+            Self::NonTermOrExpr => {
+                write!(f, "NonTermOr::")
+            }
+            Self::NonTermAndExpr => {
+                write!(f, "NonTermAnd::")
+            }
+            Self::NonTermComparisonExpr => {
+                write!(f, "NonTermComparison::")
+            }
             Self::NonTermExpr => {
                 write!(f, "NonTermExpr::")
             }
             Self::NonTermMultiDivExpr => {
                 write!(f, "NonTermMultiDiv::")
             }
-            Self::NonTermDivExpr => {
-                write!(f, "NonTermDiv::")
+            Self::NonTermUnaryExpr => {
+                write!(f, "NonTermUnary::")
+            }
+            Self::NonTermPowerExpr => {
+                write!(f, "NonTermPower::")
+            }
+            Self::NonTermPostfixExpr => {
+                write!(f, "NonTermPostfix::")
             }
             Self::NonTermTermExpr => {
                 write!(f, "NonTermTerm::")
             }
+            Self::NonTermAbsExpr => {
+                write!(f, "NonTermAbs::")
+            }
+            Self::NonTermCallExpr => {
+                write!(f, "NonTermCall::")
+            }
+            Self::TermAssign => {
+                write!(f, "Term('=')")
+            }
             Self::TermDivide => {
                 write!(f, "Term('/')")
             }
             Self::TermMultiply => {
                 write!(f, "Term('*')")
             }
+            Self::TermImplicitMultiply => {
+                write!(f, "Term(implicit '*')")
+            }
+            Self::TermModulo => {
+                write!(f, "Term('%')")
+            }
+            Self::TermIntDivide => {
+                write!(f, "Term('//')")
+            }
             Self::TermPlus => {
                 write!(f, "Term('+')")
             }
             Self::TermMinus => {
                 write!(f, "Term('-')")
             }
+            Self::TermPower => {
+                write!(f, "Term('^')")
+            }
+            Self::TermUnaryMinus => {
+                write!(f, "Term(unary '-')")
+            }
+            Self::TermUnaryPlus => {
+                write!(f, "Term(unary '+')")
+            }
+            Self::TermEqual => {
+                write!(f, "Term('==')")
+            }
+            Self::TermNotEqual => {
+                write!(f, "Term('!=')")
+            }
+            Self::TermLess => {
+                write!(f, "Term('<')")
+            }
+            Self::TermLessEqual => {
+                write!(f, "Term('<=')")
+            }
+            Self::TermGreater => {
+                write!(f, "Term('>')")
+            }
+            Self::TermGreaterEqual => {
+                write!(f, "Term('>=')")
+            }
+            Self::TermAnd => {
+                write!(f, "Term('&&')")
+            }
+            Self::TermOr => {
+                write!(f, "Term('||')")
+            }
+            Self::TermNot => {
+                write!(f, "Term('!')")
+            }
+            Self::TermFactorial => {
+                write!(f, "Term(postfix '!')")
+            }
+            Self::TermPercent => {
+                write!(f, "Term(postfix '%')")
+            }
+            Self::TermQuestion => {
+                write!(f, "Term('?')")
+            }
+            Self::TermColon => {
+                write!(f, "Term(':')")
+            }
+            Self::TermIf => {
+                write!(f, "Term(if)")
+            }
+            Self::TermThen => {
+                write!(f, "Term(then)")
+            }
+            Self::TermElse => {
+                write!(f, "Term(else)")
+            }
             Self::TermNumber(n) => {
-                write!(f, "Term({})", *n)
+                write!(f, "Term({})", n)
+            }
+            Self::TermVariable(name) => {
+                write!(f, "Term({})", name)
+            }
+            Self::TermString(s) => {
+                write!(f, "Term({})", crate::lex::quote(s))
+            }
+            Self::TermBool(b) => {
+                write!(f, "Term({})", b)
+            }
+            Self::TermFunction(name) => {
+                write!(f, "Term({}())", name)
             }
             Self::TermLeftParens => {
                 write!(f, "Term('(')")
@@ -58,62 +229,392 @@ impl fmt::Display for CfgTerm {
             Self::TermRightParens => {
                 write!(f, "Term(')')")
             }
+            Self::TermBar => {
+                write!(f, "Term('|')")
+            }
+            Self::TermComma => {
+                write!(f, "Term(',')")
+            }
         }
     }
 }
 
-#[derive(PartialEq)]
+/// Default for [`ParseOptions::max_nesting_depth`]
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 64;
+
+/// Settings for [`parse_with`](crate::parse_with)
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// whether factors written side by side, as in `2x` or `(a+b)(a-b)`,
+    /// are multiplied. Off by default, as it makes `x(y)` a call rather
+    /// than a product and turns some typos into valid input.
+    pub implicit_multiplication: bool,
+    /// how deeply brackets, calls, prefix operators, exponents and
+    /// conditionals may nest. Parsing recurses into each of these, so this
+    /// keeps pathological input such as thousands of nested parentheses
+    /// from overflowing the stack, and a large value needs a large stack. A
+    /// chain of left associative operators such as `1 + 2 + 3` does not
+    /// nest, and may be as long as it likes.
+    pub max_nesting_depth: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            implicit_multiplication: false,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets how deeply brackets, calls, prefix operators, exponents and
+    /// conditionals may nest
+    pub fn max_nesting_depth(mut self, depth: usize) -> Self {
+        self.max_nesting_depth = depth;
+        self
+    }
+
+    /// sets whether factors written side by side are multiplied
+    pub fn implicit_multiplication(mut self, enabled: bool) -> Self {
+        self.implicit_multiplication = enabled;
+        self
+    }
+}
+
+/// A node of the parse tree, holding the grammar symbol it was parsed as,
+/// its child nodes and the part of the input it covers
+#[derive(Debug, PartialEq)]
 pub struct ParseNode {
     current_node: CfgTerm,
     child_nodes: Vec<ParseNode>,
     node_depth: usize,
+    span: Span,
 }
 
 impl ParseNode {
-    pub fn new(current_node: CfgTerm, node_depth: usize) -> ParseNode {
+    /// creates a non-terminal node, its span grows as child nodes are added
+    pub(crate) fn new(current_node: CfgTerm, node_depth: usize) -> ParseNode {
+        ParseNode::with_span(current_node, node_depth, Span::default())
+    }
+
+    /// creates a terminal node covering `span` of the input
+    pub(crate) fn with_span(current_node: CfgTerm, node_depth: usize, span: Span) -> ParseNode {
         ParseNode {
             current_node,
             child_nodes: Vec::new(),
             node_depth,
+            span,
         }
     }
 
-    pub fn add_child_node(&mut self, child_node: ParseNode) {
+    pub(crate) fn add_child_node(&mut self, child_node: ParseNode) {
+        self.span = match self.child_nodes.first() {
+            Some(_) => self.span.to(child_node.span),
+            None => child_node.span,
+        };
         self.child_nodes.push(child_node);
     }
+
+    /// the grammar symbol this node was parsed as
+    pub fn kind(&self) -> &CfgTerm {
+        &self.current_node
+    }
+
+    /// child nodes, in the order they appear in the input
+    pub fn children(&self) -> &[ParseNode] {
+        &self.child_nodes
+    }
+
+    /// the part of the input covered by this node and its children
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// distance from the root of the parse tree
+    pub fn depth(&self) -> usize {
+        self.node_depth
+    }
+
+    /// names of the variables the tree uses before assigning them, each
+    /// listed once in the order they first appear in the input
+    pub fn free_variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_variables(&mut names, &mut Vec::new());
+        names
+    }
+
+    /// walks the tree with a stack of the work left rather than by
+    /// recursion, as a long chain such as `1 + 2 + ... + 1` makes the tree
+    /// as deep as the chain is long
+    fn collect_variables<'a>(&'a self, names: &mut Vec<&'a str>, assigned: &mut Vec<&'a str>) {
+        enum Work<'a> {
+            Visit(&'a ParseNode),
+            Bind(&'a str),
+            Unbind(usize),
+        }
+        let mut pending = vec![Work::Visit(self)];
+        while let Some(work) = pending.pop() {
+            let node = match work {
+                Work::Visit(node) => node,
+                Work::Bind(name) => {
+                    assigned.push(name);
+                    continue;
+                }
+                Work::Unbind(outer_len) => {
+                    assigned.truncate(outer_len);
+                    continue;
+                }
+            };
+            match (&node.current_node, node.child_nodes.as_slice()) {
+                (CfgTerm::TermVariable(name), _) => {
+                    if !assigned.contains(&name.as_str()) && !names.contains(&name.as_str()) {
+                        names.push(name);
+                    }
+                }
+                // the value is evaluated before the variable is bound
+                (CfgTerm::NonTermAssignment, [target, _, value]) => {
+                    if let CfgTerm::TermVariable(name) = &target.current_node {
+                        pending.push(Work::Bind(name));
+                    }
+                    pending.push(Work::Visit(value));
+                }
+                // the parameters are bound only inside the body
+                (CfgTerm::NonTermDefinition, [head @ .., body]) => {
+                    pending.push(Work::Unbind(assigned.len()));
+                    pending.push(Work::Visit(body));
+                    for param in head.iter().rev() {
+                        if let CfgTerm::TermVariable(name) = &param.current_node {
+                            pending.push(Work::Bind(name));
+                        }
+                    }
+                }
+                _ => pending.extend(node.child_nodes.iter().rev().map(Work::Visit)),
+            }
+        }
+    }
+
+    /// sets the depth of every node below this one from the depth of this
+    /// one. A chain such as `1 + 2 + 3` is built from the left, so the
+    /// nodes built first only get their depth once the chain has ended.
+    pub(crate) fn set_child_depths(&mut self) {
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            let child_depth = node.node_depth + 1;
+            for child_node in node.child_nodes.iter_mut() {
+                child_node.node_depth = child_depth;
+                pending.push(child_node);
+            }
+        }
+    }
+}
+
+impl Drop for ParseNode {
+    /// drops the descendants one at a time, a long chain would otherwise
+    /// be dropped by a recursion as deep as the chain is long
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.child_nodes);
+        while let Some(mut node) = pending.pop() {
+            pending.append(&mut node.child_nodes);
+        }
+    }
 }
 
 impl fmt::Display for ParseNode {
+    /// prints a line for each node, indented by its depth
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut space: String = " ".to_owned();
-        let mut s: String = " ".to_owned();
-        for (_, n) in self.child_nodes.iter().enumerate() {
-            s.push_str(&format!("\n{}", n.to_string()));
-        }
-        for _ in 0..self.node_depth {
-            space.push_str(" ");
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            if !std::ptr::eq(node, self) {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:width$}+[{}](child count={}) ",
+                "",
+                node.current_node,
+                node.child_nodes.len(),
+                width = node.node_depth + 1
+            )?;
+            pending.extend(node.child_nodes.iter().rev());
         }
-        write!(
-            f,
-            "{space}+[{}](child count={}){s}",
-            self.current_node,
-            self.child_nodes.len()
-        )
+        Ok(())
     }
 }
 
-#[derive(Debug)]
-pub(crate) enum ParseError {
-    InvalidTokenError(String),
+/// Errors found while parsing the lex tokens
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ParseError {
+    UnexpectedToken {
+        found: LexToken,
+        expected: Vec<&'static str>,
+        after: Option<LexToken>,
+        span: Span,
+    },
+    UnexpectedEndOfInput {
+        expected: Vec<&'static str>,
+        after: Option<LexToken>,
+        span: Span,
+    },
+    /// an opening bracket that is never closed, or a closing one that
+    /// closes nothing
+    UnbalancedParenthesis {
+        paren: char,
+        span: Span,
+    },
+    /// a bracket closed by a different kind of bracket, such as `(1+2]`.
+    /// `span` covers the closing bracket and `open_span` the opening one
+    MismatchedBracket {
+        open: char,
+        close: char,
+        open_span: Span,
+        span: Span,
+    },
+    TrailingInput {
+        found: LexToken,
+        span: Span,
+    },
+    NestingTooDeep {
+        max_depth: usize,
+        span: Span,
+    },
+    /// a function definition naming the same parameter twice, `span`
+    /// covers the second one
+    DuplicateParameter {
+        name: String,
+        span: Span,
+    },
+    /// a comparison of a comparison, such as `a < b < c`. `span` covers
+    /// the second operator
+    ChainedComparison {
+        span: Span,
+    },
+}
+
+impl ParseError {
+    /// the part of the input where the error was found
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEndOfInput { span, .. }
+            | ParseError::UnbalancedParenthesis { span, .. }
+            | ParseError::MismatchedBracket { span, .. }
+            | ParseError::TrailingInput { span, .. }
+            | ParseError::NestingTooDeep { span, .. }
+            | ParseError::DuplicateParameter { span, .. }
+            | ParseError::ChainedComparison { span } => *span,
+        }
+    }
+
+    /// describes what the parser was looking for, e.g.
+    /// "expected operator, ',' or ')' after '1'"
+    pub fn expected_message(&self) -> Option<String> {
+        let (expected, after) = match self {
+            ParseError::UnexpectedToken {
+                expected, after, ..
+            }
+            | ParseError::UnexpectedEndOfInput {
+                expected, after, ..
+            } => (expected, after),
+            _ => return None,
+        };
+        let mut message = String::from("expected ");
+        if let [rest @ .., last] = expected.as_slice() {
+            if !rest.is_empty() {
+                message.push_str(&format!("{} or ", rest.join(", ")));
+            }
+            message.push_str(last);
+        }
+        if let Some(tok) = after {
+            message.push_str(&format!(" after {}", describe_token(tok)));
+        }
+        Some(message)
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::InvalidTokenError(s) => write!(f, "Invalid token found: {}", s),
+            ParseError::UnexpectedToken { found, .. } => write!(
+                f,
+                "Unexpected token {}, {}",
+                describe_token(found),
+                self.expected_message().unwrap_or_default()
+            ),
+            ParseError::UnexpectedEndOfInput { .. } => write!(
+                f,
+                "Unexpected end of input, {}",
+                self.expected_message().unwrap_or_default()
+            ),
+            ParseError::UnbalancedParenthesis { paren, .. } if is_opening_bracket(*paren) => {
+                write!(f, "Unbalanced parenthesis: '{}' is never closed", paren)
+            }
+            ParseError::UnbalancedParenthesis { paren, .. } => write!(
+                f,
+                "Unbalanced parenthesis: '{}' has no matching '{}'",
+                paren,
+                matching_bracket(*paren)
+            ),
+            ParseError::MismatchedBracket { open, close, .. } => write!(
+                f,
+                "Mismatched brackets: '{}' is closed by '{}' instead of '{}'",
+                open,
+                close,
+                matching_bracket(*open)
+            ),
+            ParseError::TrailingInput { found, .. } => write!(
+                f,
+                "Unexpected trailing input starting at {}",
+                describe_token(found)
+            ),
+            ParseError::NestingTooDeep { max_depth, .. } => write!(
+                f,
+                "Expression is nested too deeply, brackets, calls, prefix operators, exponents and conditionals may nest at most {} levels deep",
+                max_depth
+            ),
+            ParseError::DuplicateParameter { name, .. } => {
+                write!(f, "Duplicate parameter: {}", name)
+            }
+            ParseError::ChainedComparison { .. } => {
+                write!(f, "Comparison operators cannot be chained")
+            }
         }
     }
 }
 
+impl std::error::Error for ParseError {}
+
+/// `tok` as messages name it, in quotes unless it is a line break
+pub(crate) fn describe_token(tok: &LexToken) -> String {
+    match tok {
+        LexToken::Newline => tok.to_string(),
+        tok => format!("'{}'", tok.to_string().trim()),
+    }
+}
+
+/// whether `c` opens a group, a '|' is taken to open one
+pub(crate) fn is_opening_bracket(c: char) -> bool {
+    matches!(c, '(' | '[' | '{' | '|')
+}
+
+/// the bracket pairing with `c`, in either direction
+pub(crate) fn matching_bracket(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        c => c,
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -1,264 +1,1156 @@
-use crate::{cfg::CfgTerm, cfg::ParseError, lex::simple::LexToken};
-
-use std::error::Error;
+use crate::{
+    cfg::matching_bracket,
+    cfg::CfgTerm,
+    cfg::ParseError,
+    cfg::ParseOptions,
+    lex::Token,
+    lex::{simple::LexToken, Span},
+};
 
 use super::ParseNode;
 
+/// What an operand may start with, listed in the error for a missing one
+const OPERAND_STARTS: [&str; 12] = [
+    "number",
+    "variable",
+    "function call",
+    "string",
+    "boolean",
+    "'('",
+    "'['",
+    "'{'",
+    "'|'",
+    "'-'",
+    "'+'",
+    "'!'",
+];
+
+/// the second '|' of a '||' that is read as two
+static SPLIT_BAR: LexToken = LexToken::Bar('|');
+
+/// A method parsing one rule of the grammar from a position and node depth,
+/// returning the node and the position of its last token
+type ParseRule<'a> =
+    fn(&mut MathParser<'a>, usize, usize) -> Result<(ParseNode, usize), ParseError>;
+
 #[derive(Default)]
 pub(crate) struct MathParser<'a> {
-    lex_tokens: &'a [LexToken],
-    pub(crate) parsed_node: Option<ParseNode>,
+    /// the input the tokens were lexed from
+    source: &'a str,
+    lex_tokens: &'a [Token],
+    options: ParseOptions,
+    /// how deeply the rule being parsed is nested
+    nesting: usize,
+    /// how many absolute values are open inside the innermost bracket, a
+    /// '|' after an operand closes one when there are any
+    open_bars: usize,
+    /// the position of a '||' read as two '|', the first of which closed an
+    /// absolute value. The token there is the second '|' from then on.
+    split_or: Option<usize>,
 }
 
 impl<'a> MathParser<'a> {
-    pub(crate) fn new(lex_tokens: &'a [LexToken]) -> Self {
+    pub(crate) fn new(source: &'a str, lex_tokens: &'a [Token], options: ParseOptions) -> Self {
         MathParser {
+            source,
             lex_tokens,
-            ..Default::default()
+            options,
+            nesting: 0,
+            open_bars: 0,
+            split_or: None,
+        }
+    }
+
+    /// the token at `pos`, the second '|' for a '||' read as two
+    fn token(&self, pos: usize) -> Option<&'a LexToken> {
+        if self.split_or == Some(pos) {
+            return Some(&SPLIT_BAR);
         }
+        self.lex_tokens.get(pos).map(|t| &t.kind)
     }
 
     /// check if we have reached EOF by inspecting the current position with the
     /// number of tokens
-    fn peek(&self, pos: usize) -> Option<&LexToken> {
+    fn peek(&self, pos: usize) -> Option<&'a LexToken> {
         if (pos + 1) >= self.lex_tokens.len() {
             return None;
         }
-        let tok = self.lex_tokens.get(pos + 1);
-        println!("=> [peek] tok: {:?} pos: [{}]", tok, pos + 1);
-        return tok;
+        let tok = self.token(pos + 1);
+        trace!("=> [peek] tok: {:?} pos: [{}]", tok, pos + 1);
+        tok
     }
 
-    /// parsing term (either number or sub expr)
-    fn parse_term(
+    /// span of the token at `pos`, or an empty span at the end of the input
+    /// if there is no such token
+    fn span_at(&self, pos: usize) -> Span {
+        match self.lex_tokens.get(pos) {
+            // the second '|' of a '||' read as two
+            Some(tok) if self.split_or == Some(pos) => Span::new(
+                tok.span.start + 1,
+                tok.span.end,
+                tok.span.line,
+                tok.span.column + 1,
+            ),
+            Some(tok) => tok.span,
+            None => self.eof_span(),
+        }
+    }
+
+    /// what may start the operand missing at `pos`. An 'if' may as well
+    /// where a whole conditional_expr starts, but not after an operator.
+    fn expected_operand(&self, pos: usize) -> Vec<&'static str> {
+        let mut expected = OPERAND_STARTS.to_vec();
+        if matches!(
+            self.token_before(pos),
+            None | Some(
+                LexToken::Semicolon(_)
+                    | LexToken::Newline
+                    | LexToken::Assign(_)
+                    | LexToken::LeftParen(_)
+                    | LexToken::Bar(_)
+                    | LexToken::Comma(_)
+                    | LexToken::Question(_)
+                    | LexToken::Colon(_)
+                    | LexToken::If
+                    | LexToken::Then
+                    | LexToken::Else
+            )
+        ) {
+            expected.push("'if'");
+        }
+        expected
+    }
+
+    /// the token just before `pos`, used to describe where an error happened
+    fn token_before(&self, pos: usize) -> Option<LexToken> {
+        pos.checked_sub(1).and_then(|p| self.token(p)).cloned()
+    }
+
+    /// empty span just past the last token. A token ends on the line it
+    /// starts on, and string literals may hold any character, so its width
+    /// in columns is the number of chars in its source text.
+    fn eof_span(&self) -> Span {
+        match self.lex_tokens.last() {
+            Some(tok) => {
+                let width = self.source[tok.span.start..tok.span.end].chars().count();
+                Span::new(
+                    tok.span.end,
+                    tok.span.end,
+                    tok.span.line,
+                    tok.span.column + width,
+                )
+            }
+            None => Span::new(0, 0, 1, 1),
+        }
+    }
+
+    /// parsing `rule` from `pos` one level of nesting deeper, failing when
+    /// that is deeper than the options allow
+    fn parse_nested(
         &mut self,
+        rule: ParseRule<'a>,
         pos: usize,
         node_depth: usize,
     ) -> Result<(ParseNode, usize), ParseError> {
-        println!("=> [{node_depth}]parsing term node at position {pos} ...");
-        let tok = self.lex_tokens.get(pos);
-        match tok {
-            Some(LexToken::LeftParen('(')) => {
-                let mut term_node = ParseNode::new(CfgTerm::NonTermTermExpr, node_depth);
+        if self.nesting >= self.options.max_nesting_depth {
+            return Err(ParseError::NestingTooDeep {
+                max_depth: self.options.max_nesting_depth,
+                span: self.span_at(pos),
+            });
+        }
+        self.nesting += 1;
+        let parsed = rule(self, pos, node_depth);
+        self.nesting -= 1;
+        parsed
+    }
 
-                println!("(");
-                let left_parens_node = ParseNode::new(CfgTerm::TermLeftParens, node_depth + 1);
-                term_node.child_nodes.push(left_parens_node);
-                let (expr_node, expr_pos) = self.parse_expr(pos + 1, node_depth + 1)?;
-                term_node.child_nodes.push(expr_node);
-                println!("term_node: {}, expr_pos: {}", term_node, expr_pos);
+    /// parsing the conditional_expr from `pos` inside a bracket or the
+    /// arguments of a call, which a '|' does not close an absolute value
+    /// opened outside of
+    fn parse_bracketed(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        let open_bars = std::mem::take(&mut self.open_bars);
+        let parsed = self.parse_nested(Self::parse_conditional_expr, pos, node_depth);
+        self.open_bars = open_bars;
+        parsed
+    }
 
-                // close parens
-                // let close_parens_tok = tokens.get(expr_pos + 1).expect("Expected close parens");
-                let close_parens_tok = self.peek(expr_pos);
-                println!("close_parens_tok: {:?}", close_parens_tok);
-                assert_eq!(*(close_parens_tok.unwrap()), LexToken::RightParen(')'));
+    /// parsing a function call, `pos` is the position of the function name
+    /// and the '(' follows it. The arguments are expressions separated by
+    /// ',', and there may be none.
+    fn parse_call(
+        &mut self,
+        name: &str,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][call] parsing call to {name} at position {pos}");
+        let mut call_node = ParseNode::new(CfgTerm::NonTermCallExpr, node_depth);
+        call_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermFunction(name.to_string()),
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        call_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermLeftParens,
+            node_depth + 1,
+            self.span_at(pos + 1),
+        ));
 
-                let right_parens_node = ParseNode::new(CfgTerm::TermRightParens, node_depth + 1);
-                term_node.child_nodes.push(right_parens_node);
-                println!("term_node: {}, expr_pos+2: {}", term_node, expr_pos + 1);
+        // call: VARIABLE '(' ')'
+        if let Some(LexToken::RightParen(')')) = self.peek(pos + 1) {
+            call_node.add_child_node(ParseNode::with_span(
+                CfgTerm::TermRightParens,
+                node_depth + 1,
+                self.span_at(pos + 2),
+            ));
+            return Ok((call_node, pos + 2));
+        }
 
-                return Ok((term_node, expr_pos + 1));
+        // call: VARIABLE '(' expr (',' expr)* ')'
+        let mut arg_pos = pos + 2;
+        loop {
+            let (arg_node, end_pos) = self.parse_bracketed(arg_pos, node_depth + 1)?;
+            call_node.add_child_node(arg_node);
+            match self.peek(end_pos) {
+                Some(LexToken::Comma(_)) => {
+                    call_node.add_child_node(ParseNode::with_span(
+                        CfgTerm::TermComma,
+                        node_depth + 1,
+                        self.span_at(end_pos + 1),
+                    ));
+                    arg_pos = end_pos + 2;
+                }
+                Some(LexToken::RightParen(')')) => {
+                    call_node.add_child_node(ParseNode::with_span(
+                        CfgTerm::TermRightParens,
+                        node_depth + 1,
+                        self.span_at(end_pos + 1),
+                    ));
+                    trace!("call_node: {call_node}");
+                    return Ok((call_node, end_pos + 1));
+                }
+                Some(LexToken::RightParen(c)) => {
+                    return Err(ParseError::MismatchedBracket {
+                        open: '(',
+                        close: *c,
+                        open_span: self.span_at(pos + 1),
+                        span: self.span_at(end_pos + 1),
+                    });
+                }
+                Some(tok) => {
+                    return Err(ParseError::UnexpectedToken {
+                        found: tok.clone(),
+                        expected: vec!["operator", "','", "')'"],
+                        after: self.token_before(end_pos + 1),
+                        span: self.span_at(end_pos + 1),
+                    });
+                }
+                None => {
+                    return Err(ParseError::UnbalancedParenthesis {
+                        paren: '(',
+                        span: self.span_at(pos + 1),
+                    });
+                }
             }
-            Some(LexToken::Num(n)) => {
-                println!("term num: {}", *n);
-                let pt_node = ParseNode::new(CfgTerm::TermNumber(*n), node_depth);
-                println!("term num node: {pt_node}");
-                return Ok((pt_node, pos));
+        }
+    }
+
+    /// checks that the token after `expr_pos` closes the `open` bracket at
+    /// `open_pos` with the matching kind of bracket
+    fn expect_closing_bracket(
+        &self,
+        open: char,
+        open_pos: usize,
+        expr_pos: usize,
+    ) -> Result<(), ParseError> {
+        let close = matching_bracket(open);
+        let close_parens_tok = self.peek(expr_pos);
+        trace!("close_parens_tok: {:?}", close_parens_tok);
+        match close_parens_tok {
+            Some(LexToken::RightParen(c) | LexToken::Bar(c)) if *c == close => Ok(()),
+            Some(LexToken::RightParen(c)) => Err(ParseError::MismatchedBracket {
+                open,
+                close: *c,
+                open_span: self.span_at(open_pos),
+                span: self.span_at(expr_pos + 1),
+            }),
+            Some(tok) => Err(ParseError::UnexpectedToken {
+                found: tok.clone(),
+                expected: vec!["operator", quoted_bracket(close)],
+                after: self.token_before(expr_pos + 1),
+                span: self.span_at(expr_pos + 1),
+            }),
+            None => Err(ParseError::UnbalancedParenthesis {
+                paren: open,
+                span: self.span_at(open_pos),
+            }),
+        }
+    }
+
+    /// the position of the last line break right after `pos` inside an
+    /// absolute value, or `pos` when there is none. The lexer leaves bars to
+    /// the parser, and inside them a line break is whitespace.
+    fn skip_line_breaks(&self, mut pos: usize) -> usize {
+        while self.open_bars > 0 && matches!(self.peek(pos), Some(LexToken::Newline)) {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// parsing an absolute value, `pos` is the position of its opening '|'.
+    /// The first '|' after an operand that is not inside a bracket opened
+    /// after it closes it, and a '||' there closes it with its first '|'.
+    fn parse_abs(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        // term: '|' conditional_expr '|'
+        let mut abs_node = ParseNode::new(CfgTerm::NonTermAbsExpr, node_depth);
+        abs_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermBar,
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        self.open_bars += 1;
+        let expr_start = self.skip_line_breaks(pos) + 1;
+        let parsed = self.parse_nested(Self::parse_conditional_expr, expr_start, node_depth + 1);
+        self.open_bars -= 1;
+        let (expr_node, expr_pos) = parsed?;
+        abs_node.add_child_node(expr_node);
+
+        let (close_span, close_pos) = match self.peek(expr_pos) {
+            Some(LexToken::Or(_)) => {
+                let span = self.span_at(expr_pos + 1);
+                self.split_or = Some(expr_pos + 1);
+                (
+                    Span::new(span.start, span.start + 1, span.line, span.column),
+                    expr_pos,
+                )
             }
             _ => {
-                return Err(ParseError::InvalidTokenError(format!(
-                    "Error: invalid term token: {}",
-                    tok.unwrap()
-                )));
+                self.expect_closing_bracket('|', pos, expr_pos)?;
+                (self.span_at(expr_pos + 1), expr_pos + 1)
             }
-        }
+        };
+        abs_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermBar,
+            node_depth + 1,
+            close_span,
+        ));
+        trace!("abs_node: {abs_node}");
+        Ok((abs_node, close_pos))
     }
 
-    // parsing the divide expression
-    fn parse_div_expr(
+    /// parsing term (a number, a variable, a function call or a sub expr)
+    fn parse_term(
         &mut self,
         pos: usize,
         node_depth: usize,
     ) -> Result<(ParseNode, usize), ParseError> {
-        println!("=> [{node_depth}][div_expr] parsing div_expr node at position {pos}");
-        // call term
-        let (term_node, new_pos) = self.parse_term(pos, node_depth + 1)?;
-        let mut dive_node = ParseNode::new(CfgTerm::NonTermDivExpr, node_depth);
-        dive_node.child_nodes.push(term_node);
-        println!("dive_node, after adding term: {dive_node}");
-
-        // term / div_expr - look for multiply '/' symbol -
-        // term - look for '+'/- symbol, this is essentially follow-on sets
-        let tok = self.peek(new_pos);
+        trace!("=> [{node_depth}]parsing term node at position {pos} ...");
+        let tok = self.token(pos);
         match tok {
-            Some(LexToken::Div(_)) => {
-                println!("=> [div_expr] found / ");
-                dive_node.add_child_node(ParseNode::new(CfgTerm::TermDivide, node_depth + 1));
+            Some(LexToken::LeftParen(open)) => {
+                let mut term_node = ParseNode::new(CfgTerm::NonTermTermExpr, node_depth);
+
+                trace!("{open}");
+                let left_parens_node = ParseNode::with_span(
+                    CfgTerm::TermLeftParens,
+                    node_depth + 1,
+                    self.span_at(pos),
+                );
+                term_node.add_child_node(left_parens_node);
+                let (expr_node, expr_pos) = self.parse_bracketed(pos + 1, node_depth + 1)?;
+                term_node.add_child_node(expr_node);
+                trace!("term_node: {}, expr_pos: {}", term_node, expr_pos);
+
+                self.expect_closing_bracket(*open, pos, expr_pos)?;
+                let right_parens_node = ParseNode::with_span(
+                    CfgTerm::TermRightParens,
+                    node_depth + 1,
+                    self.span_at(expr_pos + 1),
+                );
+                term_node.add_child_node(right_parens_node);
+                trace!("term_node: {}, expr_pos+2: {}", term_node, expr_pos + 1);
+
+                Ok((term_node, expr_pos + 1))
             }
-            Some(LexToken::Add(_)) | Some(LexToken::Subtract(_)) => {
-                // PEEK but don't consume
-                // return as this is end of div_expr, and is to be parsed by
-                // tail end of (expr: multi_div_expr <> _ expr)
-                println!("=> [div_expr] found + or - , next_pos: {}", new_pos + 1);
-                return Ok((dive_node, new_pos));
+            Some(LexToken::Bar(_)) => self.parse_abs(pos, node_depth),
+            Some(LexToken::Num(n)) => {
+                trace!("term num: {}", n);
+                let pt_node = ParseNode::with_span(
+                    CfgTerm::TermNumber(n.clone()),
+                    node_depth,
+                    self.span_at(pos),
+                );
+                trace!("term num node: {pt_node}");
+                Ok((pt_node, pos))
             }
-            Some(LexToken::Multi(_)) => {
-                // PEEK but don't consume
-                // return as this is end of div_expr, and is to be parsed by
-                // tail end of (multi_div_expr: div_expr <> * multi_div_expr)
-                println!("=> [div_expr] found * , next_pos: {}", new_pos + 1);
-                return Ok((dive_node, new_pos));
+            Some(LexToken::Bool(b)) => {
+                trace!("term bool: {}", b);
+                let pt_node =
+                    ParseNode::with_span(CfgTerm::TermBool(*b), node_depth, self.span_at(pos));
+                Ok((pt_node, pos))
             }
-            Some(LexToken::RightParen(_)) => {
-                println!("=> [div_expr] right parens");
-                return Ok((dive_node, new_pos));
+            Some(LexToken::Str(s)) => {
+                trace!("term string: {}", s);
+                let pt_node = ParseNode::with_span(
+                    CfgTerm::TermString(s.clone()),
+                    node_depth,
+                    self.span_at(pos),
+                );
+                Ok((pt_node, pos))
             }
-            None => {
-                println!("=> [div_expr] End of token stream!");
-                return Ok((dive_node, new_pos));
+            Some(LexToken::Ident(name))
+                if matches!(self.peek(pos), Some(LexToken::LeftParen('('))) =>
+            {
+                self.parse_call(name, pos, node_depth)
             }
-            _ => {
-                return Err(ParseError::InvalidTokenError(String::from(
-                    "Expected * sign!",
-                )));
+            Some(LexToken::Ident(name)) => {
+                trace!("term variable: {}", name);
+                let pt_node = ParseNode::with_span(
+                    CfgTerm::TermVariable(name.clone()),
+                    node_depth,
+                    self.span_at(pos),
+                );
+                Ok((pt_node, pos))
             }
+            Some(tok) => Err(ParseError::UnexpectedToken {
+                found: tok.clone(),
+                expected: self.expected_operand(pos),
+                after: self.token_before(pos),
+                span: self.span_at(pos),
+            }),
+            None => Err(ParseError::UnexpectedEndOfInput {
+                expected: self.expected_operand(pos),
+                after: self.token_before(pos),
+                span: self.eof_span(),
+            }),
         }
+    }
 
-        // look for div_expr
-        println!("=>[pos] looking for term ...");
-        let (tail_dive_node, tail_dive_pos) = self.parse_div_expr(new_pos + 2, node_depth + 1)?;
-        dive_node.add_child_node(tail_dive_node);
+    /// whether the '%' lexed as a percent at `pos` is the remainder operator.
+    /// The lexer takes a '%' before a '|' for a percent unless an operand
+    /// starts after the '|', but with no absolute value open the '|' opens
+    /// one.
+    fn percent_is_remainder(&self, pos: usize) -> bool {
+        self.open_bars == 0 && matches!(self.peek(pos), Some(LexToken::Bar(_)))
+    }
 
-        Ok((dive_node, tail_dive_pos))
+    /// whether `tok` is a logical or. Inside an absolute value a '||' after
+    /// an operand closes it with its first '|' instead.
+    fn is_or(&self, tok: &LexToken) -> bool {
+        matches!(tok, LexToken::Or(_)) && self.open_bars == 0
     }
 
-    /// parsing expression involving multiply (or '*')
-    fn parse_multi_div_expr(
+    /// parsing the postfix '!' and '%' operators following `operand_pos`,
+    /// if there are any. They bind tighter than '^' and apply from the left,
+    /// so `3!!` is `(3!)!`. As with power_expr, a node is only built when
+    /// there is an operator. A chain of them does not nest, as with '+'.
+    fn parse_postfix_rest(
+        &mut self,
+        mut operand_node: ParseNode,
+        mut operand_pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        loop {
+            operand_pos = self.skip_line_breaks(operand_pos);
+            let op_term = match self.peek(operand_pos) {
+                Some(LexToken::Factorial(_)) => CfgTerm::TermFactorial,
+                Some(LexToken::Percent(_)) if !self.percent_is_remainder(operand_pos + 1) => {
+                    CfgTerm::TermPercent
+                }
+                _ => return Ok((operand_node, operand_pos)),
+            };
+
+            // postfix_expr: postfix_expr '!' | postfix_expr '%'
+            let mut postfix_node = ParseNode::new(CfgTerm::NonTermPostfixExpr, node_depth);
+            postfix_node.add_child_node(operand_node);
+            postfix_node.add_child_node(ParseNode::with_span(
+                op_term,
+                node_depth + 1,
+                self.span_at(operand_pos + 1),
+            ));
+            trace!("postfix_node: {postfix_node}");
+            operand_node = postfix_node;
+            operand_pos += 1;
+        }
+    }
+
+    /// parsing exponentiation, which associates right: the exponent is a
+    /// unary_expr and so may itself be a power_expr. As with unary_expr, a
+    /// node is only built when there is a '^' (or '**'). The base is a term
+    /// with its postfix operators.
+    fn parse_power_expr(
         &mut self,
         pos: usize,
         node_depth: usize,
     ) -> Result<(ParseNode, usize), ParseError> {
-        println!("=> [{node_depth}][multi_div_expr] parsing node at position {pos}");
-        // call div_expr
-        let (div_expr_node, new_pos) = self.parse_div_expr(pos, node_depth + 1)?;
-        let mut mde_node = ParseNode::new(CfgTerm::NonTermMultiDivExpr, node_depth);
-        mde_node.child_nodes.push(div_expr_node);
-        println!("mde_node, after adding div_expr_node: {mde_node}");
+        trace!("=> [{node_depth}][power_expr] parsing node at position {pos}");
+        // power_expr: postfix_expr
+        let (term_node, term_pos) = self.parse_term(pos, node_depth)?;
+        let (base_node, base_pos) = self.parse_postfix_rest(term_node, term_pos, node_depth)?;
+        let op_node = match self.peek(base_pos) {
+            Some(LexToken::Power(_)) => ParseNode::with_span(
+                CfgTerm::TermPower,
+                node_depth + 1,
+                self.span_at(base_pos + 1),
+            ),
+            _ => return Ok((base_node, base_pos)),
+        };
 
-        // div_expr * multi_div_expr => look for multiply '*' symbol -
-        // div_expr => look for '+'/- symbol, this is essentially follow-on sets
-        println!("=> [multi_div_expr] at position: {new_pos}");
-        let tok = self.peek(new_pos);
-        match tok {
-            Some(LexToken::Multi(_)) => {
-                mde_node.add_child_node(ParseNode::new(CfgTerm::TermMultiply, node_depth + 1));
-            }
-            Some(LexToken::Add(_)) | Some(LexToken::Subtract(_)) => {
-                // return as this is end of div_expr, and is to be parsed by
-                // tail end of expr, so pass the current position (new_pos),
-                // which will then be parsed by the parent function
-                return Ok((mde_node, new_pos));
-            }
-            Some(LexToken::Num(n)) => {
-                // multi_div_expr _ <> expr
-                println!("=> [multi_div_expr] n: {}", *n);
-                let pt_node = ParseNode::new(CfgTerm::TermNumber(*n), node_depth);
-                mde_node.add_child_node(pt_node);
-                return Ok((mde_node, new_pos + 1));
-            }
-            Some(LexToken::RightParen(_)) => {
-                println!("=> [multi_div_expr] right parens");
-                return Ok((mde_node, new_pos));
-            }
-            None => {
-                println!("=> [multi_div_expr] End of token stream!");
-                return Ok((mde_node, new_pos + 1));
-            }
-            _ => {
-                return Err(ParseError::InvalidTokenError(String::from(
-                    "Expected * sign!",
-                )));
-            }
+        // power_expr: postfix_expr '^' unary_expr
+        let (exponent_node, exponent_pos) =
+            self.parse_nested(Self::parse_unary_expr, base_pos + 2, node_depth + 1)?;
+        let mut power_node = ParseNode::new(CfgTerm::NonTermPowerExpr, node_depth);
+        power_node.add_child_node(base_node);
+        power_node.add_child_node(op_node);
+        power_node.add_child_node(exponent_node);
+        trace!("power_node: {power_node}");
+        Ok((power_node, exponent_pos))
+    }
+
+    /// parsing a chain of prefix '-', '+' and '!' operators. A unary node is
+    /// only built when there is an operator, a bare term is returned
+    /// unchanged so that it sits directly below multi_div_expr.
+    fn parse_unary_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][unary_expr] parsing node at position {pos}");
+        let op_term = match self.token(pos) {
+            Some(LexToken::Subtract(_)) => CfgTerm::TermUnaryMinus,
+            Some(LexToken::Add(_)) => CfgTerm::TermUnaryPlus,
+            Some(LexToken::Not(_)) => CfgTerm::TermNot,
+            // unary_expr: power_expr
+            _ => return self.parse_power_expr(pos, node_depth),
+        };
+
+        // unary_expr: '-' unary_expr | '+' unary_expr | '!' unary_expr
+        let mut unary_node = ParseNode::new(CfgTerm::NonTermUnaryExpr, node_depth);
+        unary_node.add_child_node(ParseNode::with_span(
+            op_term,
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        let (operand_node, operand_pos) =
+            self.parse_nested(Self::parse_unary_expr, pos + 1, node_depth + 1)?;
+        unary_node.add_child_node(operand_node);
+        trace!("unary_node: {unary_node}");
+        Ok((unary_node, operand_pos))
+    }
+
+    /// whether the token after `pos` starts a factor multiplied with the
+    /// one ending at `pos`: a variable, a call, a bracket, a '|' when it
+    /// does not close an absolute value, or a number. A number right after
+    /// another one is not, so `2 3` stays an error.
+    fn starts_implicit_factor(&self, pos: usize) -> bool {
+        match self.peek(pos) {
+            Some(LexToken::Ident(_) | LexToken::LeftParen(_)) => true,
+            Some(LexToken::Bar(_)) => self.open_bars == 0,
+            Some(LexToken::Num(_)) => !matches!(self.token(pos), Some(LexToken::Num(_))),
+            _ => false,
         }
+    }
+
+    /// parsing the factors following `lhs_pos` that are multiplied with
+    /// `lhs_node` without a '*', when implicit multiplication is enabled.
+    /// The product binds tighter than '*' and '/', so `1/2x` is `1/(2x)`,
+    /// and is a multi_div_expr node whose operator is a
+    /// TermImplicitMultiply. The factors after the first are power_exprs,
+    /// as a '-' or '+' between two factors subtracts or adds.
+    ///
+    /// The operand is updated in place rather than passed through, which
+    /// keeps parse_multi_div_expr's frame small for deeply nested input.
+    fn parse_implicit_rest(
+        &mut self,
+        lhs_node: &mut ParseNode,
+        lhs_pos: &mut usize,
+        node_depth: usize,
+    ) -> Result<(), ParseError> {
+        while self.options.implicit_multiplication && self.starts_implicit_factor(*lhs_pos) {
+            // implicit_expr: implicit_expr power_expr
+            let factor_span = self.span_at(*lhs_pos + 1);
+            let op_node = ParseNode::with_span(
+                CfgTerm::TermImplicitMultiply,
+                node_depth + 1,
+                Span::new(
+                    factor_span.start,
+                    factor_span.start,
+                    factor_span.line,
+                    factor_span.column,
+                ),
+            );
+            let (rhs_node, rhs_pos) = self.parse_power_expr(*lhs_pos + 1, node_depth + 1)?;
+            let product_node = ParseNode::new(CfgTerm::NonTermMultiDivExpr, node_depth);
+            let factor_node = std::mem::replace(lhs_node, product_node);
+            lhs_node.add_child_node(factor_node);
+            lhs_node.add_child_node(op_node);
+            lhs_node.add_child_node(rhs_node);
+            trace!("implicit product_node: {lhs_node}");
+            *lhs_pos = rhs_pos;
+        }
+        Ok(())
+    }
+
+    /// parsing expression involving multiply or divide (or '*', '/', '%',
+    /// '//'). The operators share a precedence level and associate left, so
+    /// the node built so far becomes the left child of the next
+    /// multi_div_expr node.
+    fn parse_multi_div_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][multi_div_expr] parsing node at position {pos}");
+        // multi_div_expr: implicit_expr
+        let (mut term_node, mut new_pos) = self.parse_unary_expr(pos, node_depth + 1)?;
+        self.parse_implicit_rest(&mut term_node, &mut new_pos, node_depth + 1)?;
+        let mut mde_node = ParseNode::new(CfgTerm::NonTermMultiDivExpr, node_depth);
+        mde_node.add_child_node(term_node);
+        trace!("mde_node, after adding term_node: {mde_node}");
 
-        // look for expr
-        println!("=> looking for multi_div_expr ...");
-        let (tail_mde_node, tail_mde_pos) =
-            self.parse_multi_div_expr(new_pos + 2, node_depth + 1)?;
-        mde_node.add_child_node(tail_mde_node);
+        // multi_div_expr: multi_div_expr * implicit_expr | multi_div_expr / implicit_expr
+        //     | multi_div_expr % implicit_expr | multi_div_expr // implicit_expr
+        loop {
+            trace!("=> [multi_div_expr] at position: {new_pos}");
+            let op_term = match self.peek(new_pos) {
+                Some(LexToken::Multi(_)) => CfgTerm::TermMultiply,
+                Some(LexToken::Div(_)) => CfgTerm::TermDivide,
+                Some(LexToken::Modulo(_)) => CfgTerm::TermModulo,
+                Some(LexToken::Percent(_)) if self.percent_is_remainder(new_pos + 1) => {
+                    CfgTerm::TermModulo
+                }
+                Some(LexToken::IntDiv(_)) => CfgTerm::TermIntDivide,
+                tok => {
+                    // PEEK but don't consume, anything else (+/-, ')' or
+                    // the end of the token stream) is handled by the caller
+                    trace!("=> [multi_div_expr] end of multi_div_expr at {:?}", tok);
+                    return Ok((mde_node, new_pos));
+                }
+            };
+            let op_node = ParseNode::with_span(op_term, node_depth + 1, self.span_at(new_pos + 1));
 
-        Ok((mde_node, tail_mde_pos))
+            let (mut rhs_node, mut rhs_pos) = self.parse_unary_expr(new_pos + 2, node_depth + 1)?;
+            self.parse_implicit_rest(&mut rhs_node, &mut rhs_pos, node_depth + 1)?;
+            let mut parent_node = ParseNode::new(CfgTerm::NonTermMultiDivExpr, node_depth);
+            parent_node.add_child_node(mde_node);
+            parent_node.add_child_node(op_node);
+            parent_node.add_child_node(rhs_node);
+            mde_node = parent_node;
+            new_pos = rhs_pos;
+        }
     }
 
-    /// parsing top level expression
+    /// parsing top level expression, '+' and '-' associate left in the same
+    /// way as '*' and '/' do in multi_div_expr
     fn parse_expr(
         &mut self,
         pos: usize,
         node_depth: usize,
     ) -> Result<(ParseNode, usize), ParseError> {
-        println!("=> [{node_depth}][parse_expr] parsing at position {pos}");
-        let mut expr_node = ParseNode::new(crate::cfg::CfgTerm::NonTermExpr, node_depth);
-        let (multi_expr_node, new_pos) = self.parse_multi_div_expr(pos, node_depth + 1)?;
+        trace!("=> [{node_depth}][parse_expr] parsing at position {pos}");
+        // expr: multi_div_expr
+        let mut expr_node = ParseNode::new(CfgTerm::NonTermExpr, node_depth);
+        let (multi_expr_node, mut new_pos) = self.parse_multi_div_expr(pos, node_depth + 1)?;
         expr_node.add_child_node(multi_expr_node);
-        println!("expr_node, after adding multi_expr_node: {expr_node}");
+        trace!("expr_node, after adding multi_expr_node: {expr_node}");
 
-        // look for +/-
-        println!("=> [parse_expr] at position {new_pos}");
-        let tok = self.peek(new_pos);
-        match tok {
-            Some(LexToken::Add(_)) => {
-                expr_node.add_child_node(ParseNode::new(CfgTerm::TermPlus, node_depth + 1));
-            }
-            Some(LexToken::Subtract(_)) => {
-                expr_node.add_child_node(ParseNode::new(CfgTerm::TermMinus, node_depth + 1));
-            }
-            // Some(LexToken::Num(n)) => {
-            //     // multi_div_expr _ <> expr
-            //     println!("=> ***** [parse_expr] n: {}", *n);
-            //     println!("=> parsing tail expr ...");
-            //     let pt_node = ParseNode::new(CfgTerm::TermNumber(*n), node_depth);
-            //     expr_node.add_child_node(pt_node);
-            //     let (tail_expr_node, tail_expr_pos) =
-            //         self.parse_expr(new_pos + 1, node_depth + 1)?;
-            //     return Ok((tail_expr_node, tail_expr_pos));
-            // }
-            Some(LexToken::RightParen(_)) => {
-                println!("=> [expr] right parens");
-                return Ok((expr_node, new_pos));
+        // expr: expr + multi_div_expr | expr - multi_div_expr
+        loop {
+            trace!("=> [parse_expr] at position {new_pos}");
+            let op_term = match self.peek(new_pos) {
+                Some(LexToken::Add(_)) => CfgTerm::TermPlus,
+                Some(LexToken::Subtract(_)) => CfgTerm::TermMinus,
+                tok => {
+                    // a closing ')' is checked by parse_term, the end of the
+                    // token stream or trailing input by start_rule
+                    trace!("=> [parse_expr] end of expr at {:?}", tok);
+                    return Ok((expr_node, new_pos));
+                }
+            };
+            let op_node = ParseNode::with_span(op_term, node_depth + 1, self.span_at(new_pos + 1));
+
+            trace!("=> parsing tail multi_div_expr ...");
+            let (rhs_node, rhs_pos) = self.parse_multi_div_expr(new_pos + 2, node_depth + 1)?;
+            let mut parent_node = ParseNode::new(CfgTerm::NonTermExpr, node_depth);
+            parent_node.add_child_node(expr_node);
+            parent_node.add_child_node(op_node);
+            parent_node.add_child_node(rhs_node);
+            expr_node = parent_node;
+            new_pos = rhs_pos;
+        }
+    }
+
+    /// checks that the token after `pos` is the `expected` one, which
+    /// `is_expected` matches
+    fn expect_next(
+        &self,
+        pos: usize,
+        is_expected: fn(&LexToken) -> bool,
+        expected: &'static str,
+    ) -> Result<(), ParseError> {
+        match self.peek(pos) {
+            Some(tok) if is_expected(tok) => Ok(()),
+            Some(tok) => Err(ParseError::UnexpectedToken {
+                found: tok.clone(),
+                expected: vec![expected],
+                after: self.token_before(pos + 1),
+                span: self.span_at(pos + 1),
+            }),
+            None => Err(ParseError::UnexpectedEndOfInput {
+                expected: vec![expected],
+                after: self.token_before(pos + 1),
+                span: self.eof_span(),
+            }),
+        }
+    }
+
+    /// parsing a conditional, the loosest binding rule, which makes this the
+    /// rule for a whole expression. As with the operators, a node is only
+    /// built when there is an 'if' or a '?'.
+    fn parse_conditional_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][conditional_expr] parsing node at position {pos}");
+        if matches!(self.token(pos), Some(LexToken::If)) {
+            return self.parse_if_expr(pos, node_depth);
+        }
+        let (cond_node, cond_pos) = self.parse_or_expr(pos, node_depth)?;
+        match self.peek(cond_pos) {
+            Some(LexToken::Question(_)) => self.parse_ternary_rest(cond_node, cond_pos, node_depth),
+            // conditional_expr: or_expr
+            _ => Ok((cond_node, cond_pos)),
+        }
+    }
+
+    /// parsing the '?' following `cond_pos` and the two branches after it.
+    /// The branches are conditionals themselves, so that
+    /// `a ? b : c ? d : e` groups as `a ? b : (c ? d : e)`.
+    fn parse_ternary_rest(
+        &mut self,
+        cond_node: ParseNode,
+        cond_pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        // conditional_expr: or_expr '?' conditional_expr ':' conditional_expr
+        let mut conditional_node = ParseNode::new(CfgTerm::NonTermConditionalExpr, node_depth);
+        conditional_node.add_child_node(cond_node);
+        conditional_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermQuestion,
+            node_depth + 1,
+            self.span_at(cond_pos + 1),
+        ));
+        let (then_node, then_pos) =
+            self.parse_nested(Self::parse_conditional_expr, cond_pos + 2, node_depth + 1)?;
+        conditional_node.add_child_node(then_node);
+        self.expect_next(then_pos, |tok| matches!(tok, LexToken::Colon(_)), "':'")?;
+        conditional_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermColon,
+            node_depth + 1,
+            self.span_at(then_pos + 1),
+        ));
+        let (else_node, else_pos) =
+            self.parse_nested(Self::parse_conditional_expr, then_pos + 2, node_depth + 1)?;
+        conditional_node.add_child_node(else_node);
+        trace!("conditional_node: {conditional_node}");
+        Ok((conditional_node, else_pos))
+    }
+
+    /// parsing `if cond then a else b`, `pos` is the position of the 'if'.
+    /// The else branch reaches as far as it can, as with '?' and ':'.
+    fn parse_if_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        // conditional_expr: 'if' conditional_expr 'then' conditional_expr
+        //                   'else' conditional_expr
+        let mut conditional_node = ParseNode::new(CfgTerm::NonTermConditionalExpr, node_depth);
+        conditional_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermIf,
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        let (cond_node, cond_pos) =
+            self.parse_nested(Self::parse_conditional_expr, pos + 1, node_depth + 1)?;
+        conditional_node.add_child_node(cond_node);
+        self.expect_next(cond_pos, |tok| matches!(tok, LexToken::Then), "'then'")?;
+        conditional_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermThen,
+            node_depth + 1,
+            self.span_at(cond_pos + 1),
+        ));
+        let (then_node, then_pos) =
+            self.parse_nested(Self::parse_conditional_expr, cond_pos + 2, node_depth + 1)?;
+        conditional_node.add_child_node(then_node);
+        self.expect_next(then_pos, |tok| matches!(tok, LexToken::Else), "'else'")?;
+        conditional_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermElse,
+            node_depth + 1,
+            self.span_at(then_pos + 1),
+        ));
+        let (else_node, else_pos) =
+            self.parse_nested(Self::parse_conditional_expr, then_pos + 2, node_depth + 1)?;
+        conditional_node.add_child_node(else_node);
+        trace!("conditional_node: {conditional_node}");
+        Ok((conditional_node, else_pos))
+    }
+
+    /// parsing '||', the loosest binding operator. Nodes for '||', '&&' and
+    /// comparisons are only built when there is such an operator, so
+    /// arithmetic parses to the same tree it would without them. Every
+    /// nested expression passes through here, so the first operand is parsed
+    /// as an expr directly and the other levels are only entered when an
+    /// operator follows it, which keeps deep nesting from taking much more
+    /// stack.
+    fn parse_or_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][or_expr] parsing node at position {pos}");
+        let (lhs_node, lhs_pos) = self.parse_expr(pos, node_depth)?;
+        match self.peek(lhs_pos) {
+            Some(tok)
+                if matches!(tok, LexToken::And(_))
+                    || self.is_or(tok)
+                    || comparison_term(tok).is_some() =>
+            {
+                self.parse_or_expr_rest(lhs_node, lhs_pos, node_depth)
             }
-            None => {
-                println!("=> [parse_expr] End of token stream!");
-                return Ok((expr_node, new_pos));
+            _ => Ok((lhs_node, lhs_pos)),
+        }
+    }
+
+    /// parsing the rest of an or_expr whose first expr is `lhs_node`, kept
+    /// apart from parse_or_expr so that its stack frame stays small
+    fn parse_or_expr_rest(
+        &mut self,
+        lhs_node: ParseNode,
+        lhs_pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        let (comparison_node, comparison_pos) =
+            self.parse_comparison_rhs(lhs_node, lhs_pos, node_depth)?;
+        let (and_node, and_pos) = self.parse_logic_chain(
+            comparison_node,
+            comparison_pos,
+            node_depth,
+            CfgTerm::NonTermAndExpr,
+            Self::parse_comparison_expr,
+        )?;
+        // or_expr: or_expr '||' and_expr | and_expr
+        self.parse_logic_chain(
+            and_node,
+            and_pos,
+            node_depth,
+            CfgTerm::NonTermOrExpr,
+            Self::parse_and_expr,
+        )
+    }
+
+    /// parsing '&&', which associates left and binds tighter than '||'
+    fn parse_and_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][and_expr] parsing node at position {pos}");
+        let (lhs_node, lhs_pos) = self.parse_comparison_expr(pos, node_depth)?;
+        // and_expr: and_expr '&&' comparison_expr | comparison_expr
+        self.parse_logic_chain(
+            lhs_node,
+            lhs_pos,
+            node_depth,
+            CfgTerm::NonTermAndExpr,
+            Self::parse_comparison_expr,
+        )
+    }
+
+    /// parsing a comparison of two expressions
+    fn parse_comparison_expr(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][comparison_expr] parsing node at position {pos}");
+        let (lhs_node, lhs_pos) = self.parse_expr(pos, node_depth)?;
+        self.parse_comparison_rhs(lhs_node, lhs_pos, node_depth)
+    }
+
+    /// parsing the comparison operator following `lhs_pos` and the
+    /// expression after it, if there is one. Comparisons do not associate,
+    /// `a < b < c` is an error rather than comparing the boolean `a < b`
+    /// with `c`.
+    fn parse_comparison_rhs(
+        &mut self,
+        lhs_node: ParseNode,
+        lhs_pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        let op_node = match self.peek(lhs_pos).and_then(comparison_term) {
+            Some(op_term) => {
+                ParseNode::with_span(op_term, node_depth + 1, self.span_at(lhs_pos + 1))
             }
-            _ => {
-                return Err(ParseError::InvalidTokenError(String::from(
-                    "Expected +/- sign!",
-                )));
+            // comparison_expr: expr
+            None => return Ok((lhs_node, lhs_pos)),
+        };
+
+        // comparison_expr: expr COMPARISON expr
+        let (rhs_node, rhs_pos) = self.parse_expr(lhs_pos + 2, node_depth + 1)?;
+        if self.peek(rhs_pos).and_then(comparison_term).is_some() {
+            return Err(ParseError::ChainedComparison {
+                span: self.span_at(rhs_pos + 1),
+            });
+        }
+        let mut comparison_node = ParseNode::new(CfgTerm::NonTermComparisonExpr, node_depth);
+        comparison_node.add_child_node(lhs_node);
+        comparison_node.add_child_node(op_node);
+        comparison_node.add_child_node(rhs_node);
+        trace!("comparison_node: {comparison_node}");
+        Ok((comparison_node, rhs_pos))
+    }
+
+    /// parsing the '&&' operators following `new_pos`, or the '||' ones
+    /// when `kind` is NonTermOrExpr, and their right operands, which
+    /// `parse_operand` parses. They associate left in the same way as '+'
+    /// and '-' do in expr.
+    fn parse_logic_chain(
+        &mut self,
+        mut lhs_node: ParseNode,
+        mut new_pos: usize,
+        node_depth: usize,
+        kind: CfgTerm,
+        parse_operand: ParseRule<'a>,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        loop {
+            let op_term = match (self.peek(new_pos), &kind) {
+                (Some(LexToken::And(_)), CfgTerm::NonTermAndExpr) => CfgTerm::TermAnd,
+                (Some(tok), CfgTerm::NonTermOrExpr) if self.is_or(tok) => CfgTerm::TermOr,
+                _ => return Ok((lhs_node, new_pos)),
+            };
+            let op_node = ParseNode::with_span(op_term, node_depth + 1, self.span_at(new_pos + 1));
+            let (rhs_node, rhs_pos) = parse_operand(self, new_pos + 2, node_depth + 1)?;
+            let mut parent_node = ParseNode::new(kind.clone(), node_depth);
+            parent_node.add_child_node(lhs_node);
+            parent_node.add_child_node(op_node);
+            parent_node.add_child_node(rhs_node);
+            trace!("logic_node: {parent_node}");
+            lhs_node = parent_node;
+            new_pos = rhs_pos;
+        }
+    }
+
+    /// the position of the ')' when the tokens from `pos` on are
+    /// `VARIABLE '(' params? ')' '='`, the head of a function definition,
+    /// which is told apart from a call this way
+    fn definition_head_end(&self, pos: usize) -> Option<usize> {
+        let kind = |pos: usize| self.lex_tokens.get(pos).map(|t| &t.kind);
+        let mut curr_pos = pos + 2;
+        if !matches!(kind(curr_pos), Some(LexToken::RightParen(')'))) {
+            loop {
+                if !matches!(kind(curr_pos), Some(LexToken::Ident(_))) {
+                    return None;
+                }
+                curr_pos += 1;
+                match kind(curr_pos) {
+                    Some(LexToken::Comma(_)) => curr_pos += 1,
+                    _ => break,
+                }
             }
         }
+        match (kind(curr_pos), kind(curr_pos + 1)) {
+            (Some(LexToken::RightParen(')')), Some(LexToken::Assign(_))) => Some(curr_pos),
+            _ => None,
+        }
+    }
 
-        // look for expr
-        println!("=> parsing tail expr ...");
-        let (tail_expr_node, tail_expr_pos) = self.parse_expr(new_pos + 2, node_depth + 1)?;
-        expr_node.add_child_node(tail_expr_node);
+    /// parsing a function definition, `pos` is the position of the function
+    /// name and `head_end` that of the ')' closing the parameters
+    fn parse_definition(
+        &mut self,
+        name: &str,
+        pos: usize,
+        head_end: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][definition] parsing definition of {name}");
+        let mut definition_node = ParseNode::new(CfgTerm::NonTermDefinition, node_depth);
+        definition_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermFunction(name.to_string()),
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        definition_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermLeftParens,
+            node_depth + 1,
+            self.span_at(pos + 1),
+        ));
+        let mut params: Vec<&str> = Vec::new();
+        for param_pos in pos + 2..head_end {
+            let term = match &self.lex_tokens[param_pos].kind {
+                LexToken::Ident(param) if params.contains(&param.as_str()) => {
+                    return Err(ParseError::DuplicateParameter {
+                        name: param.clone(),
+                        span: self.span_at(param_pos),
+                    });
+                }
+                LexToken::Ident(param) => {
+                    params.push(param);
+                    CfgTerm::TermVariable(param.clone())
+                }
+                // definition_head_end only lets names and ',' through
+                _ => CfgTerm::TermComma,
+            };
+            definition_node.add_child_node(ParseNode::with_span(
+                term,
+                node_depth + 1,
+                self.span_at(param_pos),
+            ));
+        }
+        definition_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermRightParens,
+            node_depth + 1,
+            self.span_at(head_end),
+        ));
+        definition_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermAssign,
+            node_depth + 1,
+            self.span_at(head_end + 1),
+        ));
+        let (body_node, body_pos) = self.parse_conditional_expr(head_end + 2, node_depth + 1)?;
+        definition_node.add_child_node(body_node);
+        trace!("definition_node: {definition_node}");
+        Ok((definition_node, body_pos))
+    }
+
+    /// parsing a statement: an assignment when it starts with a variable
+    /// followed by '=', a function definition when it starts with
+    /// `name(params) =` and an expression otherwise
+    fn parse_statement(
+        &mut self,
+        pos: usize,
+        node_depth: usize,
+    ) -> Result<(ParseNode, usize), ParseError> {
+        trace!("=> [{node_depth}][statement] parsing at position {pos}");
+        let name = match (self.token(pos), self.peek(pos)) {
+            (Some(LexToken::Ident(name)), Some(LexToken::Assign(_))) => name.clone(),
+            // statement: VARIABLE '(' params? ')' '=' expr
+            (Some(LexToken::Ident(name)), Some(LexToken::LeftParen('('))) => {
+                if let Some(head_end) = self.definition_head_end(pos) {
+                    let name = name.clone();
+                    return self.parse_definition(&name, pos, head_end, node_depth);
+                }
+                return self.parse_conditional_expr(pos, node_depth);
+            }
+            // statement: conditional_expr
+            _ => return self.parse_conditional_expr(pos, node_depth),
+        };
+
+        // statement: VARIABLE '=' expr
+        let mut assignment_node = ParseNode::new(CfgTerm::NonTermAssignment, node_depth);
+        assignment_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermVariable(name),
+            node_depth + 1,
+            self.span_at(pos),
+        ));
+        assignment_node.add_child_node(ParseNode::with_span(
+            CfgTerm::TermAssign,
+            node_depth + 1,
+            self.span_at(pos + 1),
+        ));
+        let (expr_node, expr_pos) = self.parse_conditional_expr(pos + 2, node_depth + 1)?;
+        assignment_node.add_child_node(expr_node);
+        trace!("assignment_node: {assignment_node}");
+        Ok((assignment_node, expr_pos))
+    }
 
-        Ok((expr_node, tail_expr_pos))
+    /// the position of the first token at or after `pos` that is not a
+    /// statement separator
+    fn skip_separators(&self, mut pos: usize) -> usize {
+        while matches!(
+            self.token(pos),
+            Some(LexToken::Semicolon(_) | LexToken::Newline)
+        ) {
+            pos += 1;
+        }
+        pos
     }
 
-    /// respresents the start_rule in the grammar
-    fn start_rule(&mut self) -> Result<(), ParseError> {
+    /// respresents the start_rule in the grammar, a list of statements
+    /// separated by ';' or line breaks that must consume every token. Empty
+    /// statements are skipped, but there has to be at least one statement.
+    fn start_rule(&mut self) -> Result<ParseNode, ParseError> {
         let start_depth = 0;
-        let mut start_node = ParseNode::new(crate::cfg::CfgTerm::NonTermStartRule, start_depth);
-        let (expr_node, _) = self.parse_expr(0, start_depth + 1)?;
-        start_node.add_child_node(expr_node);
-        self.parsed_node = Some(start_node);
-        Ok(())
+        let mut start_node = ParseNode::new(CfgTerm::NonTermStartRule, start_depth);
+        let mut pos = self.skip_separators(0);
+        loop {
+            let (statement_node, statement_pos) = self.parse_statement(pos, start_depth + 1)?;
+            start_node.add_child_node(statement_node);
+
+            match self.peek(statement_pos) {
+                None => return Ok(start_node),
+                Some(LexToken::Semicolon(_) | LexToken::Newline) => {
+                    pos = self.skip_separators(statement_pos + 1);
+                    if pos >= self.lex_tokens.len() {
+                        return Ok(start_node);
+                    }
+                }
+                Some(LexToken::RightParen(c)) => {
+                    return Err(ParseError::UnbalancedParenthesis {
+                        paren: *c,
+                        span: self.span_at(statement_pos + 1),
+                    });
+                }
+                Some(tok) => {
+                    return Err(ParseError::TrailingInput {
+                        found: tok.clone(),
+                        span: self.span_at(statement_pos + 1),
+                    });
+                }
+            }
+        }
     }
 
-    pub(crate) fn parse(&mut self) -> Result<(), Box<dyn Error>> {
-        let _ = self.start_rule();
+    /// parses the lex tokens and returns the root of the parse tree
+    pub(crate) fn parse(&mut self) -> Result<ParseNode, ParseError> {
+        self.nesting = 0;
+        self.open_bars = 0;
+        self.split_or = None;
+        let mut start_node = self.start_rule()?;
+        start_node.set_child_depths();
+        Ok(start_node)
+    }
+}
 
-        Ok(())
+/// a closing bracket in quotes, as listed among the expected tokens
+fn quoted_bracket(close: char) -> &'static str {
+    match close {
+        ']' => "']'",
+        '}' => "'}'",
+        '|' => "'|'",
+        _ => "')'",
+    }
+}
+
+/// the terminal for a comparison operator, `None` for any other token
+fn comparison_term(tok: &LexToken) -> Option<CfgTerm> {
+    match tok {
+        LexToken::Equal(_) => Some(CfgTerm::TermEqual),
+        LexToken::NotEqual(_) => Some(CfgTerm::TermNotEqual),
+        LexToken::Less(_) => Some(CfgTerm::TermLess),
+        LexToken::LessEqual(_) => Some(CfgTerm::TermLessEqual),
+        LexToken::Greater(_) => Some(CfgTerm::TermGreater),
+        LexToken::GreaterEqual(_) => Some(CfgTerm::TermGreaterEqual),
+        _ => None,
     }
 }
@@ -1,440 +1,1441 @@
-mod tests {
-    use crate::cfg::mathparser::MathParser;
-    use crate::cfg::CfgTerm;
-    use crate::lex_multi_digit::lexer;
-
-    #[test]
-    fn test_parse_add_expr() {
-        // This test asserts that the below expression has the correct set of
-        // parse nodes, including the non-terminals and terminals.
-        let s = "2 + 3";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-                let left_child_expr_node = parsed_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a expr node");
-                println!("\nleft_child_expr_node: {}", left_child_expr_node);
-                assert_eq!(left_child_expr_node.current_node, CfgTerm::NonTermExpr);
-
-                let multi_div_expr_node = left_child_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a multi div expr node");
-                println!("\nmulti_div_expr_node: {}", multi_div_expr_node);
-                assert_eq!(
-                    multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let div_expr_node = multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                println!("\ndiv_expr_node: {}", div_expr_node);
-                assert_eq!(div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let num_node = div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(num_node.current_node, CfgTerm::TermNumber(2));
-
-                // '+' bit
-                let plus_child_node = left_child_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a terminal + node");
-                assert_eq!(plus_child_node.current_node, CfgTerm::TermPlus);
-
-                // right side of plus, expr -> multi_dev_expr '+' expr
-                let right_child_node = left_child_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a terminal number node");
-                assert_eq!(right_child_node.current_node, CfgTerm::NonTermExpr);
-
-                let right_child_me_node = right_child_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a Non terminal multi_expr node");
-                assert_eq!(
-                    right_child_me_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let right_child_div_node = right_child_me_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                assert_eq!(right_child_div_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let right_child_num_node = right_child_div_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a terminal number node");
-                assert_eq!(right_child_num_node.current_node, CfgTerm::TermNumber(3));
-            }
-            _ => {
-                println!("Unknown error!");
-            }
-        }
+use crate::cfg::mathparser::MathParser;
+use crate::cfg::{CfgTerm, ParseError, ParseNode, ParseOptions, DEFAULT_MAX_NESTING_DEPTH};
+use crate::lex::lex_multi_digit::lexer;
+use crate::lex::number::NumLiteral;
+use crate::lex::simple::LexToken;
+use crate::lex::Span;
+
+fn parse_str(s: &str) -> ParseNode {
+    parse_str_with(s, ParseOptions::default())
+}
+
+fn parse_str_with(s: &str, options: ParseOptions) -> ParseNode {
+    let my_lex = lexer(s).unwrap();
+    let tokens = my_lex.get_tokens();
+    let mut math_parser = MathParser::new(s, tokens, options);
+    math_parser.parse().expect("Expected a parse node")
+}
+
+/// what the parser expects where an operand is missing, with an 'if' where a
+/// whole conditional may start
+fn expected_operand(with_if: bool) -> Vec<&'static str> {
+    let mut expected = vec![
+        "number",
+        "variable",
+        "function call",
+        "string",
+        "boolean",
+        "'('",
+        "'['",
+        "'{'",
+        "'|'",
+        "'-'",
+        "'+'",
+        "'!'",
+    ];
+    if with_if {
+        expected.push("'if'");
     }
+    expected
+}
+
+fn parse_err(s: &str) -> ParseError {
+    parse_str_err(s, ParseOptions::default())
+}
+
+fn parse_str_err(s: &str, options: ParseOptions) -> ParseError {
+    let my_lex = lexer(s).unwrap();
+    let tokens = my_lex.get_tokens();
+    let mut math_parser = MathParser::new(s, tokens, options);
+    math_parser.parse().expect_err("Expected a parse error")
+}
+
+fn term_number(text: &str) -> CfgTerm {
+    CfgTerm::TermNumber(NumLiteral::new(text))
+}
+
+/// returns the single expr node below the start rule
+fn start_expr_node(parsed_node: &ParseNode) -> &ParseNode {
+    println!("parsed node: {}", parsed_node);
+    assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
+    assert_eq!(parsed_node.child_nodes.len(), 1);
+    let expr_node = parsed_node
+        .child_nodes
+        .first()
+        .expect("Expected a expr node");
+    assert_eq!(expr_node.current_node, CfgTerm::NonTermExpr);
+    expr_node
+}
+
+#[test]
+fn test_parse_add_expr() {
+    // This test asserts that the below expression has the correct set of
+    // parse nodes, including the non-terminals and terminals.
+    let parsed_node = parse_str("2 + 3");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes.len(), 3);
+
+    // left side of plus, expr -> expr '+' multi_div_expr
+    let left_expr_node = expr_node.child_nodes.first().expect("Expected a expr node");
+    assert_eq!(left_expr_node.current_node, CfgTerm::NonTermExpr);
+
+    let left_mde_node = left_expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let left_num_node = left_mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a num node");
+    assert_eq!(left_num_node.current_node, term_number("2"));
+
+    // '+' bit
+    let plus_node = expr_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a terminal + node");
+    assert_eq!(plus_node.current_node, CfgTerm::TermPlus);
+
+    // right side of plus
+    let right_mde_node = expr_node
+        .child_nodes
+        .get(2)
+        .expect("Expected a multi div expr node");
+    assert_eq!(right_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let right_num_node = right_mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a num node");
+    assert_eq!(right_num_node.current_node, term_number("3"));
+}
+
+#[test]
+fn test_multiply_expr() {
+    let parsed_node = parse_str("3 * 4");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes.len(), 1);
+
+    let mde_node = expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(mde_node.child_nodes.len(), 3);
+
+    let left_mde_node = mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let left_num_node = left_mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a num node");
+    assert_eq!(left_num_node.current_node, term_number("3"));
+
+    let multiply_node = mde_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a multiply term node");
+    assert_eq!(multiply_node.current_node, CfgTerm::TermMultiply);
+
+    let right_num_node = mde_node.child_nodes.get(2).expect("Expected a num node");
+    assert_eq!(right_num_node.current_node, term_number("4"));
+}
+
+#[test]
+fn test_divide_expr() {
+    let parsed_node = parse_str("3 / 4");
+    let expr_node = start_expr_node(&parsed_node);
+
+    let mde_node = expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(mde_node.child_nodes.len(), 3);
+
+    let left_mde_node = mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(left_mde_node.child_nodes[0].current_node, term_number("3"));
+
+    let divide_node = mde_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a divide term node");
+    assert_eq!(divide_node.current_node, CfgTerm::TermDivide);
+
+    let right_num_node = mde_node.child_nodes.get(2).expect("Expected a num node");
+    assert_eq!(right_num_node.current_node, term_number("4"));
+}
+
+#[test]
+fn test_subtract_is_left_associative() {
+    // 8 - 2 - 1 => (8 - 2) - 1
+    let parsed_node = parse_str("8 - 2 - 1");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes.len(), 3);
+    assert_eq!(expr_node.child_nodes[1].current_node, CfgTerm::TermMinus);
+
+    // right operand is the last number
+    let right_mde_node = &expr_node.child_nodes[2];
+    assert_eq!(right_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(right_mde_node.child_nodes[0].current_node, term_number("1"));
+
+    // left operand is the (8 - 2) sub expression
+    let left_expr_node = &expr_node.child_nodes[0];
+    assert_eq!(left_expr_node.current_node, CfgTerm::NonTermExpr);
+    assert_eq!(left_expr_node.child_nodes.len(), 3);
+    assert_eq!(
+        left_expr_node.child_nodes[1].current_node,
+        CfgTerm::TermMinus
+    );
+    assert_eq!(
+        left_expr_node.child_nodes[2].child_nodes[0].current_node,
+        term_number("2")
+    );
+
+    let inner_expr_node = &left_expr_node.child_nodes[0];
+    assert_eq!(inner_expr_node.current_node, CfgTerm::NonTermExpr);
+    assert_eq!(inner_expr_node.child_nodes.len(), 1);
+    assert_eq!(
+        inner_expr_node.child_nodes[0].child_nodes[0].current_node,
+        term_number("8")
+    );
+}
+
+#[test]
+fn test_divide_is_left_associative() {
+    // 8 / 4 / 2 => (8 / 4) / 2
+    let parsed_node = parse_str("8 / 4 / 2");
+    let expr_node = start_expr_node(&parsed_node);
+
+    let mde_node = &expr_node.child_nodes[0];
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(mde_node.child_nodes.len(), 3);
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermDivide);
+    assert_eq!(mde_node.child_nodes[2].current_node, term_number("2"));
+
+    let left_mde_node = &mde_node.child_nodes[0];
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(left_mde_node.child_nodes.len(), 3);
+    assert_eq!(
+        left_mde_node.child_nodes[1].current_node,
+        CfgTerm::TermDivide
+    );
+    assert_eq!(left_mde_node.child_nodes[2].current_node, term_number("4"));
+    assert_eq!(
+        left_mde_node.child_nodes[0].child_nodes[0].current_node,
+        term_number("8")
+    );
+}
+
+#[test]
+fn test_multiply_and_divide_share_precedence() {
+    // 8 / 4 * 2 => (8 / 4) * 2, and not 8 / (4 * 2)
+    let parsed_node = parse_str("8 / 4 * 2");
+    let expr_node = start_expr_node(&parsed_node);
+
+    let mde_node = &expr_node.child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermMultiply);
+    assert_eq!(mde_node.child_nodes[2].current_node, term_number("2"));
+
+    let left_mde_node = &mde_node.child_nodes[0];
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(
+        left_mde_node.child_nodes[1].current_node,
+        CfgTerm::TermDivide
+    );
+}
+
+#[test]
+fn test_parse_node_depth() {
+    // the left operand is pushed down a level each time it is wrapped
+    let parsed_node = parse_str("1 - 2 - 3");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.node_depth, 1);
+    assert_eq!(expr_node.child_nodes[1].node_depth, 2);
+    assert_eq!(expr_node.child_nodes[2].node_depth, 2);
 
-    #[test]
-    fn test_multiply_expr() {
-        let s = "3 * 4";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-
-                let left_child_expr_node = parsed_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a expr node");
-                println!("\nleft_child_expr_node: {}", left_child_expr_node);
-                assert_eq!(left_child_expr_node.current_node, CfgTerm::NonTermExpr);
-
-                let multi_div_expr_node = left_child_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a multi div expr node");
-                println!("\nmulti_div_expr_node: {}", multi_div_expr_node);
-                assert_eq!(
-                    multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let div_expr_node = multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                println!("\ndiv_expr_node: {}", div_expr_node);
-                assert_eq!(div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let num_node = div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(num_node.current_node, CfgTerm::TermNumber(3));
-
-                let term_multiply_node = multi_div_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a multiply term node");
-                println!("\nterm_multiply_node: {}", term_multiply_node);
-                assert_eq!(term_multiply_node.current_node, CfgTerm::TermMultiply);
-
-                let right_multi_div_expr_node = multi_div_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a multi div expr node");
-                println!("\nright_multi_div_expr_node: {}", right_multi_div_expr_node);
-                assert_eq!(
-                    right_multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let right_div_expr_node = right_multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                println!("\nright_div_expr_node: {}", right_div_expr_node);
-                assert_eq!(right_div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let right_num_node = right_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(right_num_node.current_node, CfgTerm::TermNumber(4));
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+    let left_expr_node = &expr_node.child_nodes[0];
+    assert_eq!(left_expr_node.node_depth, 2);
+    assert_eq!(left_expr_node.child_nodes[0].node_depth, 3);
+    assert_eq!(left_expr_node.child_nodes[0].child_nodes[0].node_depth, 4);
+}
+
+#[test]
+fn test_parens_and_add_expr() {
+    let parsed_node = parse_str("(2 / 3) + 4");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes.len(), 3);
+    assert_eq!(expr_node.child_nodes[1].current_node, CfgTerm::TermPlus);
+}
+
+#[test]
+fn test_parens_and_div_expr() {
+    let parsed_node = parse_str("(2 / 3) / 4");
+    let expr_node = start_expr_node(&parsed_node);
+
+    let mde_node = expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let left_mde_node = mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a multi div expr node");
+    assert_eq!(left_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let term_expr_node = left_mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a term expr node");
+    assert_eq!(term_expr_node.current_node, CfgTerm::NonTermTermExpr);
+
+    let left_parens_node = term_expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a left parens node");
+    assert_eq!(left_parens_node.current_node, CfgTerm::TermLeftParens);
+
+    // fill the ( expr ) => ( 2 / 3) bit here
+
+    let sub_term_expr_node = term_expr_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a sub term expr node");
+    assert_eq!(sub_term_expr_node.current_node, CfgTerm::NonTermExpr);
+
+    let sub_term_mde_node = sub_term_expr_node
+        .child_nodes
+        .first()
+        .expect("Expected a sub term multi div expr node");
+    assert_eq!(sub_term_mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let sub_term_left_mde_node = sub_term_mde_node
+        .child_nodes
+        .first()
+        .expect("Expected a sub term left multi div expr node");
+    assert_eq!(
+        sub_term_left_mde_node.current_node,
+        CfgTerm::NonTermMultiDivExpr
+    );
+    assert_eq!(
+        sub_term_left_mde_node.child_nodes[0].current_node,
+        term_number("2")
+    );
+
+    let sub_term_div_sym_node = sub_term_mde_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a sub term divide symbol node");
+    assert_eq!(sub_term_div_sym_node.current_node, CfgTerm::TermDivide);
+
+    let sub_term_right_num_node = sub_term_mde_node
+        .child_nodes
+        .get(2)
+        .expect("Expected a sub term right num node");
+    assert_eq!(sub_term_right_num_node.current_node, term_number("3"));
+
+    // sub term ends
+
+    let right_parens_node = term_expr_node
+        .child_nodes
+        .get(2)
+        .expect("Expected a right parens node");
+    assert_eq!(right_parens_node.current_node, CfgTerm::TermRightParens);
+
+    // '/' bit
+    let div_node = mde_node
+        .child_nodes
+        .get(1)
+        .expect("Expected a div symbol terminal node");
+    assert_eq!(div_node.current_node, CfgTerm::TermDivide);
+
+    let right_num_node = mde_node.child_nodes.get(2).expect("Expected a num node");
+    assert_eq!(right_num_node.current_node, term_number("4"));
+}
+
+#[test]
+fn test_parens_and_multiply_expr() {
+    let parsed_node = parse_str("(2 / 3) * 4");
+    let expr_node = start_expr_node(&parsed_node);
+    let mde_node = &expr_node.child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermMultiply);
+}
+
+#[test]
+fn test_parens_and_parens_expr() {
+    let parsed_node = parse_str("(2 / 3) / ( 3 / 4)");
+    let expr_node = start_expr_node(&parsed_node);
+    let mde_node = &expr_node.child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermDivide);
+    assert_eq!(
+        mde_node.child_nodes[2].current_node,
+        CfgTerm::NonTermTermExpr
+    );
+}
+
+#[test]
+fn test_unary_minus_chain() {
+    // --3: unary_expr -> '-' unary_expr -> '-' term
+    let parsed_node = parse_str("--3");
+    let expr_node = start_expr_node(&parsed_node);
+    let mde_node = &expr_node.child_nodes[0];
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+
+    let outer_node = &mde_node.child_nodes[0];
+    assert_eq!(outer_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(
+        outer_node.child_nodes[0].current_node,
+        CfgTerm::TermUnaryMinus
+    );
+    assert_eq!(outer_node.span, Span::new(0, 3, 1, 1));
+
+    let inner_node = &outer_node.child_nodes[1];
+    assert_eq!(inner_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(
+        inner_node.child_nodes[0].current_node,
+        CfgTerm::TermUnaryMinus
+    );
+    assert_eq!(inner_node.child_nodes[0].span, Span::new(1, 2, 1, 2));
+    assert_eq!(inner_node.child_nodes[1].current_node, term_number("3"));
+    assert_eq!(
+        inner_node.child_nodes[1].node_depth,
+        inner_node.node_depth + 1
+    );
+}
+
+#[test]
+fn test_unary_binds_tighter_than_multiply() {
+    // 2*-4: the '-' belongs to the right operand of '*'
+    let parsed_node = parse_str("2*-4");
+    let expr_node = start_expr_node(&parsed_node);
+    let mde_node = &expr_node.child_nodes[0];
+    let kinds: Vec<&CfgTerm> = mde_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermMultiDivExpr,
+            &CfgTerm::TermMultiply,
+            &CfgTerm::NonTermUnaryExpr
+        ]
+    );
+    let unary_node = &mde_node.child_nodes[2];
+    assert_eq!(unary_node.child_nodes[1].current_node, term_number("4"));
+
+    // -2*3 is (-2)*3, the unary node is the left operand
+    let parsed_node = parse_str("-2*3");
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    let lhs_node = &mde_node.child_nodes[0].child_nodes[0];
+    assert_eq!(lhs_node.current_node, CfgTerm::NonTermUnaryExpr);
+}
+
+#[test]
+fn test_unary_minus_and_parens() {
+    let parsed_node = parse_str("-(1+2)");
+    let unary_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(unary_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(
+        unary_node.child_nodes[1].current_node,
+        CfgTerm::NonTermTermExpr
+    );
+
+    let parsed_node = parse_str("1 - +2");
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes[1].current_node, CfgTerm::TermMinus);
+    let unary_node = &expr_node.child_nodes[2].child_nodes[0];
+    assert_eq!(
+        unary_node.child_nodes[0].current_node,
+        CfgTerm::TermUnaryPlus
+    );
+}
+
+#[test]
+fn test_modulo_and_int_divide_share_precedence_with_multiply() {
+    // 7 % 4 // 2 * 3 is ((7 % 4) // 2) * 3
+    let parsed_node = parse_str("7 % 4 // 2 * 3");
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermMultiply);
+
+    let int_div_node = &mde_node.child_nodes[0];
+    assert_eq!(
+        int_div_node.child_nodes[1].current_node,
+        CfgTerm::TermIntDivide
+    );
+    assert_eq!(int_div_node.child_nodes[1].span, Span::new(6, 8, 1, 7));
+
+    let modulo_node = &int_div_node.child_nodes[0];
+    let kinds: Vec<&CfgTerm> = modulo_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermMultiDivExpr,
+            &CfgTerm::TermModulo,
+            &term_number("4")
+        ]
+    );
+}
+
+#[test]
+fn test_power_is_right_associative() {
+    // 2^3^2: power_expr -> term '^' unary_expr, the exponent holds 3^2
+    let parsed_node = parse_str("2^3^2");
+    let power_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    let kinds: Vec<&CfgTerm> = power_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &term_number("2"),
+            &CfgTerm::TermPower,
+            &CfgTerm::NonTermPowerExpr
+        ]
+    );
+    let exponent_node = &power_node.child_nodes[2];
+    assert_eq!(exponent_node.span, Span::new(2, 5, 1, 3));
+    assert_eq!(exponent_node.child_nodes[0].current_node, term_number("3"));
+    assert_eq!(
+        exponent_node.child_nodes[0].node_depth,
+        exponent_node.node_depth + 1
+    );
+}
+
+#[test]
+fn test_power_binds_tighter_than_unary_minus() {
+    // -2^2 is -(2^2)
+    let parsed_node = parse_str("-2^2");
+    let unary_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(unary_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(
+        unary_node.child_nodes[1].current_node,
+        CfgTerm::NonTermPowerExpr
+    );
+
+    // 2^-1 takes a unary exponent
+    let parsed_node = parse_str("2**-1");
+    let power_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(power_node.child_nodes[1].span, Span::new(1, 3, 1, 2));
+    assert_eq!(
+        power_node.child_nodes[2].current_node,
+        CfgTerm::NonTermUnaryExpr
+    );
+
+    // 2*3^2 is 2*(3^2)
+    let parsed_node = parse_str("2*3^2");
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    assert_eq!(
+        mde_node.child_nodes[2].current_node,
+        CfgTerm::NonTermPowerExpr
+    );
+}
+
+#[test]
+fn test_parse_variables() {
+    let s = "price * qty + price";
+    let parsed_node = parse_str(s);
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    let kinds: Vec<&CfgTerm> = mde_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermMultiDivExpr,
+            &CfgTerm::TermMultiply,
+            &CfgTerm::TermVariable(String::from("qty"))
+        ]
+    );
+    assert_eq!(mde_node.child_nodes[2].span, Span::new(8, 11, 1, 9));
+    assert_eq!(parsed_node.free_variables(), vec!["price", "qty"]);
+    assert!(parse_str("(1 + 2) * 3").free_variables().is_empty());
+}
+
+#[test]
+fn test_parse_statements() {
+    let s = "a = 1;\n; b = (a\n  + 1)\nb";
+    let parsed_node = parse_str(s);
+    assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
+    let kinds: Vec<&CfgTerm> = parsed_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermAssignment,
+            &CfgTerm::NonTermAssignment,
+            &CfgTerm::NonTermExpr
+        ]
+    );
+
+    let assignment_node = &parsed_node.child_nodes[1];
+    assert_eq!(assignment_node.node_depth, 1);
+    assert_eq!(assignment_node.span, Span::new(9, 22, 2, 3));
+    let kinds: Vec<&CfgTerm> = assignment_node
+        .child_nodes
+        .iter()
+        .map(|n| n.kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::TermVariable(String::from("b")),
+            &CfgTerm::TermAssign,
+            &CfgTerm::NonTermExpr
+        ]
+    );
+    assert_eq!(assignment_node.child_nodes[2].node_depth, 2);
+    // a is assigned before it is used, so nothing is free
+    assert!(parsed_node.free_variables().is_empty());
+    assert_eq!(parse_str("a = a + b; b").free_variables(), vec!["a", "b"]);
+
+    assert_eq!(
+        parse_err("a = 1 = 2"),
+        ParseError::TrailingInput {
+            found: LexToken::Assign('='),
+            span: Span::new(6, 7, 1, 7),
         }
-    }
+    );
+    assert_eq!(
+        parse_err(";\n"),
+        ParseError::UnexpectedEndOfInput {
+            expected: expected_operand(true),
+            after: Some(LexToken::Semicolon(';')),
+            span: Span::new(1, 1, 1, 2),
+        }
+    );
+}
+
+#[test]
+fn test_parse_calls() {
+    let s = "max(1, x + 2) * f()";
+    let parsed_node = parse_str(s);
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    let call_node = &mde_node.child_nodes[0].child_nodes[0];
+    assert_eq!(call_node.current_node, CfgTerm::NonTermCallExpr);
+    assert_eq!(call_node.span, Span::new(0, 13, 1, 1));
+    let kinds: Vec<&CfgTerm> = call_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::TermFunction(String::from("max")),
+            &CfgTerm::TermLeftParens,
+            &CfgTerm::NonTermExpr,
+            &CfgTerm::TermComma,
+            &CfgTerm::NonTermExpr,
+            &CfgTerm::TermRightParens
+        ]
+    );
+    assert_eq!(call_node.child_nodes[3].span, Span::new(5, 6, 1, 6));
+
+    let empty_call_node = &mde_node.child_nodes[2];
+    assert_eq!(empty_call_node.child_nodes.len(), 3);
+    assert_eq!(empty_call_node.span, Span::new(16, 19, 1, 17));
+    // function names are not variables
+    assert_eq!(parsed_node.free_variables(), vec!["x"]);
 
-    #[test]
-    fn test_divide_expr() {
-        let s = "3 / 4";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-
-                let left_child_expr_node = parsed_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a expr node");
-                println!("\nleft_child_expr_node: {}", left_child_expr_node);
-                assert_eq!(left_child_expr_node.current_node, CfgTerm::NonTermExpr);
-
-                let multi_div_expr_node = left_child_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a multi div expr node");
-                println!("\nmulti_div_expr_node: {}", multi_div_expr_node);
-                assert_eq!(
-                    multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let div_expr_node = multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                println!("\ndiv_expr_node: {}", div_expr_node);
-                assert_eq!(div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let num_node = div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(num_node.current_node, CfgTerm::TermNumber(3));
-
-                let term_divide_node = div_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a divide term node");
-                println!("\nterm_divide_node: {}", term_divide_node);
-                assert_eq!(term_divide_node.current_node, CfgTerm::TermDivide);
-
-                let right_div_expr_node = div_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a div expr node");
-                assert_eq!(right_div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let right_num_node = right_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(right_num_node.current_node, CfgTerm::TermNumber(4));
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+    assert_eq!(
+        parse_err("max(1 2)"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Num(NumLiteral::new("2")),
+            expected: vec!["operator", "','", "')'"],
+            after: Some(LexToken::Num(NumLiteral::new("1"))),
+            span: Span::new(6, 7, 1, 7),
         }
-    }
+    );
+    assert_eq!(
+        parse_err("max(1,"),
+        ParseError::UnexpectedEndOfInput {
+            expected: expected_operand(true),
+            after: Some(LexToken::Comma(',')),
+            span: Span::new(6, 6, 1, 7),
+        }
+    );
+    assert_eq!(
+        parse_err("sqrt(4"),
+        ParseError::UnbalancedParenthesis {
+            paren: '(',
+            span: Span::new(4, 5, 1, 5),
+        }
+    );
+    assert!(matches!(
+        parse_err("f(1,)"),
+        ParseError::UnexpectedToken { .. }
+    ));
+}
+
+#[test]
+fn test_parse_definitions() {
+    let s = "hyp(a, b) = sqrt(a^2 + b^2) * k\nhyp(3, 4)";
+    let parsed_node = parse_str(s);
+    let definition_node = &parsed_node.child_nodes[0];
+    assert_eq!(definition_node.current_node, CfgTerm::NonTermDefinition);
+    assert_eq!(definition_node.span, Span::new(0, 31, 1, 1));
+    let kinds: Vec<&CfgTerm> = definition_node
+        .child_nodes
+        .iter()
+        .map(|n| n.kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::TermFunction(String::from("hyp")),
+            &CfgTerm::TermLeftParens,
+            &CfgTerm::TermVariable(String::from("a")),
+            &CfgTerm::TermComma,
+            &CfgTerm::TermVariable(String::from("b")),
+            &CfgTerm::TermRightParens,
+            &CfgTerm::TermAssign,
+            &CfgTerm::NonTermExpr
+        ]
+    );
+    assert_eq!(definition_node.child_nodes[4].span, Span::new(7, 8, 1, 8));
+    assert_eq!(
+        parsed_node.child_nodes[1].current_node,
+        CfgTerm::NonTermExpr
+    );
+    // the parameters are only bound in the body
+    assert_eq!(parsed_node.free_variables(), vec!["k"]);
+    assert_eq!(parse_str("f(x) = 1; x").free_variables(), vec!["x"]);
+    assert_eq!(parse_str("one() = 1").child_nodes[0].child_nodes.len(), 5);
 
-    #[test]
-    fn test_parens_and_add_expr() {
-        let s = "(2 / 3) + 4";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+    // without '=' after the ')' it is a call
+    assert_eq!(
+        parse_str("f(x) + 1").child_nodes[0].current_node,
+        CfgTerm::NonTermExpr
+    );
+    assert_eq!(
+        parse_err("f(x + 1) = 2"),
+        ParseError::TrailingInput {
+            found: LexToken::Assign('='),
+            span: Span::new(9, 10, 1, 10),
         }
-    }
+    );
+    assert_eq!(
+        parse_err("f(x, y, x) = x"),
+        ParseError::DuplicateParameter {
+            name: String::from("x"),
+            span: Span::new(8, 9, 1, 9),
+        }
+    );
+    assert!(matches!(
+        parse_err("f(x) ="),
+        ParseError::UnexpectedEndOfInput { .. }
+    ));
+}
+
+#[test]
+fn test_parse_comparisons_and_logic() {
+    // '||' binds loosest, then '&&', then the comparisons
+    let s = "qty > 0 && price <= 1000 || !vip";
+    let parsed_node = parse_str(s);
+    let or_node = &parsed_node.child_nodes[0];
+    assert_eq!(or_node.current_node, CfgTerm::NonTermOrExpr);
+    assert_eq!(or_node.node_depth, 1);
+    assert_eq!(or_node.child_nodes[1].current_node, CfgTerm::TermOr);
+    assert_eq!(or_node.child_nodes[1].span, Span::new(25, 27, 1, 26));
 
-    #[test]
-    fn test_parens_and_div_expr() {
-        let s = "(2 / 3) / 4";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-
-                let left_child_expr_node = parsed_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a expr node");
-                println!("\nleft_child_expr_node: {}", left_child_expr_node);
-                assert_eq!(left_child_expr_node.current_node, CfgTerm::NonTermExpr);
-
-                let multi_div_expr_node = left_child_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a multi div expr node");
-                println!("\nmulti_div_expr_node: {}", multi_div_expr_node);
-                assert_eq!(
-                    multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let div_expr_node = multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a div expr node");
-                println!("\ndiv_expr_node: {}", div_expr_node);
-                assert_eq!(div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let term_expr_node = div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(term_expr_node.current_node, CfgTerm::NonTermTermExpr);
-
-                let left_parens_node = term_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a left parens node");
-                assert_eq!(left_parens_node.current_node, CfgTerm::TermLeftParens);
-
-                // fill the ( expr ) => ( 2 / 3) bit here
-
-                let sub_term_expr_node = term_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a sub term expr node");
-                assert_eq!(sub_term_expr_node.current_node, CfgTerm::NonTermExpr);
-
-                let sub_term_multi_div_expr_node = sub_term_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a sub term multi div expr node");
-                assert_eq!(
-                    sub_term_multi_div_expr_node.current_node,
-                    CfgTerm::NonTermMultiDivExpr
-                );
-
-                let sub_term_div_expr_node = sub_term_multi_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a sub term div expr node");
-                assert_eq!(sub_term_div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let sub_term_left_num_node = sub_term_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a sub term left num node");
-                assert_eq!(sub_term_left_num_node.current_node, CfgTerm::TermNumber(2));
-
-                let sub_term_div_sym_node = sub_term_div_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a sub term divide symbol node");
-                assert_eq!(sub_term_div_sym_node.current_node, CfgTerm::TermDivide);
-
-                let sub_term_right_div_expr_node = sub_term_div_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a sub term right div expr node");
-                assert_eq!(
-                    sub_term_right_div_expr_node.current_node,
-                    CfgTerm::NonTermDivExpr
-                );
-
-                let sub_term_right_num_node = sub_term_right_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a sub term right num node");
-                assert_eq!(sub_term_right_num_node.current_node, CfgTerm::TermNumber(3));
-
-                // sub term ends
-
-                let right_parens_node = term_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a right parens node");
-                assert_eq!(right_parens_node.current_node, CfgTerm::TermRightParens);
-
-                // '/' bit
-                let div_node = div_expr_node
-                    .child_nodes
-                    .get(1)
-                    .expect("Expected a div symbol terminal node");
-                assert_eq!(div_node.current_node, CfgTerm::TermDivide);
-
-                let right_div_expr_node = div_expr_node
-                    .child_nodes
-                    .get(2)
-                    .expect("Expected a div symbol terminal node");
-                assert_eq!(right_div_expr_node.current_node, CfgTerm::NonTermDivExpr);
-
-                let right_num_node = right_div_expr_node
-                    .child_nodes
-                    .get(0)
-                    .expect("Expected a num node");
-                assert_eq!(right_num_node.current_node, CfgTerm::TermNumber(4));
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+    let and_node = &or_node.child_nodes[0];
+    assert_eq!(and_node.current_node, CfgTerm::NonTermAndExpr);
+    assert_eq!(and_node.node_depth, 2);
+    assert_eq!(and_node.span, Span::new(0, 24, 1, 1));
+    let kinds: Vec<&CfgTerm> = and_node.child_nodes.iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermComparisonExpr,
+            &CfgTerm::TermAnd,
+            &CfgTerm::NonTermComparisonExpr
+        ]
+    );
+    let comparison_node = &and_node.child_nodes[2];
+    assert_eq!(comparison_node.node_depth, 3);
+    assert_eq!(
+        comparison_node.child_nodes[0].current_node,
+        CfgTerm::NonTermExpr
+    );
+    assert_eq!(comparison_node.child_nodes[0].node_depth, 4);
+    assert_eq!(
+        comparison_node.child_nodes[1].current_node,
+        CfgTerm::TermLessEqual
+    );
+
+    let not_node = &or_node.child_nodes[2].child_nodes[0].child_nodes[0];
+    assert_eq!(not_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(not_node.child_nodes[0].current_node, CfgTerm::TermNot);
+    assert_eq!(parsed_node.free_variables(), vec!["qty", "price", "vip"]);
+
+    // without a boolean operator the tree is the arithmetic one
+    start_expr_node(&parse_str("1 + 2"));
+    let parsed_node = parse_str("true");
+    let bool_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(bool_node.current_node, CfgTerm::TermBool(true));
+    assert_eq!(
+        parse_str("x = a == b").child_nodes[0].child_nodes[2].current_node,
+        CfgTerm::NonTermComparisonExpr
+    );
+}
+
+#[test]
+fn test_parse_chained_comparison() {
+    assert_eq!(
+        parse_err("1 < x <= 3"),
+        ParseError::ChainedComparison {
+            span: Span::new(6, 8, 1, 7)
         }
-    }
+    );
+    assert!(matches!(
+        parse_err("a == b == c"),
+        ParseError::ChainedComparison { .. }
+    ));
+    // parentheses or '&&' between them are fine
+    parse_str("(a == b) == c");
+    parse_str("1 < x && x <= 3");
+    assert!(matches!(
+        parse_err("1 <"),
+        ParseError::UnexpectedEndOfInput { .. }
+    ));
+    assert!(matches!(
+        parse_err("a || && b"),
+        ParseError::UnexpectedToken {
+            found: LexToken::And("&&"),
+            ..
+        }
+    ));
+    // with no operand before it, '||' opens two absolute values
+    assert!(matches!(
+        parse_err("a && || b"),
+        ParseError::UnbalancedParenthesis { paren: '|', .. }
+    ));
+}
+
+#[test]
+fn test_parse_postfix_operators() {
+    // postfix operators bind tighter than '^', 2^3! is 2^(3!)
+    let parsed_node = parse_str("2^3!");
+    let power_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(power_node.current_node, CfgTerm::NonTermPowerExpr);
+    let postfix_node = &power_node.child_nodes[2];
+    assert_eq!(postfix_node.current_node, CfgTerm::NonTermPostfixExpr);
+    assert_eq!(postfix_node.node_depth, 4);
+    assert_eq!(postfix_node.span, Span::new(2, 4, 1, 3));
+    assert_eq!(postfix_node.child_nodes[0].current_node, term_number("3"));
+    assert_eq!(postfix_node.child_nodes[0].node_depth, 5);
+    assert_eq!(
+        postfix_node.child_nodes[1].current_node,
+        CfgTerm::TermFactorial
+    );
+
+    // they apply from the left, and a '%' between operands is the remainder
+    let parsed_node = parse_str("x!% % 3");
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermModulo);
+    let outer_node = &mde_node.child_nodes[0].child_nodes[0];
+    assert_eq!(outer_node.current_node, CfgTerm::NonTermPostfixExpr);
+    assert_eq!(outer_node.child_nodes[1].current_node, CfgTerm::TermPercent);
+    let inner_node = &outer_node.child_nodes[0];
+    assert_eq!(inner_node.current_node, CfgTerm::NonTermPostfixExpr);
+    assert_eq!(inner_node.node_depth, outer_node.node_depth + 1);
+    assert_eq!(
+        inner_node.child_nodes[1].current_node,
+        CfgTerm::TermFactorial
+    );
+
+    // the operand of a prefix '-' includes the postfix operators
+    let parsed_node = parse_str("-(3)!");
+    let unary_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(unary_node.current_node, CfgTerm::NonTermUnaryExpr);
+    assert_eq!(
+        unary_node.child_nodes[1].current_node,
+        CfgTerm::NonTermPostfixExpr
+    );
+    assert!(matches!(
+        parse_err("!"),
+        ParseError::UnexpectedEndOfInput { .. }
+    ));
+    assert!(matches!(
+        parse_err("3 4!"),
+        ParseError::TrailingInput { .. }
+    ));
+}
+
+#[test]
+fn test_parse_implicit_multiplication() {
+    let options = ParseOptions::new().implicit_multiplication(true);
+
+    // the marker has no width and sits at the start of the second factor
+    let parsed_node = parse_str_with("2(3+4)", options.clone());
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(mde_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(mde_node.span, Span::new(0, 6, 1, 1));
+    let op_node = &mde_node.child_nodes[1];
+    assert_eq!(op_node.current_node, CfgTerm::TermImplicitMultiply);
+    assert_eq!(op_node.span, Span::new(1, 1, 1, 2));
+    assert_eq!(op_node.node_depth, mde_node.node_depth + 1);
+
+    // the implicit product binds tighter than '/', 1/2x is 1/(2x)
+    let parsed_node = parse_str_with("1/2x", options.clone());
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+    assert_eq!(mde_node.child_nodes[1].current_node, CfgTerm::TermDivide);
+    let product_node = &mde_node.child_nodes[2];
+    assert_eq!(product_node.current_node, CfgTerm::NonTermMultiDivExpr);
+    assert_eq!(
+        product_node.child_nodes[1].current_node,
+        CfgTerm::TermImplicitMultiply
+    );
+    assert_eq!(
+        product_node.child_nodes[2].current_node,
+        CfgTerm::TermVariable("x".to_string())
+    );
+
+    // products of several factors group from the left
+    let parsed_node = parse_str_with("(1)(2)(3)", options.clone());
+    let mde_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(
+        mde_node.child_nodes[0].current_node,
+        CfgTerm::NonTermMultiDivExpr
+    );
+    assert_eq!(mde_node.child_nodes[0].span, Span::new(0, 6, 1, 1));
+
+    // a name followed by '(' is still a call, and a '-' still subtracts
+    let parsed_node = parse_str_with("f(2) - 3", options.clone());
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.child_nodes[1].current_node, CfgTerm::TermMinus);
+    assert_eq!(
+        expr_node.child_nodes[0].child_nodes[0].child_nodes[0].current_node,
+        CfgTerm::NonTermCallExpr
+    );
+
+    let my_lex = lexer("2 3").unwrap();
+    let mut math_parser = MathParser::new("2 3", my_lex.get_tokens(), options);
+    assert!(matches!(
+        math_parser.parse(),
+        Err(ParseError::TrailingInput { .. })
+    ));
+    assert!(matches!(parse_err("2x"), ParseError::TrailingInput { .. }));
+}
+
+#[test]
+fn test_parse_conditionals() {
+    let s = "qty > 100 ? price * 0.9 : price";
+    let parsed_node = parse_str(s);
+    let conditional_node = &parsed_node.child_nodes[0];
+    assert_eq!(
+        conditional_node.current_node,
+        CfgTerm::NonTermConditionalExpr
+    );
+    assert_eq!(conditional_node.node_depth, 1);
+    assert_eq!(conditional_node.span, Span::new(0, 31, 1, 1));
+    let kinds: Vec<&CfgTerm> = conditional_node
+        .child_nodes
+        .iter()
+        .map(|n| n.kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::NonTermComparisonExpr,
+            &CfgTerm::TermQuestion,
+            &CfgTerm::NonTermExpr,
+            &CfgTerm::TermColon,
+            &CfgTerm::NonTermExpr
+        ]
+    );
+    assert_eq!(conditional_node.child_nodes[0].node_depth, 2);
+    assert_eq!(conditional_node.child_nodes[0].child_nodes[0].node_depth, 3);
+    assert_eq!(
+        conditional_node.child_nodes[3].span,
+        Span::new(24, 25, 1, 25)
+    );
+    assert_eq!(parsed_node.free_variables(), vec!["qty", "price"]);
+
+    // the branches are conditionals, so they group to the right
+    let parsed_node = parse_str("a ? b : c ? d : e");
+    let else_node = &parsed_node.child_nodes[0].child_nodes[4];
+    assert_eq!(else_node.current_node, CfgTerm::NonTermConditionalExpr);
+    assert_eq!(else_node.node_depth, 2);
+    let parsed_node = parse_str("a ? b ? c : d : e");
+    assert_eq!(
+        parsed_node.child_nodes[0].child_nodes[2].current_node,
+        CfgTerm::NonTermConditionalExpr
+    );
+
+    let parsed_node = parse_str("if a then b else c + 1");
+    let conditional_node = &parsed_node.child_nodes[0];
+    let kinds: Vec<&CfgTerm> = conditional_node
+        .child_nodes
+        .iter()
+        .map(|n| n.kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &CfgTerm::TermIf,
+            &CfgTerm::NonTermExpr,
+            &CfgTerm::TermThen,
+            &CfgTerm::NonTermExpr,
+            &CfgTerm::TermElse,
+            &CfgTerm::NonTermExpr
+        ]
+    );
+    assert_eq!(conditional_node.child_nodes[0].span, Span::new(0, 2, 1, 1));
+    assert_eq!(
+        conditional_node.child_nodes[5].span,
+        Span::new(17, 22, 1, 18)
+    );
+    assert_eq!(
+        parse_str("x = if a then 1 else 2").child_nodes[0].child_nodes[2].current_node,
+        CfgTerm::NonTermConditionalExpr
+    );
+    parse_str("f(x) = x > 0 ? x : -x");
+    parse_str("max(a ? 1 : 2, (if b then 3 else 4) * 2)");
+}
+
+#[test]
+fn test_parse_conditional_errors() {
+    assert_eq!(
+        parse_err("a ? b"),
+        ParseError::UnexpectedEndOfInput {
+            expected: vec!["':'"],
+            after: Some(LexToken::Ident(String::from("b"))),
+            span: Span::new(5, 5, 1, 6),
+        }
+    );
+    assert_eq!(
+        parse_err("if a else b"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Else,
+            expected: vec!["'then'"],
+            after: Some(LexToken::Ident(String::from("a"))),
+            span: Span::new(5, 9, 1, 6),
+        }
+    );
+    assert!(matches!(
+        parse_err("if a then b"),
+        ParseError::UnexpectedEndOfInput { ref expected, .. } if expected == &["'else'"]
+    ));
+    assert!(matches!(
+        parse_err("a ? b : c : d"),
+        ParseError::TrailingInput {
+            found: LexToken::Colon(':'),
+            ..
+        }
+    ));
+    // a conditional inside a larger expression needs parentheses
+    assert!(matches!(
+        parse_err("1 + if a then 2 else 3"),
+        ParseError::UnexpectedToken {
+            found: LexToken::If,
+            ..
+        }
+    ));
+    assert!(matches!(
+        parse_err("a ? : b"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Colon(':'),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_parse_empty_input() {
+    assert_eq!(
+        parse_err(""),
+        ParseError::UnexpectedEndOfInput {
+            expected: expected_operand(true),
+            after: None,
+            span: Span::new(0, 0, 1, 1),
+        }
+    );
+    assert_eq!(
+        parse_err("2 *"),
+        ParseError::UnexpectedEndOfInput {
+            expected: expected_operand(false),
+            after: Some(LexToken::Multi('*')),
+            span: Span::new(3, 3, 1, 4),
+        }
+    );
+    // the 'é' is two bytes but one column
+    let err = parse_err("if \"héllo\"");
+    assert!(matches!(
+        err,
+        ParseError::UnexpectedEndOfInput {
+            after: Some(LexToken::Str(_)),
+            ..
+        }
+    ));
+    assert_eq!(err.span(), Span::new(11, 11, 1, 11));
+}
+
+#[test]
+fn test_parse_error_names_line_break() {
+    let err = parse_err("1\n)");
+    assert!(matches!(
+        err,
+        ParseError::UnexpectedToken {
+            after: Some(LexToken::Newline),
+            ..
+        }
+    ));
+    assert!(err.to_string().ends_with(" or 'if' after newline"));
+}
 
-    #[test]
-    fn test_parens_and_multiply_expr() {
-        let s = "(2 / 3) * 4";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+#[test]
+fn test_parse_unexpected_token() {
+    assert_eq!(
+        parse_err("2 + * 3"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Multi('*'),
+            expected: expected_operand(false),
+            after: Some(LexToken::Add('+')),
+            span: Span::new(4, 5, 1, 5),
         }
+    );
+    assert_eq!(
+        parse_err("(2 3)"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Num(NumLiteral::new("3")),
+            expected: vec!["operator", "')'"],
+            after: Some(LexToken::Num(NumLiteral::new("2"))),
+            span: Span::new(3, 4, 1, 4),
+        }
+    );
+}
+
+#[test]
+fn test_parse_unbalanced_parenthesis() {
+    assert_eq!(
+        parse_err("(2 + 3"),
+        ParseError::UnbalancedParenthesis {
+            paren: '(',
+            span: Span::new(0, 1, 1, 1),
+        }
+    );
+    assert_eq!(
+        parse_err("((2 + 3)"),
+        ParseError::UnbalancedParenthesis {
+            paren: '(',
+            span: Span::new(0, 1, 1, 1),
+        }
+    );
+    assert_eq!(
+        parse_err("2 + 3)"),
+        ParseError::UnbalancedParenthesis {
+            paren: ')',
+            span: Span::new(5, 6, 1, 6),
+        }
+    );
+}
+
+#[test]
+fn test_parse_brackets_and_bars() {
+    // '[' and '{' group like '('
+    let parsed_node = parse_str("[1 + {2}]");
+    let term_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(term_node.current_node, CfgTerm::NonTermTermExpr);
+    assert_eq!(term_node.span, Span::new(0, 9, 1, 1));
+    assert_eq!(
+        term_node.child_nodes[0].current_node,
+        CfgTerm::TermLeftParens
+    );
+    assert_eq!(
+        term_node.child_nodes[2].current_node,
+        CfgTerm::TermRightParens
+    );
+
+    let parsed_node = parse_str("|x - 3|");
+    let abs_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(abs_node.current_node, CfgTerm::NonTermAbsExpr);
+    assert_eq!(abs_node.span, Span::new(0, 7, 1, 1));
+    assert_eq!(abs_node.child_nodes[0].current_node, CfgTerm::TermBar);
+    assert_eq!(abs_node.child_nodes[1].current_node, CfgTerm::NonTermExpr);
+    assert_eq!(abs_node.child_nodes[2].current_node, CfgTerm::TermBar);
+    assert_eq!(abs_node.child_nodes[2].span, Span::new(6, 7, 1, 7));
+
+    // a call and a definition still take '(' only
+    parse_str("f(|x|, [y])");
+    assert!(matches!(
+        parse_err("f[x] = 1"),
+        ParseError::TrailingInput { .. }
+    ));
+}
+
+#[test]
+fn test_parse_bar_opens_or_closes() {
+    // a '||' after an operand inside a bar closes it with its first '|'
+    let parsed_node = parse_str("|x - |y||");
+    let abs_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(abs_node.current_node, CfgTerm::NonTermAbsExpr);
+    assert_eq!(abs_node.span, Span::new(0, 9, 1, 1));
+    assert_eq!(abs_node.child_nodes[2].span, Span::new(8, 9, 1, 9));
+    let inner_abs_node = &abs_node.child_nodes[1].child_nodes[2].child_nodes[0];
+    assert_eq!(inner_abs_node.current_node, CfgTerm::NonTermAbsExpr);
+    assert_eq!(inner_abs_node.span, Span::new(5, 8, 1, 6));
+
+    // after a bar closed that way, with no bar open, the second '|' opens
+    // a factor
+    let options = ParseOptions::new().implicit_multiplication(true);
+    let parsed_node = parse_str_with("|a||b|", options);
+    let product_node = &start_expr_node(&parsed_node).child_nodes[0].child_nodes[0];
+    assert_eq!(product_node.child_nodes[0].span, Span::new(0, 3, 1, 1));
+    assert_eq!(
+        product_node.child_nodes[1].current_node,
+        CfgTerm::TermImplicitMultiply
+    );
+    assert_eq!(product_node.child_nodes[2].span, Span::new(3, 6, 1, 4));
+    assert_eq!(
+        parse_err("|a||b|"),
+        ParseError::TrailingInput {
+            found: LexToken::Bar('|'),
+            span: Span::new(3, 4, 1, 4),
+        }
+    );
+    // inside brackets, '||' is an or
+    parse_str("|(a || b)|");
+
+    // a line break inside bars does not end the statement
+    parse_str("|x\n+ 1|");
+    parse_str("|1\n|");
+    assert_eq!(parse_str("a = |x|\nb = |x%|\nc").child_nodes.len(), 3);
+
+    // a '%' before a '|' that opens a bar is the remainder
+    let modulo = |s: &str| {
+        let parsed_node = parse_str(s);
+        let mde_node = &start_expr_node(&parsed_node).child_nodes[0];
+        mde_node.child_nodes.get(1).map(|n| n.current_node.clone())
+    };
+    assert_eq!(modulo("7 % | - 2|"), Some(CfgTerm::TermModulo));
+    assert_eq!(modulo("7 % |x|"), Some(CfgTerm::TermModulo));
+
+    // how much input there is around a bar does not change what it does
+    for len in [0, 10 * DEFAULT_MAX_NESTING_DEPTH] {
+        let parsed_node = parse_str(&format!("{}|x%| - |y|", "1 + ".repeat(len)));
+        let expr_node = start_expr_node(&parsed_node);
+        assert_eq!(expr_node.child_nodes[1].current_node, CfgTerm::TermMinus);
+        let lhs_node = &expr_node.child_nodes[0];
+        let abs_node = &lhs_node.child_nodes[lhs_node.child_nodes.len() - 1].child_nodes[0];
+        assert_eq!(abs_node.current_node, CfgTerm::NonTermAbsExpr);
+        assert_eq!(
+            abs_node.span,
+            Span::new(4 * len, 4 * len + 4, 1, 4 * len + 1)
+        );
+        let postfix_node = &abs_node.child_nodes[1].child_nodes[0].child_nodes[0];
+        assert_eq!(
+            postfix_node.child_nodes[1].current_node,
+            CfgTerm::TermPercent
+        );
     }
+}
 
-    #[test]
-    fn test_parens_and_parens_expr() {
-        let s = "(2 / 3) / ( 3 / 4)";
-        let my_lex = lexer(s).unwrap();
-        let tokens = my_lex.get_tokens();
-        let mut math_parser = MathParser::new(tokens);
-        let _ = math_parser.parse();
-        match math_parser.parsed_node {
-            Some(parsed_node) => {
-                println!("parsed node: {}", parsed_node);
-                assert_eq!(parsed_node.current_node, CfgTerm::NonTermStartRule);
-                let start_child = parsed_node.current_node;
-                println!("start_child: {}", start_child);
-                assert_eq!(parsed_node.child_nodes.len(), 1);
-            }
-            _ => {
-                println!("Unknown error!");
-            }
+#[test]
+fn test_parse_mismatched_brackets() {
+    assert_eq!(
+        parse_err("(1+2]"),
+        ParseError::MismatchedBracket {
+            open: '(',
+            close: ']',
+            open_span: Span::new(0, 1, 1, 1),
+            span: Span::new(4, 5, 1, 5),
+        }
+    );
+    assert_eq!(
+        parse_err("{[1}]"),
+        ParseError::MismatchedBracket {
+            open: '[',
+            close: '}',
+            open_span: Span::new(1, 2, 1, 2),
+            span: Span::new(3, 4, 1, 4),
         }
+    );
+    // a '|' inside parentheses opened after the bar does not close it
+    assert!(matches!(
+        parse_err("|(1|)"),
+        ParseError::UnexpectedToken {
+            found: LexToken::Bar('|'),
+            ..
+        }
+    ));
+    assert_eq!(
+        parse_err("(|1)"),
+        ParseError::MismatchedBracket {
+            open: '|',
+            close: ')',
+            open_span: Span::new(1, 2, 1, 2),
+            span: Span::new(3, 4, 1, 4),
+        }
+    );
+    assert_eq!(
+        parse_err("max(1, 2}"),
+        ParseError::MismatchedBracket {
+            open: '(',
+            close: '}',
+            open_span: Span::new(3, 4, 1, 4),
+            span: Span::new(8, 9, 1, 9),
+        }
+    );
+    assert_eq!(
+        parse_err("[1 + 2"),
+        ParseError::UnbalancedParenthesis {
+            paren: '[',
+            span: Span::new(0, 1, 1, 1),
+        }
+    );
+    assert_eq!(
+        parse_err("1 + 2}"),
+        ParseError::UnbalancedParenthesis {
+            paren: '}',
+            span: Span::new(5, 6, 1, 6),
+        }
+    );
+    assert!(matches!(
+        parse_err("[1 + 2 3]"),
+        ParseError::UnexpectedToken {
+            expected,
+            ..
+        } if expected == vec!["operator", "']'"]
+    ));
+}
+
+#[test]
+fn test_parse_trailing_input() {
+    assert_eq!(
+        parse_err("2 3"),
+        ParseError::TrailingInput {
+            found: LexToken::Num(NumLiteral::new("3")),
+            span: Span::new(2, 3, 1, 3),
+        }
+    );
+    assert_eq!(
+        parse_err("(2) (3)"),
+        ParseError::TrailingInput {
+            found: LexToken::LeftParen('('),
+            span: Span::new(4, 5, 1, 5),
+        }
+    );
+}
+
+#[test]
+fn test_parse_node_spans() {
+    let s = "(12 / 3) + 4";
+    let parsed_node = parse_str(s);
+    assert_eq!(parsed_node.span, Span::new(0, 12, 1, 1));
+
+    let expr_node = start_expr_node(&parsed_node);
+    assert_eq!(expr_node.span, Span::new(0, 12, 1, 1));
+    assert_eq!(expr_node.child_nodes[1].span, Span::new(9, 10, 1, 10));
+    assert_eq!(expr_node.child_nodes[2].span, Span::new(11, 12, 1, 12));
+
+    // ( 12 / 3 ) sub term
+    let term_expr_node = &expr_node.child_nodes[0].child_nodes[0].child_nodes[0];
+    assert_eq!(term_expr_node.current_node, CfgTerm::NonTermTermExpr);
+    assert_eq!(term_expr_node.span, Span::new(0, 8, 1, 1));
+    assert_eq!(
+        &s[term_expr_node.span.start..term_expr_node.span.end],
+        "(12 / 3)"
+    );
+
+    let sub_expr_node = &term_expr_node.child_nodes[1];
+    assert_eq!(
+        &s[sub_expr_node.span.start..sub_expr_node.span.end],
+        "12 / 3"
+    );
+}
+
+#[test]
+fn test_parse_nesting_too_deep() {
+    let depth = DEFAULT_MAX_NESTING_DEPTH;
+    let s = format!("{}1{}", "(".repeat(depth + 1), ")".repeat(depth + 1));
+    assert!(matches!(
+        parse_err(&s),
+        ParseError::NestingTooDeep { max_depth, .. } if max_depth == DEFAULT_MAX_NESTING_DEPTH
+    ));
+
+    let s = format!("{}1", "-".repeat(depth + 1));
+    assert!(matches!(parse_err(&s), ParseError::NestingTooDeep { .. }));
+
+    let s = vec!["1"; depth + 2].join("^");
+    assert!(matches!(parse_err(&s), ParseError::NestingTooDeep { .. }));
+
+    let s = format!("{}1{}", "abs(".repeat(depth + 1), ")".repeat(depth + 1));
+    assert!(matches!(parse_err(&s), ParseError::NestingTooDeep { .. }));
+
+    let s = format!("{}1", "a ? 1 : ".repeat(depth + 1));
+    assert!(matches!(parse_err(&s), ParseError::NestingTooDeep { .. }));
+
+    let s = format!("{}a", "if ".repeat(depth + 1));
+    assert!(matches!(parse_err(&s), ParseError::NestingTooDeep { .. }));
+
+    // right at the limit
+    let s = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    parse_str(&s);
+    let s = format!("{}1", "-".repeat(depth));
+    parse_str(&s);
+    let s = vec!["1"; depth + 1].join("^");
+    parse_str(&s);
+    let s = format!("{}1{}", "abs(".repeat(depth), ")".repeat(depth));
+    parse_str(&s);
+    let s = format!("{}1", "if a then 1 else ".repeat(depth));
+    parse_str(&s);
+}
+
+#[test]
+fn test_parse_max_nesting_depth() {
+    let else_ifs = |n: usize| format!("{}1", "if a then 1 else ".repeat(n));
+    let exponents = |n: usize| vec!["2"; n + 1].join("^");
+    let options = ParseOptions::new().max_nesting_depth(80);
+    for s in [else_ifs(80), exponents(80)] {
+        parse_str_with(&s, options.clone());
+        assert!(matches!(
+            parse_str_err(&s, ParseOptions::default()),
+            ParseError::NestingTooDeep { max_depth, .. } if max_depth == DEFAULT_MAX_NESTING_DEPTH
+        ));
     }
+    for s in [else_ifs(81), exponents(81)] {
+        assert!(matches!(
+            parse_str_err(&s, options.clone()),
+            ParseError::NestingTooDeep { max_depth: 80, .. }
+        ));
+    }
+
+    let options = ParseOptions::new().max_nesting_depth(1);
+    parse_str_with("(1 + 2) * -3", options.clone());
+    assert!(matches!(
+        parse_str_err("-(1)", options),
+        ParseError::NestingTooDeep { max_depth: 1, .. }
+    ));
+}
+
+#[test]
+fn test_parse_long_chains() {
+    // a chain of left associative operators does not nest, however long
+    let len = 10 * DEFAULT_MAX_NESTING_DEPTH;
+    let parsed_node = parse_str(&vec!["1"; len].join(" + "));
+    let mut expr_node = start_expr_node(&parsed_node);
+    for depth in 1..len {
+        assert_eq!(expr_node.current_node, CfgTerm::NonTermExpr);
+        assert_eq!(expr_node.node_depth, depth);
+        assert_eq!(expr_node.child_nodes[2].node_depth, depth + 1);
+        expr_node = &expr_node.child_nodes[0];
+    }
+    assert_eq!(expr_node.child_nodes.len(), 1);
+
+    parse_str(&vec!["2"; len].join(" * "));
+    parse_str(&vec!["true"; len].join(" && "));
+    parse_str(&vec!["false"; len].join(" || "));
+    parse_str(&format!("1{}", "!".repeat(len)));
+    parse_str_with(
+        &vec!["x"; len].join(" "),
+        ParseOptions::new().implicit_multiplication(true),
+    );
+    let s = format!(
+        "{}1{}",
+        "(1 + ".repeat(DEFAULT_MAX_NESTING_DEPTH),
+        ")".repeat(DEFAULT_MAX_NESTING_DEPTH)
+    );
+    parse_str(&s);
 }
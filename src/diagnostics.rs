@@ -0,0 +1,492 @@
+// Render lexer and parser errors against the source they came from
+use std::fmt::Write;
+
+use crate::ast::LowerError;
+use crate::cfg::{describe_token, is_opening_bracket, matching_bracket, ParseError};
+use crate::eval::EvalError;
+use crate::lex::{LexError, Span};
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOLD_RED: &str = "\x1b[1;31m";
+const ANSI_BOLD_BLUE: &str = "\x1b[1;34m";
+const ANSI_BOLD: &str = "\x1b[1m";
+
+/// Whether a diagnostic is rendered as plain text or with ANSI colour codes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Plain,
+    Ansi,
+}
+
+impl Style {
+    fn paint(&self, colour: &str, s: &str) -> String {
+        match self {
+            Style::Plain => s.to_string(),
+            Style::Ansi => format!("{colour}{s}{ANSI_RESET}"),
+        }
+    }
+}
+
+/// An error ready to be shown to the user: an error code, a one line
+/// message, and an optional label printed next to the underlined span
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>,
+    /// another part of the input the error refers to, underlined with '-'
+    /// and printed with its own label
+    pub secondary: Option<(Span, String)>,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic in the style of
+    ///
+    /// ```text
+    /// error[E0101]: unexpected token '*'
+    ///  --> 1:5
+    ///   |
+    /// 1 | 2 + * 3
+    ///   |     ^ expected number, variable, function call, string, boolean, '(', '[', '{', '|', '-', '+' or '!' after '+'
+    /// ```
+    ///
+    /// A secondary span is underlined with '-' below its own line, which is
+    /// only printed once when both spans are on the same line.
+    pub fn render(&self, source: &str, style: Style) -> String {
+        let mut out = String::new();
+        let mut markers = vec![(self.span, '^', ANSI_BOLD_RED, self.label.as_deref())];
+        if let Some((span, label)) = &self.secondary {
+            markers.push((*span, '-', ANSI_BOLD_BLUE, Some(label.as_str())));
+        }
+        markers.sort_by_key(|(span, ..)| span.line);
+        let width = markers
+            .iter()
+            .map(|(span, ..)| span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        let bar = style.paint(ANSI_BOLD_BLUE, "|");
+
+        let _ = writeln!(
+            out,
+            "{}{}",
+            style.paint(ANSI_BOLD_RED, &format!("error[{}]", self.code)),
+            style.paint(ANSI_BOLD, &format!(": {}", self.message))
+        );
+        let _ = writeln!(
+            out,
+            "{gutter}{} {}:{}",
+            style.paint(ANSI_BOLD_BLUE, "-->"),
+            self.span.line,
+            self.span.column
+        );
+        let _ = writeln!(out, "{gutter} {bar}");
+
+        let mut printed_line = None;
+        for (span, underline_char, colour, label) in markers {
+            let source_line = source
+                .split('\n')
+                .nth(span.line.saturating_sub(1))
+                .unwrap_or("")
+                .trim_end_matches('\r');
+            if printed_line != Some(span.line) {
+                let _ = writeln!(
+                    out,
+                    "{} {bar} {}",
+                    style.paint(ANSI_BOLD_BLUE, &format!("{:>width$}", span.line)),
+                    source_line
+                );
+                printed_line = Some(span.line);
+            }
+
+            // keep tabs in the indent so the carets line up with the source
+            // line
+            let indent: String = source_line
+                .chars()
+                .take(span.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let underline = underline_char
+                .to_string()
+                .repeat(underline_width(span, source_line));
+            let mut marker = style.paint(colour, &underline);
+            if let Some(label) = label {
+                marker.push(' ');
+                marker.push_str(&style.paint(colour, label));
+            }
+            let _ = writeln!(out, "{gutter} {bar} {indent}{marker}");
+        }
+
+        out
+    }
+}
+
+/// number of characters to underline, at least one so that errors at the
+/// end of the input still get a caret, and never past the end of the line
+fn underline_width(span: Span, source_line: &str) -> usize {
+    let line_start = span.column.saturating_sub(1);
+    let line_rest: String = source_line.chars().skip(line_start).collect();
+    let span_len = span.end.saturating_sub(span.start);
+    let width = line_rest
+        .char_indices()
+        .take_while(|(offset, _)| *offset < span_len)
+        .count();
+    width.max(1)
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Self {
+        let (code, message, label) = match err {
+            LexError::InvalidCharacter { c, .. } => (
+                "E0001",
+                format!("invalid character '{}'", c),
+                "not a number, operator or parenthesis".to_string(),
+            ),
+            LexError::InvalidNumber { reason, .. } => {
+                ("E0002", String::from("invalid number"), reason.clone())
+            }
+            LexError::InvalidString { reason, .. } => {
+                ("E0003", String::from("invalid string"), reason.clone())
+            }
+        };
+        Diagnostic {
+            code,
+            message,
+            span: err.span(),
+            label: Some(label),
+            secondary: None,
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        let (code, message, label) = match err {
+            ParseError::UnexpectedToken { found, .. } => (
+                "E0101",
+                format!("unexpected token {}", describe_token(found)),
+                err.expected_message(),
+            ),
+            ParseError::UnexpectedEndOfInput { .. } => (
+                "E0102",
+                String::from("unexpected end of input"),
+                err.expected_message(),
+            ),
+            ParseError::UnbalancedParenthesis { paren, .. } if is_opening_bracket(*paren) => (
+                "E0103",
+                String::from("unbalanced parenthesis"),
+                Some(format!("this '{}' is never closed", paren)),
+            ),
+            ParseError::UnbalancedParenthesis { paren, .. } => (
+                "E0103",
+                String::from("unbalanced parenthesis"),
+                Some(format!(
+                    "this '{}' has no matching '{}'",
+                    paren,
+                    matching_bracket(*paren)
+                )),
+            ),
+            ParseError::MismatchedBracket { open, close, .. } => (
+                "E0108",
+                String::from("mismatched brackets"),
+                Some(format!(
+                    "expected '{}', found '{}'",
+                    matching_bracket(*open),
+                    close
+                )),
+            ),
+            ParseError::TrailingInput { found, .. } => (
+                "E0104",
+                format!("unexpected trailing input {}", describe_token(found)),
+                Some(String::from("expected end of input")),
+            ),
+            ParseError::NestingTooDeep { max_depth, .. } => (
+                "E0105",
+                String::from("expression is nested too deeply"),
+                Some(format!("more than {} levels of nesting here", max_depth)),
+            ),
+            ParseError::DuplicateParameter { name, .. } => (
+                "E0106",
+                format!("parameter '{}' is repeated", name),
+                Some(String::from("already a parameter of this function")),
+            ),
+            ParseError::ChainedComparison { .. } => (
+                "E0107",
+                String::from("comparison operators cannot be chained"),
+                Some(String::from(
+                    "join the comparisons with '&&', as in `a < b && b < c`",
+                )),
+            ),
+        };
+        // a mismatched bracket also points at the bracket it fails to close
+        let secondary = match err {
+            ParseError::MismatchedBracket {
+                open, open_span, ..
+            } => Some((*open_span, format!("to close this '{}'", open))),
+            _ => None,
+        };
+        Diagnostic {
+            code,
+            message,
+            span: err.span(),
+            label,
+            secondary,
+        }
+    }
+}
+
+impl From<&LowerError> for Diagnostic {
+    fn from(err: &LowerError) -> Self {
+        Diagnostic {
+            code: "E0301",
+            message: String::from("malformed parse tree"),
+            span: err.span,
+            label: Some(err.message.clone()),
+            secondary: None,
+        }
+    }
+}
+
+impl From<&EvalError> for Diagnostic {
+    fn from(err: &EvalError) -> Self {
+        let (code, message, label) = match err {
+            EvalError::DivisionByZero { .. } => (
+                "E0201",
+                String::from("division by zero"),
+                String::from("this divisor is zero"),
+            ),
+            EvalError::Overflow { .. } => (
+                "E0202",
+                String::from("arithmetic overflow"),
+                String::from("the result is out of range"),
+            ),
+            EvalError::Undefined { .. } => (
+                "E0203",
+                String::from("undefined result"),
+                String::from("the result is not a real number"),
+            ),
+            EvalError::InvalidFactorial { .. } => (
+                "E0211",
+                String::from("invalid factorial"),
+                String::from("factorials are only defined for non-negative integers"),
+            ),
+            EvalError::UnboundVariable { names, .. } => {
+                let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+                let message = match quoted.as_slice() {
+                    [name] => format!("unbound variable {}", name),
+                    _ => format!("unbound variables {}", quoted.join(", ")),
+                };
+                ("E0204", message, String::from("this variable has no value"))
+            }
+            EvalError::UnknownFunction { name, .. } => (
+                "E0205",
+                format!("unknown function '{}'", name),
+                String::from("no function has this name"),
+            ),
+            EvalError::WrongArgumentCount {
+                name,
+                expected,
+                found,
+                ..
+            } => (
+                "E0206",
+                format!("wrong number of arguments to '{}'", name),
+                format!("expected {}, found {}", expected, found),
+            ),
+            EvalError::TypeMismatch {
+                expected, found, ..
+            } => (
+                "E0207",
+                String::from("type mismatch"),
+                format!("expected a {}, found a {}", expected, found),
+            ),
+            EvalError::Custom { message, .. } => {
+                ("E0208", message.clone(), String::from("in this call"))
+            }
+            EvalError::CallDepthExceeded {
+                name, max_depth, ..
+            } => (
+                "E0209",
+                format!("calls to '{}' nest too deeply", name),
+                format!("this call nests more than {} calls deep", max_depth),
+            ),
+            EvalError::NoValue { .. } => (
+                "E0210",
+                String::from("no value"),
+                String::from("a function definition has no value"),
+            ),
+        };
+        Diagnostic {
+            code,
+            message,
+            span: err.span(),
+            label: Some(label),
+            secondary: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::mathparser::MathParser;
+    use crate::cfg::ParseOptions;
+    use crate::lex::{lex_multi_digit, simple};
+
+    fn parse_diagnostic(s: &str) -> Diagnostic {
+        let my_lex = lex_multi_digit::lexer(s).unwrap();
+        let mut math_parser = MathParser::new(s, my_lex.get_tokens(), ParseOptions::default());
+        let err = math_parser.parse().unwrap_err();
+        Diagnostic::from(&err)
+    }
+
+    #[test]
+    fn test_render_unexpected_token() {
+        let s = "2 + * 3";
+        let rendered = parse_diagnostic(s).render(s, Style::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0101]: unexpected token '*'\n \
+             --> 1:5\n  \
+             |\n\
+             1 | 2 + * 3\n  \
+             |     ^ expected number, variable, function call, string, boolean, '(', '[', '{', '|', '-', '+' or '!' after '+'\n"
+        );
+    }
+
+    #[test]
+    fn test_render_end_of_input() {
+        let s = "(12 +";
+        let rendered = parse_diagnostic(s).render(s, Style::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0102]: unexpected end of input\n \
+             --> 1:6\n  \
+             |\n\
+             1 | (12 +\n  \
+             |      ^ expected number, variable, function call, string, boolean, '(', '[', '{', '|', '-', '+' or '!' after '+'\n"
+        );
+    }
+
+    #[test]
+    fn test_render_underlines_whole_span() {
+        let s = "1 + 99999999999999999999";
+        let err = crate::eval(s).unwrap_err();
+        let rendered = err.diagnostic().render(s, Style::Plain);
+        assert!(rendered.starts_with("error[E0202]: arithmetic overflow\n"));
+        assert!(rendered.ends_with(
+            "1 | 1 + 99999999999999999999\n  |     ^^^^^^^^^^^^^^^^^^^^ the result is out of range\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_second_line() {
+        let s = "1 +\n\t2 $ 3";
+        let err = lex_multi_digit::lexer(s).unwrap_err();
+        let rendered = Diagnostic::from(&err).render(s, Style::Plain);
+        assert!(rendered.contains(" --> 2:4\n"));
+        assert!(rendered.contains("2 | \t2 $ 3\n  | \t  ^ "));
+    }
+
+    #[test]
+    fn test_simple_lexer_diagnostic() {
+        let s = "(2 $ 3)";
+        let err = simple::lexer(s).unwrap_err();
+        let diagnostic = Diagnostic::from(&err);
+        assert_eq!(diagnostic.code, "E0001");
+        assert_eq!(diagnostic.span, Span::new(3, 4, 1, 4));
+        assert!(diagnostic
+            .render(s, Style::Plain)
+            .contains("1 | (2 $ 3)\n  |    ^ "));
+    }
+
+    #[test]
+    fn test_render_unbalanced_parenthesis() {
+        let s = "(1 + 2";
+        let diagnostic = parse_diagnostic(s);
+        assert_eq!(diagnostic.code, "E0103");
+        assert!(diagnostic
+            .render(s, Style::Plain)
+            .ends_with("  | ^ this '(' is never closed\n"));
+    }
+
+    #[test]
+    fn test_render_mismatched_brackets() {
+        let s = "2 * (1 + 2]";
+        let rendered = parse_diagnostic(s).render(s, Style::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0108]: mismatched brackets\n \
+             --> 1:11\n  \
+             |\n\
+             1 | 2 * (1 + 2]\n  \
+             |           ^ expected ')', found ']'\n  \
+             |     - to close this '('\n"
+        );
+
+        // the line of the opening bracket is printed first
+        let s = "x = [1 +\n\n\t2}";
+        let rendered = parse_diagnostic(s).render(s, Style::Plain);
+        assert!(rendered.ends_with(
+            " --> 3:3\n  \
+             |\n\
+             1 | x = [1 +\n  \
+             |     - to close this '['\n\
+             3 | \t2}\n  \
+             | \t ^ expected ']', found '}'\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_eval_error() {
+        let s = "6 / (3 - 3)";
+        let err = crate::eval(s).unwrap_err();
+        let rendered = err.diagnostic().render(s, Style::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0201]: division by zero\n \
+             --> 1:6\n  \
+             |\n\
+             1 | 6 / (3 - 3)\n  \
+             |      ^^^^^ this divisor is zero\n"
+        );
+    }
+
+    #[test]
+    fn test_render_unbound_variables() {
+        let s = "price * qty + shipping";
+        let env = crate::Environment::new().with("qty", crate::Number::Int(2));
+        let err = crate::eval_in(s, &env, &crate::EvalOptions::new()).unwrap_err();
+        let rendered = err.diagnostic().render(s, Style::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0204]: unbound variables 'price', 'shipping'\n \
+             --> 1:1\n  \
+             |\n\
+             1 | price * qty + shipping\n  \
+             | ^^^^^ this variable has no value\n"
+        );
+    }
+
+    #[test]
+    fn test_render_wrong_argument_count() {
+        let s = "1 + log(1, 2, 3)";
+        let err = crate::eval(s).unwrap_err();
+        assert!(err.diagnostic().render(s, Style::Plain).ends_with(
+            "1 | 1 + log(1, 2, 3)\n  |     ^^^^^^^^^^^^ expected 1 or 2 arguments, found 3\n"
+        ));
+        assert_eq!(
+            err.diagnostic().message,
+            "wrong number of arguments to 'log'"
+        );
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let s = "2 3";
+        let rendered = parse_diagnostic(s).render(s, Style::Ansi);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0104]\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m \x1b[1;31mexpected end of input\x1b[0m"));
+    }
+}
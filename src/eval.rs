@@ -0,0 +1,2365 @@
+// Evaluate an expression to a value
+mod builtins;
+mod environment;
+mod fraction;
+mod function;
+mod number;
+mod value;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+
+use crate::ast::{BinOp, CompareOp, Expr, LogicOp, PostfixOp, Program, Statement};
+use crate::lex::{number::NumLiteral, Span};
+use builtins::{abs, Builtin};
+
+pub use environment::Environment;
+pub use fraction::Fraction;
+pub use function::{Arity, Function, NativeFunction, UserFunction};
+pub use number::Number;
+pub use value::Value;
+
+/// Errors found while evaluating an expression
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EvalError {
+    /// `span` covers the divisor, or the base of a power of zero with a
+    /// negative exponent
+    DivisionByZero { span: Span },
+    /// `span` covers the operation (or number literal) that overflowed
+    Overflow { span: Span },
+    /// the result is not a real number, such as `(-8) ^ 0.5`. `span` covers
+    /// the operation
+    Undefined { span: Span },
+    /// the factorial of a negative number or one that is not an integer.
+    /// `span` covers the operand
+    InvalidFactorial { span: Span },
+    /// variables with no value in the environment, each listed once in the
+    /// order they appear. `span` covers the first use of the first of them
+    UnboundVariable { names: Vec<String>, span: Span },
+    /// a call to a function that does not exist, `span` covers its name
+    UnknownFunction { name: String, span: Span },
+    /// a call with a number of arguments the function does not take,
+    /// `span` covers the call
+    WrongArgumentCount {
+        name: String,
+        expected: Arity,
+        found: usize,
+        span: Span,
+    },
+    /// a value of the wrong type, such as a boolean in arithmetic or a
+    /// number compared with a string. `span` covers the value, or the call
+    /// for an argument a function rejected
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+        span: Span,
+    },
+    /// an error a [`Function`] reported, see [`EvalError::custom`]. `span`
+    /// covers the call
+    Custom { message: String, span: Span },
+    /// calls to defined functions nested deeper than
+    /// [`EvalOptions::max_call_depth`], usually a recursion that never
+    /// ends. `span` covers the outermost call
+    CallDepthExceeded {
+        name: String,
+        max_depth: usize,
+        span: Span,
+    },
+    /// a program that ends with a function definition evaluated for its
+    /// value, `span` covers the definition
+    NoValue { span: Span },
+}
+
+impl EvalError {
+    /// the part of the input where the error was found
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::DivisionByZero { span }
+            | EvalError::Overflow { span }
+            | EvalError::Undefined { span }
+            | EvalError::InvalidFactorial { span }
+            | EvalError::UnboundVariable { span, .. }
+            | EvalError::UnknownFunction { span, .. }
+            | EvalError::WrongArgumentCount { span, .. }
+            | EvalError::TypeMismatch { span, .. }
+            | EvalError::Custom { span, .. }
+            | EvalError::CallDepthExceeded { span, .. }
+            | EvalError::NoValue { span } => *span,
+        }
+    }
+
+    /// an error for a [`Function`] to return when it cannot compute a
+    /// result, such as an unknown currency. The evaluator points it at the
+    /// call.
+    pub fn custom(message: impl Into<String>) -> Self {
+        EvalError::Custom {
+            message: message.into(),
+            span: Span::default(),
+        }
+    }
+
+    /// the error with its span set to `span`, unless it already has one
+    fn or_span(self, span: Span) -> Self {
+        if self.span() == Span::default() {
+            self.at(span)
+        } else {
+            self
+        }
+    }
+
+    /// the error with its span set to `span`
+    fn at(mut self, span: Span) -> Self {
+        match &mut self {
+            EvalError::DivisionByZero { span: own_span }
+            | EvalError::Overflow { span: own_span }
+            | EvalError::Undefined { span: own_span }
+            | EvalError::InvalidFactorial { span: own_span }
+            | EvalError::UnboundVariable { span: own_span, .. }
+            | EvalError::UnknownFunction { span: own_span, .. }
+            | EvalError::WrongArgumentCount { span: own_span, .. }
+            | EvalError::TypeMismatch { span: own_span, .. }
+            | EvalError::Custom { span: own_span, .. }
+            | EvalError::CallDepthExceeded { span: own_span, .. }
+            | EvalError::NoValue { span: own_span } => *own_span = span,
+        }
+        self
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero { .. } => write!(f, "Division by zero"),
+            EvalError::Overflow { .. } => write!(f, "Arithmetic overflow"),
+            EvalError::Undefined { .. } => write!(f, "Result is not a real number"),
+            EvalError::InvalidFactorial { .. } => {
+                write!(f, "Factorial of a negative or fractional number")
+            }
+            EvalError::UnboundVariable { names, .. } if names.len() == 1 => {
+                write!(f, "Unbound variable: {}", names[0])
+            }
+            EvalError::UnboundVariable { names, .. } => {
+                write!(f, "Unbound variables: {}", names.join(", "))
+            }
+            EvalError::UnknownFunction { name, .. } => write!(f, "Unknown function: {}", name),
+            EvalError::WrongArgumentCount {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "Wrong number of arguments to {}: expected {}, found {}",
+                name, expected, found
+            ),
+            EvalError::TypeMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "Type mismatch: expected a {}, found a {}",
+                expected, found
+            ),
+            EvalError::Custom { message, .. } => write!(f, "{}", message),
+            EvalError::CallDepthExceeded {
+                name, max_depth, ..
+            } => write!(
+                f,
+                "Call depth exceeded: calls to {} nest deeper than {}",
+                name, max_depth
+            ),
+            EvalError::NoValue { .. } => {
+                write!(f, "No value: the program ends with a function definition")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// How `//` and `%` round when the quotient is negative. Both operators
+/// always agree, so that `(a // b) * b + a % b == a`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DivisionRounding {
+    /// round the quotient towards negative infinity, the remainder takes the
+    /// sign of the divisor: `-7 // 2 == -4`, `-7 % 2 == 1`
+    #[default]
+    Floor,
+    /// round the quotient towards zero, the remainder takes the sign of the
+    /// dividend: `-7 // 2 == -3`, `-7 % 2 == -1`
+    Truncate,
+}
+
+/// The number type integer arithmetic is done in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EvalMode {
+    /// i64, an integer result out of its range is an overflow error
+    #[default]
+    Standard,
+    /// integers of any size, `2^200` is exact. Results are still limited to
+    /// [`MAX_BIG_INT_BITS`] bits so that a single `^` cannot exhaust memory.
+    BigInt,
+    /// exact fractions of unbounded integers, so `1/3*3` is exactly 1 and
+    /// `0.1 + 0.2` is exactly 3/10. Only a power with a fractional exponent
+    /// falls back to floating point. Numerators and denominators are
+    /// limited to [`MAX_BIG_INT_BITS`] bits.
+    Rational,
+}
+
+/// Largest integer, in bits, that [`EvalMode::BigInt`] and
+/// [`EvalMode::Rational`] compute before reporting an overflow
+pub const MAX_BIG_INT_BITS: u64 = 1 << 20;
+
+/// Default for [`EvalOptions::max_call_depth`]
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+/// Settings for [`evaluate_with`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct EvalOptions {
+    pub division_rounding: DivisionRounding,
+    pub mode: EvalMode,
+    /// how deeply calls to defined functions may nest, so that a recursion
+    /// that never ends is an error rather than using up memory. Evaluation
+    /// does not recurse, so this may be as large as memory allows.
+    pub max_call_depth: usize,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            division_rounding: DivisionRounding::default(),
+            mode: EvalMode::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+}
+
+impl EvalOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets how deeply calls to defined functions may nest
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// sets how `//` and `%` round negative quotients
+    pub fn division_rounding(mut self, rounding: DivisionRounding) -> Self {
+        self.division_rounding = rounding;
+        self
+    }
+
+    /// sets the number type integer arithmetic is done in
+    pub fn mode(mut self, mode: EvalMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// applies the binary operator to two integers, using checked arithmetic so
+/// that overflow is reported as an error. Division and negative powers fall
+/// back to floating point when the result is not an integer.
+fn apply_int_op(
+    op: BinOp,
+    lhs: i64,
+    rhs: i64,
+    expr: &Expr,
+    options: &EvalOptions,
+) -> Result<Number, EvalError> {
+    let overflow = || EvalError::Overflow { span: expr.span() };
+    let value = match op {
+        BinOp::Add => lhs.checked_add(rhs),
+        BinOp::Sub => lhs.checked_sub(rhs),
+        BinOp::Mul | BinOp::ImplicitMul => lhs.checked_mul(rhs),
+        BinOp::Div => {
+            // i64::MIN % -1 wraps to the correct 0, the division itself is
+            // checked below
+            if lhs.wrapping_rem(rhs) != 0 {
+                return Ok(Number::Float(lhs as f64 / rhs as f64));
+            }
+            lhs.checked_div(rhs)
+        }
+        BinOp::IntDiv => {
+            let quotient = lhs.checked_div(rhs).ok_or_else(overflow)?;
+            match options.division_rounding {
+                DivisionRounding::Truncate => Some(quotient),
+                // the truncated quotient is one too large when it is
+                // negative and not exact
+                DivisionRounding::Floor if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) => {
+                    Some(quotient - 1)
+                }
+                DivisionRounding::Floor => Some(quotient),
+            }
+        }
+        BinOp::Rem => {
+            // the only overflowing case, i64::MIN % -1, wraps to the correct 0
+            let remainder = lhs.wrapping_rem(rhs);
+            match options.division_rounding {
+                DivisionRounding::Truncate => Some(remainder),
+                DivisionRounding::Floor if remainder != 0 && (remainder < 0) != (rhs < 0) => {
+                    Some(remainder + rhs)
+                }
+                DivisionRounding::Floor => Some(remainder),
+            }
+        }
+        BinOp::Pow => match lhs {
+            // the only bases whose powers fit however large the exponent
+            0 | 1 if rhs >= 0 => Some(if rhs == 0 { 1 } else { lhs }),
+            -1 => Some(if rhs % 2 == 0 { 1 } else { -1 }),
+            _ if rhs < 0 => return Ok(Number::Float((lhs as f64).powf(rhs as f64))),
+            _ => u32::try_from(rhs).ok().and_then(|exp| lhs.checked_pow(exp)),
+        },
+    };
+    value.map(Number::Int).ok_or_else(overflow)
+}
+
+fn big_int_to_f64(n: &BigInt) -> f64 {
+    n.to_f64().unwrap_or(f64::NAN)
+}
+
+/// applies the binary operator to two integers of any size. As with
+/// `apply_int_op`, division and negative powers fall back to floating point
+/// when the result is not an integer.
+fn apply_big_int_op(
+    op: BinOp,
+    lhs: BigInt,
+    rhs: BigInt,
+    expr: &Expr,
+    options: &EvalOptions,
+) -> Result<Number, EvalError> {
+    let value = match op {
+        BinOp::Add => lhs + rhs,
+        BinOp::Sub => lhs - rhs,
+        BinOp::Mul | BinOp::ImplicitMul => lhs * rhs,
+        BinOp::Div => {
+            let (quotient, remainder) = lhs.div_rem(&rhs);
+            if !remainder.is_zero() {
+                return apply_float_op(
+                    op,
+                    big_int_to_f64(&lhs),
+                    big_int_to_f64(&rhs),
+                    expr,
+                    options,
+                );
+            }
+            quotient
+        }
+        BinOp::IntDiv => match options.division_rounding {
+            DivisionRounding::Floor => lhs.div_floor(&rhs),
+            DivisionRounding::Truncate => lhs / rhs,
+        },
+        BinOp::Rem => match options.division_rounding {
+            DivisionRounding::Floor => lhs.mod_floor(&rhs),
+            DivisionRounding::Truncate => lhs % rhs,
+        },
+        BinOp::Pow if rhs.is_negative() => {
+            return apply_float_op(
+                op,
+                big_int_to_f64(&lhs),
+                big_int_to_f64(&rhs),
+                expr,
+                options,
+            );
+        }
+        BinOp::Pow if lhs.is_zero() || lhs.is_one() || rhs.is_zero() => {
+            if rhs.is_zero() {
+                BigInt::one()
+            } else {
+                lhs
+            }
+        }
+        BinOp::Pow if lhs == -BigInt::one() => {
+            if rhs.is_even() {
+                BigInt::one()
+            } else {
+                lhs
+            }
+        }
+        BinOp::Pow => {
+            // |lhs| >= 2 here, so the result has at least `exponent` bits
+            let exponent = u64::try_from(&rhs)
+                .ok()
+                .filter(|exponent| lhs.bits().saturating_mul(*exponent) <= MAX_BIG_INT_BITS)
+                .ok_or(EvalError::Overflow { span: expr.span() })?;
+            lhs.pow(exponent)
+        }
+    };
+    if value.bits() > MAX_BIG_INT_BITS {
+        return Err(EvalError::Overflow { span: expr.span() });
+    }
+    Ok(Number::BigInt(value))
+}
+
+/// applies the binary operator to two exact fractions
+fn apply_rational_op(
+    op: BinOp,
+    lhs: &BigRational,
+    rhs: &BigRational,
+    expr: &Expr,
+    options: &EvalOptions,
+) -> Result<Number, EvalError> {
+    let overflow = || EvalError::Overflow { span: expr.span() };
+    let int_div = || match options.division_rounding {
+        DivisionRounding::Floor => (lhs / rhs).floor(),
+        DivisionRounding::Truncate => (lhs / rhs).trunc(),
+    };
+    let value = match op {
+        BinOp::Add => lhs + rhs,
+        BinOp::Sub => lhs - rhs,
+        BinOp::Mul | BinOp::ImplicitMul => lhs * rhs,
+        BinOp::Div => lhs / rhs,
+        BinOp::IntDiv => int_div(),
+        BinOp::Rem => lhs - rhs * int_div(),
+        BinOp::Pow if !rhs.is_integer() => {
+            return apply_float_op(
+                op,
+                Fraction::from(lhs.clone()).to_f64(),
+                Fraction::from(rhs.clone()).to_f64(),
+                expr,
+                options,
+            );
+        }
+        BinOp::Pow => {
+            let exponent = rhs.to_integer();
+            let bits = lhs.numer().bits().max(lhs.denom().bits());
+            // powers of 0, 1 and -1 never grow, whatever the exponent
+            let power = if bits <= 1 {
+                if exponent.is_zero() {
+                    BigRational::one()
+                } else if exponent.is_even() {
+                    lhs.abs()
+                } else {
+                    lhs.clone()
+                }
+            } else {
+                let exponent_abs = u64::try_from(exponent.abs())
+                    .ok()
+                    .filter(|exponent| bits.saturating_mul(*exponent) <= MAX_BIG_INT_BITS)
+                    .ok_or_else(overflow)?;
+                Pow::pow(lhs, exponent_abs)
+            };
+            if exponent.is_negative() {
+                power.recip()
+            } else {
+                power
+            }
+        }
+    };
+    if value.numer().bits() > MAX_BIG_INT_BITS || value.denom().bits() > MAX_BIG_INT_BITS {
+        return Err(overflow());
+    }
+    Ok(Number::Rational(Fraction::from(value)))
+}
+
+/// applies the binary operator in floating point, reporting infinite and NaN
+/// results as errors rather than returning them
+fn apply_float_op(
+    op: BinOp,
+    lhs: f64,
+    rhs: f64,
+    expr: &Expr,
+    options: &EvalOptions,
+) -> Result<Number, EvalError> {
+    let value = match op {
+        BinOp::Add => lhs + rhs,
+        BinOp::Sub => lhs - rhs,
+        BinOp::Mul | BinOp::ImplicitMul => lhs * rhs,
+        BinOp::Div => lhs / rhs,
+        BinOp::IntDiv => match options.division_rounding {
+            DivisionRounding::Floor => (lhs / rhs).floor(),
+            DivisionRounding::Truncate => (lhs / rhs).trunc(),
+        },
+        BinOp::Rem => {
+            let remainder = lhs % rhs;
+            match options.division_rounding {
+                DivisionRounding::Floor if remainder != 0.0 && (remainder < 0.0) != (rhs < 0.0) => {
+                    remainder + rhs
+                }
+                _ => remainder,
+            }
+        }
+        BinOp::Pow => lhs.powf(rhs),
+    };
+    if value.is_nan() {
+        return Err(EvalError::Undefined { span: expr.span() });
+    }
+    if value.is_infinite() {
+        return Err(EvalError::Overflow { span: expr.span() });
+    }
+    Ok(Number::Float(value))
+}
+
+/// applies the binary operator to the operands, in integer arithmetic when
+/// both are integers
+fn apply_op(
+    op: BinOp,
+    lhs: Number,
+    rhs: Number,
+    expr: &Expr,
+    lhs_expr: &Expr,
+    rhs_expr: &Expr,
+    options: &EvalOptions,
+) -> Result<Number, EvalError> {
+    match op {
+        BinOp::Div | BinOp::IntDiv | BinOp::Rem if rhs.is_zero() => {
+            return Err(EvalError::DivisionByZero {
+                span: rhs_expr.span(),
+            });
+        }
+        BinOp::Pow if lhs.is_zero() && rhs.to_f64() < 0.0 => {
+            return Err(EvalError::DivisionByZero {
+                span: lhs_expr.span(),
+            });
+        }
+        _ => {}
+    }
+    match (lhs, rhs) {
+        (Number::Int(lhs), Number::Int(rhs)) => apply_int_op(op, lhs, rhs, expr, options),
+        (Number::BigInt(lhs), Number::BigInt(rhs)) => apply_big_int_op(op, lhs, rhs, expr, options),
+        (Number::Rational(lhs), Number::Rational(rhs)) => apply_rational_op(
+            op,
+            lhs.as_big_rational(),
+            rhs.as_big_rational(),
+            expr,
+            options,
+        ),
+        (lhs, rhs) => apply_float_op(op, lhs.to_f64(), rhs.to_f64(), expr, options),
+    }
+}
+
+/// the exact value of a literal in the rational mode, `None` when it is too
+/// large
+fn rational_literal(value: &NumLiteral) -> Option<Number> {
+    value
+        .to_big_rational()
+        .filter(|r| r.numer().bits() <= MAX_BIG_INT_BITS && r.denom().bits() <= MAX_BIG_INT_BITS)
+        .map(|r| Number::Rational(Fraction::from(r)))
+}
+
+/// converts the value of a variable to the number type of `mode`, so that
+/// it combines with the literals around it as they do with each other
+fn convert_to_mode(value: &Number, mode: EvalMode, span: Span) -> Result<Number, EvalError> {
+    let overflow = || EvalError::Overflow { span };
+    match (mode, value) {
+        (EvalMode::Rational, Number::Float(x)) => BigRational::from_float(*x)
+            .map(|r| Number::Rational(Fraction::from(r)))
+            .ok_or_else(overflow),
+        (EvalMode::Rational, Number::Int(n)) => {
+            Ok(Number::Rational(Fraction::from(BigInt::from(*n))))
+        }
+        (EvalMode::Rational, Number::BigInt(n)) => Ok(Number::Rational(Fraction::from(n.clone()))),
+        (_, Number::Float(_)) | (EvalMode::Rational, Number::Rational(_)) => Ok(value.clone()),
+        (_, Number::Rational(r)) if !r.is_integer() => Ok(Number::Float(r.to_f64())),
+        (EvalMode::BigInt, Number::Int(n)) => Ok(Number::BigInt(BigInt::from(*n))),
+        (EvalMode::BigInt, Number::BigInt(_)) => Ok(value.clone()),
+        (EvalMode::BigInt, Number::Rational(r)) => Ok(Number::BigInt(r.numer().clone())),
+        (_, Number::Int(_)) => Ok(value.clone()),
+        (_, Number::BigInt(n)) => n.to_i64().map(Number::Int).ok_or_else(overflow),
+        (_, Number::Rational(r)) => r.numer().to_i64().map(Number::Int).ok_or_else(overflow),
+    }
+}
+
+/// Walks the expression and returns its value, using the default
+/// [`EvalOptions`]
+pub fn evaluate(expr: &Expr) -> Result<Value, EvalError> {
+    evaluate_with(expr, &EvalOptions::default())
+}
+
+/// Walks the expression and returns its value. The expression may not refer
+/// to any variables, see [`evaluate_in`].
+pub fn evaluate_with(expr: &Expr, options: &EvalOptions) -> Result<Value, EvalError> {
+    evaluate_in(expr, &Environment::new(), options)
+}
+
+/// Walks the expression and returns its value, looking its variables up in
+/// `env`. A variable with no value is only an error where it is evaluated,
+/// so `true || x` and `false ? x : 1` need no value for `x`. The
+/// [`EvalError::UnboundVariable`] then lists every variable of the
+/// expression with no value. Calls go to the functions registered in `env`
+/// first, then to the built-in ones.
+pub fn evaluate_in(
+    expr: &Expr,
+    env: &Environment,
+    options: &EvalOptions,
+) -> Result<Value, EvalError> {
+    Evaluator::new(env, options)
+        .run(expr)
+        .map_err(|err| with_all_unbound(err, expr.variables(), env))
+}
+
+/// The outcome of running a [`Program`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ProgramResult {
+    /// the value of the last statement, for an assignment the value
+    /// assigned. `None` when the last statement defines a function
+    pub value: Option<Value>,
+    /// the environment the program started in with its assignments applied
+    pub env: Environment,
+}
+
+/// Runs the statements of the program in order, starting from a copy of
+/// `env`. As with [`evaluate_in`], a variable with no value is only an error
+/// where it is evaluated, and the error then lists every variable that is
+/// neither in `env` nor assigned before its use. Function definitions are added to the
+/// environment with [`Environment::define`]. The variables of a function
+/// body are looked up when it is called, so it may use a variable assigned
+/// after the definition.
+pub fn evaluate_program(
+    program: &Program,
+    env: &Environment,
+    options: &EvalOptions,
+) -> Result<ProgramResult, EvalError> {
+    // the parser never produces a program without statements
+    debug_assert!(!program.statements.is_empty(), "empty program");
+    let unbound = |err| with_all_unbound(err, program.unassigned_variables(), env);
+    let mut env = env.clone();
+    let mut value = None;
+    for statement in program.statements.iter() {
+        value = match statement {
+            Statement::Assign {
+                name, value: expr, ..
+            } => {
+                let value = Evaluator::new(&env, options).run(expr).map_err(unbound)?;
+                env.set(name, value.clone());
+                Some(value)
+            }
+            Statement::Define {
+                name, params, body, ..
+            } => {
+                env.define(UserFunction {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                });
+                None
+            }
+            Statement::Expr(expr) => {
+                Some(Evaluator::new(&env, options).run(expr).map_err(unbound)?)
+            }
+        };
+    }
+    Ok(ProgramResult { value, env })
+}
+
+/// `err`, if it is about one of `variables` having no value in `env`,
+/// widened to every one of them with no value, so that they are reported
+/// together. An error from the body of a called function is left as it is.
+fn with_all_unbound(err: EvalError, variables: Vec<(&str, Span)>, env: &Environment) -> EvalError {
+    let EvalError::UnboundVariable { names: found, .. } = &err else {
+        return err;
+    };
+    let mut names: Vec<String> = Vec::new();
+    let mut first_span = None;
+    for (name, span) in variables {
+        if env.get(name).is_none() && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+            first_span.get_or_insert(span);
+        }
+    }
+    match first_span {
+        Some(span) if found.iter().all(|name| names.contains(name)) => {
+            EvalError::UnboundVariable { names, span }
+        }
+        _ => err,
+    }
+}
+
+/// the number `value` of `expr`, an operand of an arithmetic operator
+fn expect_number(value: Value, expr: &Expr) -> Result<Number, EvalError> {
+    match value {
+        Value::Number(n) => Ok(n),
+        value => Err(EvalError::TypeMismatch {
+            expected: "number",
+            found: value.type_name(),
+            span: expr.span(),
+        }),
+    }
+}
+
+/// the boolean `value` of `expr`, an operand of a boolean operator or a
+/// condition
+fn expect_bool(value: Value, expr: &Expr) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => Err(EvalError::TypeMismatch {
+            expected: "boolean",
+            found: value.type_name(),
+            span: expr.span(),
+        }),
+    }
+}
+
+/// how two numbers compare, exactly when both have the same exact type and
+/// in floating point otherwise
+fn compare_numbers(lhs: &Number, rhs: &Number) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Number::Int(lhs), Number::Int(rhs)) => Some(lhs.cmp(rhs)),
+        (Number::BigInt(lhs), Number::BigInt(rhs)) => Some(lhs.cmp(rhs)),
+        (Number::Rational(lhs), Number::Rational(rhs)) => {
+            Some(lhs.as_big_rational().cmp(rhs.as_big_rational()))
+        }
+        (lhs, rhs) => lhs.to_f64().partial_cmp(&rhs.to_f64()),
+    }
+}
+
+/// compares `lhs_value` and `rhs_value`, the values of `lhs` and `rhs`. Any
+/// two values of the same type can be tested for equality, but only
+/// numbers are ordered.
+fn compare(
+    op: CompareOp,
+    lhs_value: Value,
+    rhs_value: Value,
+    lhs: &Expr,
+    rhs: &Expr,
+) -> Result<bool, EvalError> {
+    let ordering = match (&lhs_value, &rhs_value) {
+        (Value::Number(lhs_number), Value::Number(rhs_number)) => {
+            compare_numbers(lhs_number, rhs_number)
+        }
+        _ if lhs_value.type_name() != rhs_value.type_name() => {
+            return Err(EvalError::TypeMismatch {
+                expected: lhs_value.type_name(),
+                found: rhs_value.type_name(),
+                span: rhs.span(),
+            });
+        }
+        _ => match op {
+            CompareOp::Eq => return Ok(lhs_value == rhs_value),
+            CompareOp::Ne => return Ok(lhs_value != rhs_value),
+            _ => {
+                return Err(EvalError::TypeMismatch {
+                    expected: "number",
+                    found: lhs_value.type_name(),
+                    span: lhs.span(),
+                });
+            }
+        },
+    };
+    Ok(match op {
+        CompareOp::Eq => ordering == Some(Ordering::Equal),
+        CompareOp::Ne => ordering != Some(Ordering::Equal),
+        CompareOp::Lt => ordering == Some(Ordering::Less),
+        CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        CompareOp::Gt => ordering == Some(Ordering::Greater),
+        CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+    })
+}
+
+/// `value` with its number, if it has one, converted to the type of `mode`
+fn convert_value_to_mode(value: &Value, mode: EvalMode, span: Span) -> Result<Value, EvalError> {
+    match value {
+        Value::Number(n) => convert_to_mode(n, mode, span).map(Value::Number),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// the value of a number literal in the number type of `mode`
+fn evaluate_literal(value: &NumLiteral, span: Span, mode: EvalMode) -> Result<Number, EvalError> {
+    let number = match mode {
+        _ if !value.is_integer() && mode != EvalMode::Rational => {
+            return match value.to_f64() {
+                x if x.is_finite() => Ok(Number::Float(x)),
+                _ => Err(EvalError::Overflow { span }),
+            };
+        }
+        EvalMode::Standard => value.to_i64().map(Number::Int),
+        EvalMode::BigInt => value
+            .to_big_int()
+            .filter(|n| n.bits() <= MAX_BIG_INT_BITS)
+            .map(Number::BigInt),
+        EvalMode::Rational => rational_literal(value),
+    };
+    number.ok_or(EvalError::Overflow { span })
+}
+
+/// `-n`, `span` covers the negation
+fn negate(n: Number, span: Span) -> Result<Number, EvalError> {
+    match n {
+        Number::Int(n) => n
+            .checked_neg()
+            .map(Number::Int)
+            .ok_or(EvalError::Overflow { span }),
+        Number::BigInt(n) => Ok(Number::BigInt(-n)),
+        Number::Rational(r) => Ok(Number::Rational(Fraction::from(-r.as_big_rational()))),
+        Number::Float(x) => Ok(Number::Float(-x)),
+    }
+}
+
+/// the product of the integers from `lo` to `hi`, multiplying the
+/// products of the two halves of the range so that the numbers multiplied
+/// are of similar size
+fn range_product(lo: u64, hi: u64) -> BigInt {
+    if hi - lo < 16 {
+        return (lo..=hi).map(BigInt::from).product();
+    }
+    let mid = lo + (hi - lo) / 2;
+    range_product(lo, mid) * range_product(mid + 1, hi)
+}
+
+/// `n!` for a non-negative `n`, `None` when it has more than
+/// [`MAX_BIG_INT_BITS`] bits
+fn big_factorial(n: &BigInt) -> Option<BigInt> {
+    let n = n.to_u64()?;
+    if n < 2 {
+        return Some(BigInt::one());
+    }
+    // the result has log2(n!) bits, which is cheap to add up before
+    // computing anything
+    let mut bits = 0.0;
+    for k in 2..=n {
+        bits += (k as f64).log2();
+        if bits > MAX_BIG_INT_BITS as f64 {
+            return None;
+        }
+    }
+    Some(range_product(2, n)).filter(|value| value.bits() <= MAX_BIG_INT_BITS)
+}
+
+/// `n!`, only defined for non-negative integers. `expr` is the factorial
+/// and `operand` its operand.
+fn factorial(n: Number, expr: &Expr, operand: &Expr) -> Result<Number, EvalError> {
+    let invalid = || EvalError::InvalidFactorial {
+        span: operand.span(),
+    };
+    let overflow = || EvalError::Overflow { span: expr.span() };
+    match n {
+        Number::Int(n) if n < 0 => Err(invalid()),
+        Number::Int(n) => (2..=n)
+            .try_fold(1i64, |product, k| product.checked_mul(k))
+            .map(Number::Int)
+            .ok_or_else(overflow),
+        Number::BigInt(n) if n.is_negative() => Err(invalid()),
+        Number::BigInt(n) => big_factorial(&n).map(Number::BigInt).ok_or_else(overflow),
+        Number::Rational(r) if !r.is_integer() || r.numer().is_negative() => Err(invalid()),
+        Number::Rational(r) => big_factorial(r.numer())
+            .map(|n| Number::Rational(Fraction::from(n)))
+            .ok_or_else(overflow),
+        Number::Float(x) if x < 0.0 || x.fract() != 0.0 => Err(invalid()),
+        // 171! is beyond the range of an f64
+        Number::Float(x) if x > 170.0 => Err(overflow()),
+        Number::Float(x) => Ok(Number::Float(
+            (2..=x as u32).fold(1.0, |product, k| product * k as f64),
+        )),
+    }
+}
+
+/// The function a call goes to
+#[derive(Clone, Copy)]
+enum Callee<'a> {
+    /// defined by a program, its body is evaluated in place of the call
+    Defined(&'a UserFunction),
+    /// registered in the environment or built in
+    Native(&'a dyn Function),
+}
+
+/// What is left to do with a value once the evaluator has it. Rather than
+/// recursing into operands, arguments and the bodies of called functions,
+/// the evaluator keeps a stack of these, so however deeply an expression or
+/// a recursion nests, it takes heap memory rather than call stack.
+enum Continuation<'a> {
+    /// the value is the left operand of `expr`, a binary or boolean
+    /// operator or a comparison
+    Lhs(&'a Expr),
+    /// the value is the right operand of the binary operator `expr`, whose
+    /// left operand is the number
+    BinaryRhs(&'a Expr, Number),
+    /// the value is the right operand of the boolean operator `expr`
+    LogicRhs(&'a Expr),
+    /// the value is the right operand of the comparison `expr`, whose left
+    /// operand is the value
+    CompareRhs(&'a Expr, Value),
+    /// the value is the operand of `expr`, a prefix or postfix operator or
+    /// an absolute value
+    Operand(&'a Expr),
+    /// the value is the condition of the conditional `expr`
+    Condition(&'a Expr),
+    /// the value is that of the next of `arg_exprs`, the arguments of a
+    /// call to `callee` covering `span`, after the values `args` of those
+    /// before it
+    Argument {
+        span: Span,
+        callee: Callee<'a>,
+        arg_exprs: &'a [Expr],
+        args: Vec<Value>,
+    },
+    /// the value is that of the body of a defined function, evaluated in
+    /// the environment on top of `call_envs`. Errors in the body are
+    /// reported at the call the span covers, since the definition may be in
+    /// an earlier input.
+    Return(Span),
+}
+
+/// What a call to a function comes to
+enum Called<'a> {
+    Value(Value),
+    /// the body of a defined function, to evaluate in the environment just
+    /// pushed on `call_envs`
+    Body(&'a Expr),
+}
+
+/// Walks an expression with a stack of [`Continuation`]s
+struct Evaluator<'a> {
+    /// the environment evaluation starts in. The environment of a call only
+    /// adds variables to it, so every function is looked up here.
+    env: &'a Environment,
+    options: &'a EvalOptions,
+    /// the environments of the calls to defined functions being evaluated,
+    /// innermost last
+    call_envs: Vec<Environment>,
+    continuations: Vec<Continuation<'a>>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(env: &'a Environment, options: &'a EvalOptions) -> Self {
+        Evaluator {
+            env,
+            options,
+            call_envs: Vec::new(),
+            continuations: Vec::new(),
+        }
+    }
+
+    /// the value of `expr`
+    fn run(mut self, expr: &'a Expr) -> Result<Value, EvalError> {
+        let mut value = match self.descend(expr) {
+            Ok(value) => value,
+            Err(err) => return Err(self.unwind(err)),
+        };
+        while let Some(continuation) = self.continuations.pop() {
+            value = match self.resume(continuation, value) {
+                Ok(value) => value,
+                Err(err) => return Err(self.unwind(err)),
+            };
+        }
+        Ok(value)
+    }
+
+    /// `err` reported at the outermost call to a defined function whose
+    /// body it was found in
+    fn unwind(&mut self, mut err: EvalError) -> EvalError {
+        while let Some(continuation) = self.continuations.pop() {
+            if let Continuation::Return(span) = continuation {
+                err = err.at(span);
+            }
+        }
+        err
+    }
+
+    /// the variables of the innermost call, or those evaluation started with
+    fn variables(&self) -> &Environment {
+        self.call_envs.last().unwrap_or(self.env)
+    }
+
+    /// follows `expr` down to the operand evaluated first, leaving what is
+    /// left to do with each expression on the way as a continuation, and
+    /// returns the value of that operand
+    fn descend(&mut self, mut expr: &'a Expr) -> Result<Value, EvalError> {
+        loop {
+            let next = match expr {
+                Expr::Num { value, span } => {
+                    return evaluate_literal(value, *span, self.options.mode).map(Value::Number);
+                }
+                Expr::Str { value, .. } => return Ok(Value::Str(value.clone())),
+                Expr::Bool { value, .. } => return Ok(Value::Bool(*value)),
+                Expr::Var { name, span } => {
+                    return match self.variables().get(name) {
+                        Some(value) => convert_value_to_mode(value, self.options.mode, *span),
+                        None => Err(EvalError::UnboundVariable {
+                            names: vec![name.clone()],
+                            span: *span,
+                        }),
+                    };
+                }
+                Expr::Call {
+                    name,
+                    args,
+                    name_span,
+                    span,
+                } => {
+                    let callee = self.callee(name, args.len(), *name_span, *span)?;
+                    match args.first() {
+                        Some(arg) => {
+                            self.continuations.push(Continuation::Argument {
+                                span: *span,
+                                callee,
+                                arg_exprs: args,
+                                args: Vec::new(),
+                            });
+                            arg
+                        }
+                        None => match self.call(callee, Vec::new(), *span)? {
+                            Called::Value(value) => return Ok(value),
+                            Called::Body(body) => body,
+                        },
+                    }
+                }
+                Expr::Neg { operand, .. }
+                | Expr::Not { operand, .. }
+                | Expr::Abs { operand, .. }
+                | Expr::Postfix { operand, .. } => {
+                    self.continuations.push(Continuation::Operand(expr));
+                    operand
+                }
+                Expr::Binary { lhs, .. } | Expr::Logic { lhs, .. } | Expr::Compare { lhs, .. } => {
+                    self.continuations.push(Continuation::Lhs(expr));
+                    lhs
+                }
+                Expr::Conditional { cond, .. } => {
+                    self.continuations.push(Continuation::Condition(expr));
+                    cond
+                }
+            };
+            expr = next;
+        }
+    }
+
+    /// takes `value` on with what was left to do with it, returning the
+    /// value of the expression it completes or of the operand evaluated
+    /// first in what it still has to evaluate
+    fn resume(&mut self, continuation: Continuation<'a>, value: Value) -> Result<Value, EvalError> {
+        let next = match continuation {
+            Continuation::Lhs(expr) => match expr {
+                Expr::Binary { lhs, rhs, .. } => {
+                    let lhs_value = expect_number(value, lhs)?;
+                    self.continuations
+                        .push(Continuation::BinaryRhs(expr, lhs_value));
+                    rhs
+                }
+                Expr::Logic { op, lhs, rhs, .. } => {
+                    // the right operand is only evaluated when the left one
+                    // does not decide the result
+                    match (op, expect_bool(value, lhs)?) {
+                        (LogicOp::And, false) => return Ok(Value::Bool(false)),
+                        (LogicOp::Or, true) => return Ok(Value::Bool(true)),
+                        _ => {}
+                    }
+                    self.continuations.push(Continuation::LogicRhs(expr));
+                    rhs
+                }
+                Expr::Compare { rhs, .. } => {
+                    self.continuations
+                        .push(Continuation::CompareRhs(expr, value));
+                    rhs
+                }
+                // descend only leaves the operators above for their left
+                // operand
+                _ => return Ok(value),
+            },
+            Continuation::BinaryRhs(expr, lhs_value) => {
+                return match expr {
+                    Expr::Binary { op, lhs, rhs, .. } => {
+                        let rhs_value = expect_number(value, rhs)?;
+                        apply_op(*op, lhs_value, rhs_value, expr, lhs, rhs, self.options)
+                            .map(Value::Number)
+                    }
+                    _ => Ok(value),
+                };
+            }
+            Continuation::LogicRhs(expr) => {
+                return match expr {
+                    Expr::Logic { rhs, .. } => expect_bool(value, rhs).map(Value::Bool),
+                    _ => Ok(value),
+                };
+            }
+            Continuation::CompareRhs(expr, lhs_value) => {
+                return match expr {
+                    Expr::Compare { op, lhs, rhs, .. } => {
+                        compare(*op, lhs_value, value, lhs, rhs).map(Value::Bool)
+                    }
+                    _ => Ok(value),
+                };
+            }
+            Continuation::Operand(expr) => return apply_to_operand(expr, value, self.options),
+            Continuation::Condition(expr) => match expr {
+                // only the chosen branch is evaluated, so the other one may
+                // divide by zero or call an unknown function
+                Expr::Conditional {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    if expect_bool(value, cond)? {
+                        then_branch
+                    } else {
+                        else_branch
+                    }
+                }
+                _ => return Ok(value),
+            },
+            Continuation::Argument {
+                span,
+                callee,
+                arg_exprs,
+                mut args,
+            } => {
+                args.push(value);
+                match arg_exprs.get(args.len()) {
+                    Some(arg) => {
+                        self.continuations.push(Continuation::Argument {
+                            span,
+                            callee,
+                            arg_exprs,
+                            args,
+                        });
+                        arg
+                    }
+                    None => match self.call(callee, args, span)? {
+                        Called::Value(value) => return Ok(value),
+                        Called::Body(body) => body,
+                    },
+                }
+            }
+            Continuation::Return(_) => {
+                self.call_envs.pop();
+                return Ok(value);
+            }
+        };
+        self.descend(next)
+    }
+
+    /// the function `name` a call with `arg_count` arguments goes to:
+    /// defined in the environment, registered in it or built in, in that
+    /// order. `span` covers the call.
+    fn callee(
+        &self,
+        name: &str,
+        arg_count: usize,
+        name_span: Span,
+        span: Span,
+    ) -> Result<Callee<'a>, EvalError> {
+        let env = self.env;
+        let callee = match env.definition(name) {
+            Some(function) => Callee::Defined(function),
+            None => Callee::Native(match env.function(name) {
+                Some(function) => function,
+                None => Builtin::lookup(name).ok_or_else(|| EvalError::UnknownFunction {
+                    name: name.to_string(),
+                    span: name_span,
+                })?,
+            }),
+        };
+        let arity = match callee {
+            Callee::Defined(function) => function.arity(),
+            Callee::Native(function) => function.arity(),
+        };
+        if !arity.accepts(arg_count) {
+            return Err(EvalError::WrongArgumentCount {
+                name: name.to_string(),
+                expected: arity,
+                found: arg_count,
+                span,
+            });
+        }
+        if let Callee::Defined(function) = callee {
+            if self.call_envs.len() >= self.options.max_call_depth {
+                return Err(EvalError::CallDepthExceeded {
+                    name: function.name.clone(),
+                    max_depth: self.options.max_call_depth,
+                    span,
+                });
+            }
+        }
+        Ok(callee)
+    }
+
+    /// calls `callee` with `args`, the values of its arguments. A defined
+    /// function's body is evaluated with the parameters bound on top of the
+    /// caller's variables. `span` covers the call.
+    fn call(
+        &mut self,
+        callee: Callee<'a>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Called<'a>, EvalError> {
+        match callee {
+            Callee::Defined(function) => {
+                let mut call_env = self.variables().clone();
+                for (param, arg) in function.params.iter().zip(args) {
+                    call_env.set(param, arg);
+                }
+                self.call_envs.push(call_env);
+                self.continuations.push(Continuation::Return(span));
+                Ok(Called::Body(&function.body))
+            }
+            Callee::Native(function) => {
+                let value = function.call(&args).map_err(|err| err.or_span(span))?;
+                // integers are converted to the mode's type, but an inexact
+                // floating point result stays one, as it does for `sqrt(2)`
+                match value {
+                    Value::Number(Number::Float(_)) => Ok(Called::Value(value)),
+                    value => {
+                        convert_value_to_mode(&value, self.options.mode, span).map(Called::Value)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// applies `expr`, a prefix or postfix operator or an absolute value, to
+/// `value`, the value of its operand
+fn apply_to_operand(expr: &Expr, value: Value, options: &EvalOptions) -> Result<Value, EvalError> {
+    let number = match expr {
+        Expr::Neg { operand, span } => negate(expect_number(value, operand)?, *span),
+        Expr::Not { operand, .. } => return expect_bool(value, operand).map(|b| Value::Bool(!b)),
+        Expr::Abs { operand, span } => abs(&expect_number(value, operand)?, *span),
+        Expr::Postfix {
+            op: PostfixOp::Factorial,
+            operand,
+            ..
+        } => factorial(expect_number(value, operand)?, expr, operand),
+        Expr::Postfix {
+            op: PostfixOp::Percent,
+            operand,
+            span,
+        } => {
+            let value = expect_number(value, operand)?;
+            let hundred = convert_to_mode(&Number::Int(100), options.mode, *span)?;
+            apply_op(BinOp::Div, value, hundred, expr, operand, expr, options)
+        }
+        // descend only leaves the operators above for their operand
+        _ => return Ok(value),
+    };
+    number.map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expr;
+
+    /// the number a test expression evaluates to
+    fn number(value: Value) -> Number {
+        match value {
+            Value::Number(n) => n,
+            value => panic!("Expected a number, found {}", value),
+        }
+    }
+
+    fn eval_str(s: &str) -> Result<Number, EvalError> {
+        let expr = parse_expr(s).expect("Expected an expression");
+        evaluate(&expr).map(number)
+    }
+
+    #[test]
+    fn test_eval_precedence() {
+        assert_eq!(eval_str("2+3*4"), Ok(Number::Int(14)));
+        assert_eq!(eval_str("2*3+4"), Ok(Number::Int(10)));
+        assert_eq!(eval_str("12 / 4 + 1"), Ok(Number::Int(4)));
+    }
+
+    #[test]
+    fn test_eval_parens() {
+        assert_eq!(eval_str("(2+3)*4"), Ok(Number::Int(20)));
+        assert_eq!(eval_str("(2 / 3) + 4"), Ok(Number::Float(2.0 / 3.0 + 4.0)));
+        assert_eq!(eval_str("(8 / 2) / (3 - 1)"), Ok(Number::Int(2)));
+    }
+
+    #[test]
+    fn test_eval_left_associative() {
+        assert_eq!(eval_str("8-2-1"), Ok(Number::Int(5)));
+        assert_eq!(eval_str("8/4/2"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("8/4*2"), Ok(Number::Int(4)));
+        assert_eq!(eval_str("10-4+3"), Ok(Number::Int(9)));
+    }
+
+    #[test]
+    fn test_eval_long_chains() {
+        // long chains are evaluated in a loop rather than by recursion
+        let sum = vec!["1"; 5000].join(" + ");
+        assert_eq!(eval_str(&sum), Ok(Number::Int(5000)));
+        let expr = parse_expr(&sum).unwrap();
+        assert_eq!(expr.to_string(), sum);
+        let product = vec!["1"; 5000].join(" * ");
+        assert_eq!(eval_str(&product), Ok(Number::Int(1)));
+        let factorials = format!("1{}", "!".repeat(5000));
+        assert_eq!(eval_str(&factorials), Ok(Number::Int(1)));
+        let conjunction = vec!["true"; 5000].join(" && ");
+        assert_eq!(eval_bool(&conjunction, &EvalOptions::default()), Ok(true));
+    }
+
+    #[test]
+    fn test_eval_division_by_zero() {
+        assert_eq!(
+            eval_str("4 / 0"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(4, 5, 1, 5)
+            })
+        );
+        assert_eq!(
+            eval_str("4 / (2 - 2)"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(5, 10, 1, 6)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_unary() {
+        assert_eq!(eval_str("-3"), Ok(Number::Int(-3)));
+        assert_eq!(eval_str("2*-4"), Ok(Number::Int(-8)));
+        assert_eq!(eval_str("-(1+2)"), Ok(Number::Int(-3)));
+        assert_eq!(eval_str("--3"), Ok(Number::Int(3)));
+        assert_eq!(eval_str("+-+3"), Ok(Number::Int(-3)));
+        assert_eq!(eval_str("2 - -3"), Ok(Number::Int(5)));
+        assert_eq!(eval_str("-8 / 4"), Ok(Number::Int(-2)));
+    }
+
+    #[test]
+    fn test_eval_power() {
+        assert_eq!(eval_str("2^10"), Ok(Number::Int(1024)));
+        assert_eq!(eval_str("2**10"), Ok(Number::Int(1024)));
+        assert_eq!(eval_str("2^3^2"), Ok(Number::Int(512)));
+        assert_eq!(eval_str("(2^3)^2"), Ok(Number::Int(64)));
+        assert_eq!(eval_str("-2^2"), Ok(Number::Int(-4)));
+        assert_eq!(eval_str("(-2)^2"), Ok(Number::Int(4)));
+        assert_eq!(eval_str("2*3^2"), Ok(Number::Int(18)));
+        assert_eq!(eval_str("0^0"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("1 ^ (65536 * 65536)"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("(-1) ^ (65536 * 65536 + 1)"), Ok(Number::Int(-1)));
+        assert_eq!(eval_str("2 ^ -1"), Ok(Number::Float(0.5)));
+        assert_eq!(eval_str("4 ^ 0.5"), Ok(Number::Float(2.0)));
+    }
+
+    #[test]
+    fn test_eval_power_errors() {
+        assert_eq!(
+            eval_str("0 ^ -1"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(0, 1, 1, 1)
+            })
+        );
+        assert_eq!(
+            eval_str("(-8) ^ 0.5"),
+            Err(EvalError::Undefined {
+                span: Span::new(0, 10, 1, 1)
+            })
+        );
+        assert_eq!(
+            eval_str("2 ^ 63"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 6, 1, 1)
+            })
+        );
+        assert!(matches!(
+            eval_str("2 ^ 62 * 2"),
+            Err(EvalError::Overflow { span }) if span == Span::new(0, 10, 1, 1)
+        ));
+    }
+
+    #[test]
+    fn test_eval_modulo_and_int_div() {
+        assert_eq!(eval_str("7 % 3"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("7 // 2"), Ok(Number::Int(3)));
+        assert_eq!(eval_str("2 + 7 % 3 * 2"), Ok(Number::Int(4)));
+        assert_eq!(eval_str("17 // 5 % 2"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("6 // 3"), Ok(Number::Int(2)));
+    }
+
+    #[test]
+    fn test_eval_division_rounding() {
+        let floor = EvalOptions::new();
+        let truncate = EvalOptions::new().division_rounding(DivisionRounding::Truncate);
+        let cases = [
+            // (input, floor result, truncate result)
+            ("-7 // 2", -4, -3),
+            ("7 // -2", -4, -3),
+            ("-7 // -2", 3, 3),
+            ("-6 // 2", -3, -3),
+            ("-7 % 2", 1, -1),
+            ("7 % -2", -1, 1),
+            ("-7 % -2", -1, -1),
+            ("-6 % 2", 0, 0),
+        ];
+        for (s, floor_value, truncate_value) in cases {
+            let expr = parse_expr(s).unwrap();
+            assert_eq!(
+                evaluate_with(&expr, &floor).map(number),
+                Ok(Number::Int(floor_value)),
+                "{}",
+                s
+            );
+            assert_eq!(
+                evaluate_with(&expr, &truncate).map(number),
+                Ok(Number::Int(truncate_value)),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_modulo_and_int_div_errors() {
+        assert_eq!(
+            eval_str("7 % (1 - 1)"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(5, 10, 1, 6)
+            })
+        );
+        assert_eq!(
+            eval_str("7 // 0"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(5, 6, 1, 6)
+            })
+        );
+        let min = "(-(2 ^ 62) * 2)";
+        assert!(matches!(
+            eval_str(&format!("{} // -1", min)),
+            Err(EvalError::Overflow { .. })
+        ));
+        assert_eq!(eval_str(&format!("{} % -1", min)), Ok(Number::Int(0)));
+    }
+
+    #[test]
+    fn test_eval_decimals() {
+        assert_eq!(eval_str("2.75"), Ok(Number::Float(2.75)));
+        assert_eq!(eval_str(".5 + 10."), Ok(Number::Float(10.5)));
+        assert_eq!(eval_str("2 * 1e-9"), Ok(Number::Float(2e-9)));
+        assert_eq!(eval_str("6.02E23 / 2"), Ok(Number::Float(3.01e23)));
+        assert_eq!(eval_str("-1.5 // 1"), Ok(Number::Float(-2.0)));
+        assert_eq!(eval_str("5.5 % 2"), Ok(Number::Float(1.5)));
+        // a decimal literal makes the result a float even when it is whole
+        assert_eq!(eval_str("1.0 + 1"), Ok(Number::Float(2.0)));
+    }
+
+    #[test]
+    fn test_eval_radix_literals() {
+        assert_eq!(eval_str("0xFF - 0b1010 + 0o17"), Ok(Number::Int(260)));
+        assert_eq!(eval_str("1_000_000 / 1_000"), Ok(Number::Int(1000)));
+        assert_eq!(eval_str("0x7fff_ffff_ffff_ffff"), Ok(Number::Int(i64::MAX)));
+        assert_eq!(
+            eval_str("0x8000_0000_0000_0000"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 21, 1, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_integer_division_stays_exact() {
+        assert_eq!(eval_str("6 / 3"), Ok(Number::Int(2)));
+        assert_eq!(eval_str("1 / 2"), Ok(Number::Float(0.5)));
+        assert_eq!(eval_str("-7 / 2"), Ok(Number::Float(-3.5)));
+    }
+
+    #[test]
+    fn test_eval_decimal_errors() {
+        assert_eq!(
+            eval_str("1 / 0.0"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(4, 7, 1, 5)
+            })
+        );
+        assert_eq!(
+            eval_str("1 + 1e999"),
+            Err(EvalError::Overflow {
+                span: Span::new(4, 9, 1, 5)
+            })
+        );
+        assert_eq!(
+            eval_str("1e300 * 1e300"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 13, 1, 1)
+            })
+        );
+        assert_eq!(
+            eval_str("99999999999999999999"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 20, 1, 1)
+            })
+        );
+    }
+
+    fn eval_big(s: &str) -> Result<Number, EvalError> {
+        let expr = parse_expr(s).expect("Expected an expression");
+        evaluate_with(&expr, &EvalOptions::new().mode(EvalMode::BigInt)).map(number)
+    }
+
+    fn big(s: &str) -> Number {
+        Number::BigInt(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_eval_big_int() {
+        assert_eq!(
+            eval_big("2^200"),
+            Ok(big(
+                "1606938044258990275541962092341162602522202993782792835301376"
+            ))
+        );
+        assert_eq!(
+            eval_big("99999999999999999999*3"),
+            Ok(big("299999999999999999997"))
+        );
+        assert_eq!(eval_big("-2^3^2 + 1"), Ok(big("-511")));
+        assert_eq!(
+            eval_big("0xFFFF_FFFF_FFFF_FFFF_FF + 1"),
+            Ok(big("4722366482869645213696"))
+        );
+        assert_eq!(eval_big("(2^100 + 1) % 2^50"), Ok(big("1")));
+        assert_eq!(eval_big("(-1)^(2^100)"), Ok(big("1")));
+        // the same expressions overflow in the standard mode
+        assert!(matches!(eval_str("2^200"), Err(EvalError::Overflow { .. })));
+        assert!(matches!(
+            eval_str("99999999999999999999*3"),
+            Err(EvalError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_big_int_division() {
+        assert_eq!(eval_big("2^100 / 2^98"), Ok(big("4")));
+        assert_eq!(eval_big("1 / 4"), Ok(Number::Float(0.25)));
+        assert_eq!(eval_big("2 ^ -2"), Ok(Number::Float(0.25)));
+        assert_eq!(eval_big("1.5 * 2"), Ok(Number::Float(3.0)));
+
+        let truncate = EvalOptions::new()
+            .mode(EvalMode::BigInt)
+            .division_rounding(DivisionRounding::Truncate);
+        for (s, floor_value, truncate_value) in [("-7 // 2", "-4", "-3"), ("-7 % 2", "1", "-1")] {
+            let expr = parse_expr(s).unwrap();
+            assert_eq!(eval_big(s), Ok(big(floor_value)));
+            assert_eq!(
+                evaluate_with(&expr, &truncate).map(number),
+                Ok(big(truncate_value))
+            );
+        }
+        assert_eq!(
+            eval_big("2^100 // (1 - 1)"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(10, 15, 1, 11)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_big_int_limit() {
+        assert!(eval_big("2 ^ (2 ^ 19)").is_ok());
+        assert_eq!(
+            eval_big("2 ^ (2 ^ 20)"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 12, 1, 1)
+            })
+        );
+        assert!(matches!(
+            eval_big("3 ^ 99999999999999999999"),
+            Err(EvalError::Overflow { .. })
+        ));
+        assert!(matches!(
+            eval_big("(2 ^ (2 ^ 19)) * (2 ^ (2 ^ 19))"),
+            Err(EvalError::Overflow { span }) if span == Span::new(0, 31, 1, 1)
+        ));
+    }
+
+    fn eval_rational(s: &str) -> Result<Number, EvalError> {
+        let expr = parse_expr(s).expect("Expected an expression");
+        evaluate_with(&expr, &EvalOptions::new().mode(EvalMode::Rational)).map(number)
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Number {
+        Number::Rational(Fraction::new(BigInt::from(numer), BigInt::from(denom)).unwrap())
+    }
+
+    #[test]
+    fn test_eval_rational() {
+        assert_eq!(eval_rational("1/3*3"), Ok(ratio(1, 1)));
+        assert_eq!(eval_rational("0.1 + 0.2"), Ok(ratio(3, 10)));
+        assert_eq!(eval_rational("1/3 + 1/6"), Ok(ratio(1, 2)));
+        assert_eq!(eval_rational("-6/4"), Ok(ratio(-3, 2)));
+        assert_eq!(eval_rational("(2/3)^-2"), Ok(ratio(9, 4)));
+        assert_eq!(eval_rational("(-1)^(2^100)"), Ok(ratio(1, 1)));
+        assert_eq!(eval_rational("1.5e-3"), Ok(ratio(3, 2000)));
+        assert_ne!(eval_str("0.1 + 0.2"), Ok(Number::Float(0.3)));
+
+        let Ok(Number::Rational(value)) = eval_rational("22/7") else {
+            panic!("Expected a fraction");
+        };
+        assert_eq!(value.to_string(), "22/7");
+        assert_eq!(value.to_mixed(), "3 1/7");
+        assert_eq!(value.to_decimal(4), "3.1429");
+        // only a fractional exponent leaves the exact numbers
+        assert_eq!(eval_rational("(9/4)^0.5"), Ok(Number::Float(1.5)));
+    }
+
+    #[test]
+    fn test_eval_rational_division() {
+        let truncate = EvalOptions::new()
+            .mode(EvalMode::Rational)
+            .division_rounding(DivisionRounding::Truncate);
+        for (s, floor_value, truncate_value) in [
+            ("-7 // 2", ratio(-4, 1), ratio(-3, 1)),
+            ("-7 % 2", ratio(1, 1), ratio(-1, 1)),
+            ("7/2 % 1", ratio(1, 2), ratio(1, 2)),
+            ("-7/2 % 1", ratio(1, 2), ratio(-1, 2)),
+        ] {
+            let expr = parse_expr(s).unwrap();
+            assert_eq!(eval_rational(s), Ok(floor_value), "{}", s);
+            assert_eq!(
+                evaluate_with(&expr, &truncate).map(number),
+                Ok(truncate_value),
+                "{}",
+                s
+            );
+        }
+        assert_eq!(
+            eval_rational("1/3 / (1/3 - 1/3)"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(7, 16, 1, 8)
+            })
+        );
+        assert!(matches!(
+            eval_rational("(1 - 1) ^ -1"),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_rational_limit() {
+        assert!(eval_rational("(1/2) ^ (2 ^ 19)").is_ok());
+        assert_eq!(
+            eval_rational("(1/2) ^ (2 ^ 20)"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 16, 1, 1)
+            })
+        );
+        assert!(matches!(
+            eval_rational("1e999999"),
+            Err(EvalError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_variables() {
+        let env = Environment::new()
+            .with("x", Number::Int(6))
+            .with("half", ratio(1, 2))
+            .with("big", big("99999999999999999999"));
+        let eval_env = |s: &str, mode: EvalMode| {
+            let expr = parse_expr(s).unwrap();
+            evaluate_in(&expr, &env, &EvalOptions::new().mode(mode)).map(number)
+        };
+        assert_eq!(
+            eval_env("x * x - 1", EvalMode::Standard),
+            Ok(Number::Int(35))
+        );
+        assert_eq!(
+            eval_env("x * half", EvalMode::Standard),
+            Ok(Number::Float(3.0))
+        );
+        assert_eq!(
+            eval_env("big * x", EvalMode::BigInt),
+            Ok(big("599999999999999999994"))
+        );
+        assert_eq!(eval_env("x * half", EvalMode::Rational), Ok(ratio(3, 1)));
+        assert_eq!(
+            eval_env("1 + big", EvalMode::Standard),
+            Err(EvalError::Overflow {
+                span: Span::new(4, 7, 1, 5)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_unbound_variables() {
+        let env = Environment::new().with("qty", Number::Int(3));
+        let expr = parse_expr("price * qty + (shipping - price)").unwrap();
+        assert_eq!(
+            evaluate_in(&expr, &env, &EvalOptions::new()),
+            Err(EvalError::UnboundVariable {
+                names: vec![String::from("price"), String::from("shipping")],
+                span: Span::new(0, 5, 1, 1)
+            })
+        );
+        // only what is evaluated needs a value
+        assert_eq!(
+            evaluate(&parse_expr("true || x").unwrap()),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(eval_str("false ? x : 1"), Ok(Number::Int(1)));
+        assert_eq!(
+            eval_str("1 / 0 + y"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(4, 5, 1, 5)
+            })
+        );
+        let program = crate::parse_program("a = 1; a > 0 || b").unwrap();
+        let result = evaluate_program(&program, &Environment::new(), &EvalOptions::new());
+        assert_eq!(result.unwrap().value, Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_eval_program() {
+        let program = crate::parse_program("a = 3; b = a * 2; b + 1").unwrap();
+        let result = evaluate_program(&program, &Environment::new(), &EvalOptions::new());
+        let expected_env = Environment::new()
+            .with("a", Number::Int(3))
+            .with("b", Number::Int(6));
+        assert_eq!(
+            result,
+            Ok(ProgramResult {
+                value: Some(Value::Number(Number::Int(7))),
+                env: expected_env
+            })
+        );
+
+        // the value of an assignment is the value assigned, and a variable
+        // can be assigned again
+        let program = crate::parse_program("n = 1\nn = n * 10").unwrap();
+        let env = Environment::new().with("unused", Number::Int(0));
+        let result = evaluate_program(&program, &env, &EvalOptions::new()).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(10))));
+        assert_eq!(result.env.get("n"), Some(&Value::Number(Number::Int(10))));
+        assert_eq!(
+            result.env.get("unused"),
+            Some(&Value::Number(Number::Int(0)))
+        );
+
+        let program = crate::parse_program("a = b; c = a + d; b = 1").unwrap();
+        assert_eq!(
+            evaluate_program(&program, &Environment::new(), &EvalOptions::new()),
+            Err(EvalError::UnboundVariable {
+                names: vec![String::from("b"), String::from("d")],
+                span: Span::new(4, 5, 1, 5)
+            })
+        );
+
+        // a program has at least one statement
+        assert!(crate::parse_program("").is_err());
+        assert!(crate::parse_program("\n;\n").is_err());
+    }
+
+    #[test]
+    fn test_eval_builtins() {
+        let cases = [
+            ("sqrt(16)", Number::Int(4)),
+            ("sqrt(2.25)", Number::Float(1.5)),
+            ("abs(-3) + abs(2)", Number::Int(5)),
+            ("floor(-2.5)", Number::Float(-3.0)),
+            ("ceil(-0.5)", Number::Float(0.0)),
+            ("round(2.5) + round(-2.5)", Number::Float(0.0)),
+            ("round(7)", Number::Int(7)),
+            ("min(3, 1.5, 2)", Number::Float(1.5)),
+            ("max(3, 1.5, 2)", Number::Int(3)),
+            ("max(-1)", Number::Int(-1)),
+            ("log(1000)", Number::Float(3.0)),
+            ("log(8, 2)", Number::Float(3.0)),
+            ("ln(1)", Number::Float(0.0)),
+            ("exp(0)", Number::Float(1.0)),
+            ("sin(0) + cos(0) + tan(0)", Number::Float(1.0)),
+            ("asin(1) * 2", Number::Float(std::f64::consts::PI)),
+            ("acos(1) + atan(0)", Number::Float(0.0)),
+        ];
+        for (s, value) in cases {
+            assert_eq!(eval_str(s), Ok(value), "{}", s);
+        }
+        assert!(matches!(eval_str("sqrt(2)"), Ok(Number::Float(x)) if (x * x - 2.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_eval_builtins_exact() {
+        assert_eq!(
+            eval_big("sqrt(2^200)"),
+            Ok(big("1267650600228229401496703205376"))
+        );
+        assert_eq!(eval_rational("sqrt(9/4)"), Ok(ratio(3, 2)));
+        assert_eq!(eval_rational("abs(-1/3)"), Ok(ratio(1, 3)));
+        assert_eq!(eval_rational("floor(-7/2)"), Ok(ratio(-4, 1)));
+        assert_eq!(eval_rational("ceil(-7/2)"), Ok(ratio(-3, 1)));
+        assert_eq!(eval_rational("round(5/2)"), Ok(ratio(3, 1)));
+        assert_eq!(eval_rational("min(1/3, 1/4)"), Ok(ratio(1, 4)));
+        assert_eq!(
+            eval_rational("sqrt(2/9)"),
+            Ok(Number::Float((2.0f64 / 9.0).sqrt()))
+        );
+    }
+
+    #[test]
+    fn test_eval_builtin_errors() {
+        assert_eq!(
+            eval_str("1 + foo(2)"),
+            Err(EvalError::UnknownFunction {
+                name: String::from("foo"),
+                span: Span::new(4, 7, 1, 5)
+            })
+        );
+        assert_eq!(
+            eval_str("sqrt(1, 2)"),
+            Err(EvalError::WrongArgumentCount {
+                name: String::from("sqrt"),
+                expected: Arity::Exact(1),
+                found: 2,
+                span: Span::new(0, 10, 1, 1)
+            })
+        );
+        assert!(matches!(
+            eval_str("max()"),
+            Err(EvalError::WrongArgumentCount {
+                expected: Arity::AtLeast(1),
+                found: 0,
+                ..
+            })
+        ));
+        for s in ["sqrt(-1)", "ln(0)", "log(-2)", "log(8, 1)", "asin(2)"] {
+            assert!(
+                matches!(eval_str(s), Err(EvalError::Undefined { .. })),
+                "{}",
+                s
+            );
+        }
+        assert!(matches!(
+            eval_str("exp(1000)"),
+            Err(EvalError::Overflow { .. })
+        ));
+        assert_eq!(
+            eval_str("abs(-(2^62) * 2)"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 16, 1, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_registered_functions() {
+        let fx = NativeFunction::new("fx", Arity::Exact(2), |args: &[Value]| {
+            let amount = args[0].expect_number()?;
+            match args[1].expect_str()? {
+                "EUR" => Ok(Value::Number(Number::Float(amount.to_f64() * 0.5))),
+                "JPY" => Ok(Value::Number(Number::Int(150))),
+                currency => Err(EvalError::custom(format!("unknown currency {}", currency))),
+            }
+        });
+        let currency = NativeFunction::new("currency", Arity::Exact(0), |_: &[Value]| {
+            Ok(Value::from("EUR"))
+        });
+        // registered functions replace built-in ones of the same name
+        let sqrt = NativeFunction::new("sqrt", Arity::Exact(1), |_: &[Value]| {
+            Ok(Value::Number(Number::Int(-1)))
+        });
+        let env = Environment::new()
+            .with_function(fx)
+            .with_function(currency)
+            .with_function(sqrt)
+            .with("cur", "EUR");
+        let eval_env = |s: &str, mode: EvalMode| {
+            let expr = parse_expr(s).unwrap();
+            evaluate_in(&expr, &env, &EvalOptions::new().mode(mode))
+        };
+        let standard = EvalMode::Standard;
+        assert_eq!(
+            eval_env("fx(10, \"EUR\") + 1", standard),
+            Ok(Value::Number(Number::Float(6.0)))
+        );
+        assert_eq!(
+            eval_env("fx(4, currency()) + fx(4, cur)", standard),
+            Ok(Value::Number(Number::Float(4.0)))
+        );
+        assert_eq!(eval_env("cur", standard), Ok(Value::from("EUR")));
+        assert_eq!(
+            eval_env("sqrt(16)", standard),
+            Ok(Value::Number(Number::Int(-1)))
+        );
+        // an integer result is converted to the mode's type
+        assert_eq!(
+            eval_env("fx(1, \"JPY\")", EvalMode::BigInt),
+            Ok(Value::Number(big("150")))
+        );
+        assert_eq!(
+            eval_env("fx(1, \"JPY\") / 7", EvalMode::Rational),
+            Ok(Value::Number(ratio(150, 7)))
+        );
+
+        // errors without a span are pointed at the call
+        assert_eq!(
+            eval_env("1 + fx(1, \"GBP\")", standard),
+            Err(EvalError::Custom {
+                message: String::from("unknown currency GBP"),
+                span: Span::new(4, 16, 1, 5)
+            })
+        );
+        assert_eq!(
+            eval_env("fx(\"EUR\", 1)", standard),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "string",
+                span: Span::new(0, 12, 1, 1)
+            })
+        );
+        assert!(matches!(
+            eval_env("fx(1)", standard),
+            Err(EvalError::WrongArgumentCount { found: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_definitions() {
+        let run = |s: &str, options: &EvalOptions| {
+            let program = crate::parse_program(s).unwrap();
+            evaluate_program(&program, &Environment::new(), options)
+        };
+        let options = EvalOptions::new();
+        let result = run("f(x) = x^2 + 1; f(3)", &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(10))));
+        let f = result.env.definition("f").unwrap();
+        assert_eq!(f.params, ["x"]);
+        assert_eq!(f.arity(), Arity::Exact(1));
+        assert_eq!(f.to_string(), "f(x) = x ^ 2 + 1");
+        assert!(result.env.function("f").is_none());
+
+        // a definition has no value and parameters shadow variables only in
+        // the body
+        let result = run("x = 10; g(x, y) = x - y; g(1, 2) * x", &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(-10))));
+        assert_eq!(run("one() = 1", &options).unwrap().value, None);
+        // variables in the body are looked up at the call, and definitions
+        // replace built-in functions and earlier definitions
+        let result = run(
+            "k = 1; h(x) = x * k; k = 3; sqrt(x) = -1; h(2) + sqrt(4)",
+            &options,
+        );
+        assert_eq!(result.unwrap().value, Some(Value::Number(Number::Int(5))));
+        assert_eq!(
+            run("f(x) = 1; f(x) = 2 * x; f(4)", &options).unwrap().value,
+            Some(Value::Number(Number::Int(8)))
+        );
+        // the arguments are converted like any other value
+        let rational = EvalOptions::new().mode(EvalMode::Rational);
+        assert_eq!(
+            run("half(x) = x / 2; half(1)", &rational).unwrap().value,
+            Some(Value::Number(ratio(1, 2)))
+        );
+
+        // errors in the body are reported at the call
+        assert_eq!(
+            run("inv(x) = 1 / x\n1 + inv(0)", &options),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(19, 25, 2, 5)
+            })
+        );
+        assert_eq!(
+            run("f(x, y) = x; f(1)", &options),
+            Err(EvalError::WrongArgumentCount {
+                name: String::from("f"),
+                expected: Arity::Exact(2),
+                found: 1,
+                span: Span::new(13, 17, 1, 14)
+            })
+        );
+        // a body's variables are looked up at the call, and only then
+        // reported if they have no value
+        assert_eq!(
+            run("f(x) = x * scale; scale = 2; f(3)", &options)
+                .unwrap()
+                .value,
+            Some(Value::Number(Number::Int(6)))
+        );
+        assert!(run("f(x) = x * y; y = 2", &options).is_ok());
+        assert_eq!(
+            run("f(x) = x + rate; f(1)", &options),
+            Err(EvalError::UnboundVariable {
+                names: vec![String::from("rate")],
+                span: Span::new(17, 21, 1, 18)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_call_depth() {
+        let run = |s: &str, options: &EvalOptions| {
+            let program = crate::parse_program(s).unwrap();
+            evaluate_program(&program, &Environment::new(), options)
+        };
+        let options = EvalOptions::new();
+        assert_eq!(options.max_call_depth, DEFAULT_MAX_CALL_DEPTH);
+        assert_eq!(
+            run("loop(n) = 1 + loop(n + 1); 2 * loop(0)", &options),
+            Err(EvalError::CallDepthExceeded {
+                name: String::from("loop"),
+                max_depth: DEFAULT_MAX_CALL_DEPTH,
+                span: Span::new(31, 38, 1, 32)
+            })
+        );
+        // nested calls count, calls one after the other do not
+        let s = "a(x) = x + 1; b(x) = a(x) + a(x); c(x) = b(b(x)); c(1)";
+        assert_eq!(
+            run(s, &options.clone().max_call_depth(3)).unwrap().value,
+            Some(Value::Number(Number::Int(10)))
+        );
+        assert!(matches!(
+            run(s, &options.max_call_depth(2)),
+            Err(EvalError::CallDepthExceeded { name, max_depth: 2, .. }) if name == "a"
+        ));
+        // f(n) nests n + 1 calls, so f(max - 1) is the deepest that runs
+        let countdown = |n: usize| format!("f(n) = n == 0 ? 0 : 1 + f(n - 1); f({})", n);
+        for max in [DEFAULT_MAX_CALL_DEPTH, 10_000] {
+            let options = EvalOptions::new().max_call_depth(max);
+            assert_eq!(
+                run(&countdown(max - 1), &options).unwrap().value,
+                Some(Value::Number(Number::Int(max as i64 - 1)))
+            );
+            assert!(matches!(
+                run(&countdown(max), &options),
+                Err(EvalError::CallDepthExceeded { name, max_depth, .. })
+                    if name == "f" && max_depth == max
+            ));
+        }
+    }
+
+    #[test]
+    fn test_eval_type_mismatch() {
+        assert_eq!(
+            eval_str("1 + (\"a\")"),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "string",
+                span: Span::new(5, 8, 1, 6)
+            })
+        );
+        assert!(matches!(
+            eval_str("-\"a\""),
+            Err(EvalError::TypeMismatch { span, .. }) if span == Span::new(1, 4, 1, 2)
+        ));
+        assert_eq!(
+            eval_str("sqrt(\"4\")"),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "string",
+                span: Span::new(0, 9, 1, 1)
+            })
+        );
+        let expr = parse_expr("\"a \\\"b\\\"\"").unwrap();
+        assert_eq!(evaluate(&expr), Ok(Value::from("a \"b\"")));
+        assert_eq!(evaluate(&expr).unwrap().to_string(), "\"a \\\"b\\\"\"");
+    }
+
+    fn eval_bool(s: &str, options: &EvalOptions) -> Result<bool, EvalError> {
+        let expr = parse_expr(s).expect("Expected an expression");
+        evaluate_with(&expr, options).map(|value| match value {
+            Value::Bool(b) => b,
+            value => panic!("Expected a boolean, found {}", value),
+        })
+    }
+
+    #[test]
+    fn test_eval_comparisons() {
+        let cases = [
+            ("1 + 2 == 3", true),
+            ("1 + 2 != 3", false),
+            ("2 < 10", true),
+            ("2 <= 2", true),
+            ("-2 > 1", false),
+            ("2^10 >= 1000", true),
+            ("1 == 1.0", true),
+            ("1 / 3 < 0.34", true),
+            ("\"EUR\" == \"EUR\"", true),
+            ("\"EUR\" != \"eur\"", true),
+            ("true == (1 < 2)", true),
+            ("false != false", false),
+        ];
+        for mode in [EvalMode::Standard, EvalMode::BigInt, EvalMode::Rational] {
+            let options = EvalOptions::new().mode(mode);
+            for (s, expected) in cases {
+                assert_eq!(eval_bool(s, &options), Ok(expected), "{} in {:?}", s, mode);
+            }
+        }
+        // exact in the rational mode only
+        let s = "0.1 + 0.2 == 0.3";
+        assert_eq!(eval_bool(s, &EvalOptions::new()), Ok(false));
+        let rational = EvalOptions::new().mode(EvalMode::Rational);
+        assert_eq!(eval_bool(s, &rational), Ok(true));
+        let big = EvalOptions::new().mode(EvalMode::BigInt);
+        assert_eq!(eval_bool("2^100 > 2^99 * 1.5", &big), Ok(true));
+    }
+
+    #[test]
+    fn test_eval_logic() {
+        let options = EvalOptions::new();
+        let cases = [
+            ("true && false", false),
+            ("true || false", true),
+            ("!false && !!true", true),
+            ("1 < 2 && 2 < 3 || false", true),
+            ("false || 1 > 2", false),
+            ("!(1 == 2)", true),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(eval_bool(s, &options), Ok(expected), "{}", s);
+        }
+
+        // the right operand is not evaluated once the left one decides
+        assert_eq!(eval_bool("false && 1 / 0 > 0", &options), Ok(false));
+        assert_eq!(eval_bool("true || 1 + \"a\" > 0", &options), Ok(true));
+        assert_eq!(
+            eval_bool("true && 1 / 0 > 0", &options),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(12, 13, 1, 13)
+            })
+        );
+
+        let program = crate::parse_program("qty > 0 && price <= 1000").unwrap();
+        let env = Environment::new()
+            .with("qty", Number::Int(3))
+            .with("price", Number::Float(999.5));
+        let result = evaluate_program(&program, &env, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Bool(true)));
+        let env = env.with("qty", Number::Int(0));
+        let result = evaluate_program(&program, &env, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_eval_factorial() {
+        assert_eq!(eval_str("0!"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("1!"), Ok(Number::Int(1)));
+        assert_eq!(eval_str("5!"), Ok(Number::Int(120)));
+        assert_eq!(eval_str("3!!"), Ok(Number::Int(720)));
+        assert_eq!(eval_str("2^3!"), Ok(Number::Int(64)));
+        assert_eq!(eval_str("-3!"), Ok(Number::Int(-6)));
+        assert_eq!(eval_str("20!"), Ok(Number::Int(2432902008176640000)));
+        assert_eq!(eval_str("4.0!"), Ok(Number::Float(24.0)));
+        assert_eq!(eval_str("(1 + 2)!"), Ok(Number::Int(6)));
+
+        let cases = [
+            ("(-1)!", Span::new(1, 3, 1, 2)),
+            ("2.5!", Span::new(0, 3, 1, 1)),
+            ("(1/2)!", Span::new(1, 4, 1, 2)),
+            ("(-2.0)!", Span::new(1, 5, 1, 2)),
+        ];
+        for (s, span) in cases {
+            assert_eq!(
+                eval_str(s),
+                Err(EvalError::InvalidFactorial { span }),
+                "{}",
+                s
+            );
+        }
+        assert_eq!(
+            eval_str("1 + 21!"),
+            Err(EvalError::Overflow {
+                span: Span::new(4, 7, 1, 5)
+            })
+        );
+        assert!(matches!(
+            eval_str("171.0!"),
+            Err(EvalError::Overflow { .. })
+        ));
+        assert!(matches!(
+            eval_str("9223372036854775807!"),
+            Err(EvalError::Overflow { .. })
+        ));
+
+        // the unbounded modes compute factorials up to MAX_BIG_INT_BITS bits
+        for mode in [EvalMode::BigInt, EvalMode::Rational] {
+            let options = EvalOptions::new().mode(mode);
+            let value = evaluate_with(&parse_expr("25!").unwrap(), &options).unwrap();
+            assert_eq!(value.to_string(), "15511210043330985984000000");
+            assert_eq!(
+                evaluate_with(&parse_expr("0!").unwrap(), &options)
+                    .unwrap()
+                    .to_string(),
+                "1"
+            );
+            assert!(matches!(
+                evaluate_with(&parse_expr("1000000!").unwrap(), &options),
+                Err(EvalError::Overflow { .. })
+            ));
+            assert!(matches!(
+                evaluate_with(&parse_expr("(-5)!").unwrap(), &options),
+                Err(EvalError::InvalidFactorial { .. })
+            ));
+        }
+        let options = EvalOptions::new().mode(EvalMode::Rational);
+        assert!(matches!(
+            evaluate_with(&parse_expr("(7/2)!").unwrap(), &options),
+            Err(EvalError::InvalidFactorial { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_abs() {
+        assert_eq!(eval_str("|3 - 10|"), Ok(Number::Int(7)));
+        assert_eq!(eval_str("|1 - |2 - 7||"), Ok(Number::Int(4)));
+        assert_eq!(eval_str("|-2.5| * [2]"), Ok(Number::Float(5.0)));
+        assert_eq!(eval_str("-|-4|^{1/2}"), Ok(Number::Float(-2.0)));
+        assert_eq!(
+            eval_str("|-(2^62) * 2|"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 13, 1, 1)
+            })
+        );
+        assert_eq!(eval_rational("|1/3 - 1|").unwrap().to_string(), "2/3");
+        assert_eq!(
+            eval_str("|true|"),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "boolean",
+                span: Span::new(1, 5, 1, 2)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_implicit_multiplication() {
+        let options = crate::ParseOptions::new().implicit_multiplication(true);
+        let eval_implicit = |s: &str| {
+            let expr = crate::parse_expr_with(s, &options).expect("Expected an expression");
+            evaluate(&expr).map(number)
+        };
+        assert_eq!(eval_implicit("2(3+4)"), Ok(Number::Int(14)));
+        assert_eq!(eval_implicit("(1+2)(3+4)"), Ok(Number::Int(21)));
+        assert_eq!(eval_implicit("12/2(3)"), Ok(Number::Int(2)));
+        assert_eq!(eval_implicit("2(3)^2"), Ok(Number::Int(18)));
+        assert_eq!(eval_implicit("2(3)-1"), Ok(Number::Int(5)));
+
+        let env = Environment::new().with("x", Number::Int(5));
+        let program = crate::parse_program_with("3x + 2sqrt(x - 1)", &options).unwrap();
+        let result = evaluate_program(&program, &env, &EvalOptions::new()).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(19))));
+    }
+
+    #[test]
+    fn test_eval_percent() {
+        assert_eq!(eval_str("50% * 200"), Ok(Number::Float(100.0)));
+        assert_eq!(eval_str("300%"), Ok(Number::Int(3)));
+        assert_eq!(eval_str("50% - 10"), Ok(Number::Float(-9.5)));
+        assert_eq!(eval_str("7 % 4"), Ok(Number::Int(3)));
+        assert_eq!(eval_str("7 % -2"), Ok(Number::Int(-1)));
+        assert_eq!(eval_str("10%%"), Ok(Number::Float(0.001)));
+        assert_eq!(eval_str("2.5%"), Ok(Number::Float(0.025)));
+
+        let options = EvalOptions::new().mode(EvalMode::Rational);
+        let value = evaluate_with(&parse_expr("15% * 30").unwrap(), &options).unwrap();
+        assert_eq!(value.to_string(), "9/2");
+        let options = EvalOptions::new().mode(EvalMode::BigInt);
+        let value = evaluate_with(&parse_expr("12300%").unwrap(), &options).unwrap();
+        assert_eq!(value, Value::Number(Number::BigInt(BigInt::from(123))));
+
+        let env = Environment::new().with("price", Number::Int(80));
+        let program = crate::parse_program("price - 25% * price").unwrap();
+        let result = evaluate_program(&program, &env, &EvalOptions::new()).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Float(60.0))));
+        assert_eq!(
+            eval_str("\"a\"%"),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                found: "string",
+                span: Span::new(0, 3, 1, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_conditionals() {
+        assert_eq!(eval_str("1 < 2 ? 10 : 20"), Ok(Number::Int(10)));
+        assert_eq!(eval_str("if 1 > 2 then 10 else 20"), Ok(Number::Int(20)));
+        assert_eq!(eval_str("false ? 1 : true ? 2 : 3"), Ok(Number::Int(2)));
+        assert_eq!(eval_str("(true ? 2 : 3) * 4"), Ok(Number::Int(8)));
+        assert_eq!(
+            eval_bool("if true then 1 == 1 else false", &EvalOptions::new()),
+            Ok(true)
+        );
+
+        // only the chosen branch is evaluated
+        let program = crate::parse_program("x == 0 ? 0 : 1 / x").unwrap();
+        let options = EvalOptions::new();
+        let env = Environment::new().with("x", Number::Int(0));
+        let result = evaluate_program(&program, &env, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(0))));
+        let env = env.with("x", Number::Int(4));
+        let result = evaluate_program(&program, &env, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Float(0.25))));
+        assert_eq!(eval_str("true ? 1 : nope(2)"), Ok(Number::Int(1)));
+        assert_eq!(
+            eval_str("false ? 1 : 1 / 0"),
+            Err(EvalError::DivisionByZero {
+                span: Span::new(16, 17, 1, 17)
+            })
+        );
+
+        // a conditional lets a recursive function stop
+        let s = "fact(n) = n <= 1 ? 1 : n * fact(n - 1)\nfact(10)";
+        let result = crate::eval_program(s, &Environment::new(), &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(3628800))));
+    }
+
+    #[test]
+    fn test_eval_bool_type_mismatch() {
+        let options = EvalOptions::new();
+        let cases = [
+            ("3 + true", "number", "boolean", Span::new(4, 8, 1, 5)),
+            ("-(1 < 2)", "number", "boolean", Span::new(2, 7, 1, 3)),
+            ("1 && true", "boolean", "number", Span::new(0, 1, 1, 1)),
+            (
+                "false || \"a\" != \"a\" || 2",
+                "boolean",
+                "number",
+                Span::new(23, 24, 1, 24),
+            ),
+            ("!0", "boolean", "number", Span::new(1, 2, 1, 2)),
+            ("1 == true", "number", "boolean", Span::new(5, 9, 1, 6)),
+            ("\"a\" != 1", "string", "number", Span::new(7, 8, 1, 8)),
+            ("true < false", "number", "boolean", Span::new(0, 4, 1, 1)),
+            ("\"a\" < \"b\"", "number", "string", Span::new(0, 3, 1, 1)),
+            ("sqrt(true)", "number", "boolean", Span::new(0, 10, 1, 1)),
+            ("1 ? 2 : 3", "boolean", "number", Span::new(0, 1, 1, 1)),
+            (
+                "if \"yes\" then 1 else 2",
+                "boolean",
+                "string",
+                Span::new(3, 8, 1, 4),
+            ),
+        ];
+        for (s, expected, found, span) in cases {
+            let expr = parse_expr(s).unwrap();
+            assert_eq!(
+                evaluate_with(&expr, &options),
+                Err(EvalError::TypeMismatch {
+                    expected,
+                    found,
+                    span
+                }),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_negative_results() {
+        assert_eq!(eval_str("2 - 3"), Ok(Number::Int(-1)));
+        assert_eq!(eval_str("1 + (2 - 3)"), Ok(Number::Int(0)));
+    }
+
+    #[test]
+    fn test_eval_overflow() {
+        // 2^63 is one past i64::MAX
+        let two_pow_63 = "(65536 * 65536 * 65536 * 32768)";
+        assert_eq!(
+            eval_str(two_pow_63),
+            Err(EvalError::Overflow {
+                span: Span::new(1, 30, 1, 2)
+            })
+        );
+        assert!(matches!(
+            eval_str("-(65536 * 65536 * 65536 * 16384) * 2 - 1"),
+            Err(EvalError::Overflow { span }) if span == Span::new(0, 40, 1, 1)
+        ));
+        assert_eq!(
+            eval_str("-(65536 * 65536 * 65536 * 16384) * 2 / -1"),
+            Err(EvalError::Overflow {
+                span: Span::new(0, 41, 1, 1)
+            })
+        );
+    }
+}
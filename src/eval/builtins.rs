@@ -0,0 +1,261 @@
+// The standard library of math functions
+use std::cmp::Ordering;
+
+use num_integer::Roots;
+use num_traits::Signed;
+
+use super::fraction::Fraction;
+use super::function::{Arity, Function};
+use super::number::Number;
+use super::value::Value;
+use super::{big_int_to_f64, EvalError};
+use crate::lex::Span;
+
+/// The functions every expression can call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Builtin {
+    Sqrt,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Min,
+    Max,
+    /// natural logarithm
+    Ln,
+    /// base 10 logarithm, or the logarithm to the base given as the second
+    /// argument
+    Log,
+    Exp,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+}
+
+impl Builtin {
+    const ALL: [Builtin; 16] = [
+        Builtin::Sqrt,
+        Builtin::Abs,
+        Builtin::Floor,
+        Builtin::Ceil,
+        Builtin::Round,
+        Builtin::Min,
+        Builtin::Max,
+        Builtin::Ln,
+        Builtin::Log,
+        Builtin::Exp,
+        Builtin::Sin,
+        Builtin::Cos,
+        Builtin::Tan,
+        Builtin::Asin,
+        Builtin::Acos,
+        Builtin::Atan,
+    ];
+
+    pub(crate) fn lookup(name: &str) -> Option<&'static Builtin> {
+        Builtin::ALL.iter().find(|builtin| builtin.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Builtin::Sqrt => "sqrt",
+            Builtin::Abs => "abs",
+            Builtin::Floor => "floor",
+            Builtin::Ceil => "ceil",
+            Builtin::Round => "round",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Ln => "ln",
+            Builtin::Log => "log",
+            Builtin::Exp => "exp",
+            Builtin::Sin => "sin",
+            Builtin::Cos => "cos",
+            Builtin::Tan => "tan",
+            Builtin::Asin => "asin",
+            Builtin::Acos => "acos",
+            Builtin::Atan => "atan",
+        }
+    }
+
+    fn arity(self) -> Arity {
+        match self {
+            Builtin::Min | Builtin::Max => Arity::AtLeast(1),
+            Builtin::Log => Arity::Between(1, 2),
+            _ => Arity::Exact(1),
+        }
+    }
+
+    /// calls the function, `args` must be as many as its arity accepts.
+    /// Integer arguments give exact results where there is one: `sqrt(16)`
+    /// is 4 but `sqrt(2)` is floating point. Errors are reported at `span`.
+    fn apply(self, args: &[Number], span: Span) -> Result<Number, EvalError> {
+        let undefined = || EvalError::Undefined { span };
+        match (self, args) {
+            (Builtin::Sqrt, [x]) => sqrt(x, span),
+            (Builtin::Abs, [x]) => abs(x, span),
+            (Builtin::Floor | Builtin::Ceil | Builtin::Round, [x]) => Ok(self.round_to_integer(x)),
+            (Builtin::Min, [first, rest @ ..]) => Ok(rest
+                .iter()
+                .fold(
+                    first,
+                    |min, x| if compare(x, min).is_lt() { x } else { min },
+                )
+                .clone()),
+            (Builtin::Max, [first, rest @ ..]) => Ok(rest
+                .iter()
+                .fold(
+                    first,
+                    |max, x| if compare(x, max).is_gt() { x } else { max },
+                )
+                .clone()),
+            (Builtin::Ln, [x]) if x.to_f64() > 0.0 => float_result(x.to_f64().ln(), span),
+            (Builtin::Log, [x]) if x.to_f64() > 0.0 => float_result(x.to_f64().log10(), span),
+            (Builtin::Log, [x, base]) if x.to_f64() > 0.0 => {
+                let base = base.to_f64();
+                if base <= 0.0 || base == 1.0 {
+                    return Err(undefined());
+                }
+                float_result(x.to_f64().ln() / base.ln(), span)
+            }
+            (Builtin::Ln | Builtin::Log, _) => Err(undefined()),
+            (Builtin::Exp, [x]) => float_result(x.to_f64().exp(), span),
+            (Builtin::Sin, [x]) => float_result(x.to_f64().sin(), span),
+            (Builtin::Cos, [x]) => float_result(x.to_f64().cos(), span),
+            (Builtin::Tan, [x]) => float_result(x.to_f64().tan(), span),
+            (Builtin::Asin, [x]) => float_result(x.to_f64().asin(), span),
+            (Builtin::Acos, [x]) => float_result(x.to_f64().acos(), span),
+            (Builtin::Atan, [x]) => float_result(x.to_f64().atan(), span),
+            // the caller checks the arity
+            _ => Err(undefined()),
+        }
+    }
+
+    /// `x` rounded by `floor`, `ceil` or `round` to an integer of the same
+    /// number type. `round` takes half way cases away from zero.
+    fn round_to_integer(self, x: &Number) -> Number {
+        match x {
+            Number::Int(_) | Number::BigInt(_) => x.clone(),
+            Number::Rational(r) => {
+                let r = r.as_big_rational();
+                let rounded = match self {
+                    Builtin::Floor => r.floor(),
+                    Builtin::Ceil => r.ceil(),
+                    _ => r.round(),
+                };
+                Number::Rational(Fraction::from(rounded))
+            }
+            Number::Float(x) => {
+                let rounded = match self {
+                    Builtin::Floor => x.floor(),
+                    Builtin::Ceil => x.ceil(),
+                    _ => x.round(),
+                };
+                // so that ceil(-0.5) prints as 0 rather than -0
+                Number::Float(if rounded == 0.0 { 0.0 } else { rounded })
+            }
+        }
+    }
+}
+
+impl Function for Builtin {
+    fn name(&self) -> &str {
+        Builtin::name(*self)
+    }
+
+    fn arity(&self) -> Arity {
+        Builtin::arity(*self)
+    }
+
+    /// every built-in function takes numbers only. Errors have a default
+    /// span, the evaluator points them at the call.
+    fn call(&self, args: &[Value]) -> Result<Value, EvalError> {
+        let numbers = args
+            .iter()
+            .map(|arg| arg.expect_number().cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.apply(&numbers, Span::default()).map(Value::Number)
+    }
+}
+
+/// a floating point result, NaN means the function is not defined for the
+/// argument and infinity that the result is out of range
+fn float_result(value: f64, span: Span) -> Result<Number, EvalError> {
+    if value.is_nan() {
+        return Err(EvalError::Undefined { span });
+    }
+    if value.is_infinite() {
+        return Err(EvalError::Overflow { span });
+    }
+    Ok(Number::Float(value))
+}
+
+/// orders two numbers, exactly when they have the same type
+fn compare(lhs: &Number, rhs: &Number) -> Ordering {
+    match (lhs, rhs) {
+        (Number::Int(lhs), Number::Int(rhs)) => lhs.cmp(rhs),
+        (Number::BigInt(lhs), Number::BigInt(rhs)) => lhs.cmp(rhs),
+        (Number::Rational(lhs), Number::Rational(rhs)) => lhs.cmp(rhs),
+        (lhs, rhs) => lhs.to_f64().total_cmp(&rhs.to_f64()),
+    }
+}
+
+fn sqrt(x: &Number, span: Span) -> Result<Number, EvalError> {
+    let undefined = EvalError::Undefined { span };
+    match x {
+        Number::Int(n) if *n < 0 => Err(undefined),
+        Number::Int(n) => {
+            let root = n.sqrt();
+            if root * root == *n {
+                return Ok(Number::Int(root));
+            }
+            float_result((*n as f64).sqrt(), span)
+        }
+        Number::BigInt(n) if n.is_negative() => Err(undefined),
+        Number::BigInt(n) => {
+            let root = n.sqrt();
+            if &root * &root == *n {
+                return Ok(Number::BigInt(root));
+            }
+            float_result(big_int_to_f64(n).sqrt(), span)
+        }
+        Number::Rational(r) if r.numer().is_negative() => Err(undefined),
+        Number::Rational(r) => {
+            let (numer_root, denom_root) = (r.numer().sqrt(), r.denom().sqrt());
+            if &numer_root * &numer_root == *r.numer() && &denom_root * &denom_root == *r.denom() {
+                // both roots are positive, so the denominator is not zero
+                if let Some(root) = Fraction::new(numer_root, denom_root) {
+                    return Ok(Number::Rational(root));
+                }
+            }
+            float_result(r.to_f64().sqrt(), span)
+        }
+        Number::Float(x) => float_result(x.sqrt(), span),
+    }
+}
+
+pub(crate) fn abs(x: &Number, span: Span) -> Result<Number, EvalError> {
+    match x {
+        Number::Int(n) => n
+            .checked_abs()
+            .map(Number::Int)
+            .ok_or(EvalError::Overflow { span }),
+        Number::BigInt(n) => Ok(Number::BigInt(n.abs())),
+        Number::Rational(r) => Ok(Number::Rational(Fraction::from(r.as_big_rational().abs()))),
+        Number::Float(x) => Ok(Number::Float(x.abs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert!(compare(&Number::Int(2), &Number::Int(3)).is_lt());
+        assert!(compare(&Number::Int(2), &Number::Float(1.5)).is_gt());
+        assert!(compare(&Number::Float(-0.5), &Number::Int(0)).is_lt());
+    }
+}
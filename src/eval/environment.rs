@@ -0,0 +1,134 @@
+// Values bound to variable names, and the functions defined next to them
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::function::{Function, UserFunction};
+use super::value::Value;
+
+/// A function added to an environment, either by the application or by a
+/// program
+#[derive(Clone)]
+enum Callable {
+    Registered(Rc<dyn Function>),
+    Defined(Rc<UserFunction>),
+}
+
+impl PartialEq for Callable {
+    /// registered functions are equal when they are the same registration,
+    /// shared by clones of an environment
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Callable::Registered(f), Callable::Registered(g)) => Rc::ptr_eq(f, g),
+            (Callable::Defined(f), Callable::Defined(g)) => f == g,
+            _ => false,
+        }
+    }
+}
+
+/// The variables an expression is evaluated in, see
+/// [`evaluate_in`](super::evaluate_in), and the functions it can call on
+/// top of the built-in ones. Bindings are kept sorted by name.
+#[derive(Clone, Default, PartialEq)]
+pub struct Environment {
+    bindings: BTreeMap<String, Value>,
+    functions: BTreeMap<String, Callable>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// binds `name` to `value`, replacing any earlier binding
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    /// binds `name` to `value` and returns the value it was bound to before
+    pub fn set(&mut self, name: &str, value: impl Into<Value>) -> Option<Value> {
+        self.bindings.insert(name.to_string(), value.into())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.bindings.get(name)
+    }
+
+    /// every binding, in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.bindings
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// adds `function`, replacing any registered, defined or built-in
+    /// function of the same name
+    pub fn with_function(mut self, function: impl Function + 'static) -> Self {
+        self.register(function);
+        self
+    }
+
+    /// adds `function`, replacing any registered, defined or built-in
+    /// function of the same name
+    pub fn register(&mut self, function: impl Function + 'static) {
+        self.functions.insert(
+            function.name().to_string(),
+            Callable::Registered(Rc::new(function)),
+        );
+    }
+
+    /// the registered function called `name`, built-in functions are not
+    /// included
+    pub fn function(&self, name: &str) -> Option<&dyn Function> {
+        match self.functions.get(name)? {
+            Callable::Registered(function) => Some(function.as_ref()),
+            Callable::Defined(_) => None,
+        }
+    }
+
+    /// every registered function, in name order
+    pub fn functions(&self) -> impl Iterator<Item = &dyn Function> {
+        self.functions
+            .values()
+            .filter_map(|callable| match callable {
+                Callable::Registered(function) => Some(function.as_ref()),
+                Callable::Defined(_) => None,
+            })
+    }
+
+    /// adds `function`, replacing any registered, defined or built-in
+    /// function of the same name. Programs call this for each
+    /// `name(params) = body` statement.
+    pub fn define(&mut self, function: UserFunction) {
+        self.functions
+            .insert(function.name.clone(), Callable::Defined(Rc::new(function)));
+    }
+
+    /// the function called `name` that a program defined
+    pub fn definition(&self, name: &str) -> Option<&UserFunction> {
+        match self.functions.get(name)? {
+            Callable::Defined(function) => Some(function),
+            Callable::Registered(_) => None,
+        }
+    }
+
+    /// every function a program defined, in name order
+    pub fn definitions(&self) -> impl Iterator<Item = &UserFunction> {
+        self.functions
+            .values()
+            .filter_map(|callable| match callable {
+                Callable::Defined(function) => Some(function.as_ref()),
+                Callable::Registered(_) => None,
+            })
+    }
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Environment")
+            .field("bindings", &self.bindings)
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,139 @@
+// Exact fractions for the rational evaluation mode
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+
+/// An exact fraction, always normalised to lowest terms with a positive
+/// denominator. Displays as `p/q`, or just `p` when it is an integer.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fraction(BigRational);
+
+impl Fraction {
+    /// `None` when `denom` is zero
+    pub fn new(numer: BigInt, denom: BigInt) -> Option<Self> {
+        if denom.is_zero() {
+            return None;
+        }
+        Some(Fraction(BigRational::new(numer, denom)))
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        self.0.numer()
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        self.0.denom()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    /// the fraction as a mixed number, `3 1/2` for 7/2. Proper fractions and
+    /// integers print as they do with `Display`.
+    pub fn to_mixed(&self) -> String {
+        let whole = self.0.trunc();
+        let rest = (&self.0 - &whole).abs();
+        if whole.is_zero() || rest.is_zero() {
+            return self.to_string();
+        }
+        format!("{} {}", whole, rest)
+    }
+
+    /// the fraction in decimal notation with exactly `digits` digits after
+    /// the point, rounding half away from zero: `0.667` for 2/3 with 3
+    /// digits
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let scale = BigInt::from(10).pow(digits);
+        let (quotient, remainder) = (self.numer().abs() * &scale).div_rem(self.denom());
+        let rounded = if remainder * 2 >= *self.denom() {
+            quotient + 1
+        } else {
+            quotient
+        };
+        let sign = if self.0.is_negative() && !rounded.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        let (whole, fraction) = rounded.div_rem(&scale);
+        if digits == 0 {
+            return format!("{}{}", sign, whole);
+        }
+        format!("{}{}.{:0>digits$}", sign, whole, fraction)
+    }
+
+    pub(crate) fn as_big_rational(&self) -> &BigRational {
+        &self.0
+    }
+}
+
+impl From<BigRational> for Fraction {
+    fn from(ratio: BigRational) -> Self {
+        Fraction(ratio)
+    }
+}
+
+impl From<BigInt> for Fraction {
+    fn from(n: BigInt) -> Self {
+        Fraction(BigRational::from_integer(n))
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numer())
+        } else {
+            write!(f, "{}/{}", self.numer(), self.denom())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::One;
+
+    fn fraction(numer: i64, denom: i64) -> Fraction {
+        Fraction::new(BigInt::from(numer), BigInt::from(denom)).unwrap()
+    }
+
+    #[test]
+    fn test_normalised() {
+        let f = fraction(6, -4);
+        assert_eq!(f.numer(), &BigInt::from(-3));
+        assert_eq!(f.denom(), &BigInt::from(2));
+        assert_eq!(f.to_string(), "-3/2");
+        assert_eq!(fraction(8, 4).to_string(), "2");
+        assert_eq!(Fraction::new(BigInt::one(), BigInt::zero()), None);
+    }
+
+    #[test]
+    fn test_to_mixed() {
+        assert_eq!(fraction(7, 2).to_mixed(), "3 1/2");
+        assert_eq!(fraction(-7, 2).to_mixed(), "-3 1/2");
+        assert_eq!(fraction(1, 3).to_mixed(), "1/3");
+        assert_eq!(fraction(-1, 3).to_mixed(), "-1/3");
+        assert_eq!(fraction(6, 3).to_mixed(), "2");
+    }
+
+    #[test]
+    fn test_to_decimal() {
+        assert_eq!(fraction(1, 3).to_decimal(5), "0.33333");
+        assert_eq!(fraction(2, 3).to_decimal(3), "0.667");
+        assert_eq!(fraction(-2, 3).to_decimal(3), "-0.667");
+        assert_eq!(fraction(7, 2).to_decimal(0), "4");
+        assert_eq!(fraction(1, 8).to_decimal(2), "0.13");
+        assert_eq!(fraction(-1, 1000).to_decimal(2), "0.00");
+        assert_eq!(fraction(1, 40).to_decimal(3), "0.025");
+        assert_eq!(fraction(5, 1).to_decimal(2), "5.00");
+    }
+}
@@ -0,0 +1,153 @@
+// Functions expressions can call, and how embedders add their own
+use std::fmt;
+
+use super::value::Value;
+use super::EvalError;
+use crate::ast::Expr;
+
+/// The number of arguments a function takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    /// from the first to the second, both included
+    Between(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == n,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    /// as in "expected 1 or 2 arguments"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match *self {
+            Arity::Exact(n) => write!(f, "{} {}", n, plural(n)),
+            Arity::Between(min, max) if max == min + 1 => write!(f, "{} or {} arguments", min, max),
+            Arity::Between(min, max) => write!(f, "{} to {} arguments", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {} {}", min, plural(min)),
+        }
+    }
+}
+
+/// A function expressions can call, registered in an
+/// [`Environment`](super::Environment) with
+/// [`register`](super::Environment::register).
+///
+/// The evaluator checks the number of arguments against [`arity`](Self::arity)
+/// before calling. Errors returned from [`call`](Self::call) with a default
+/// span, such as [`EvalError::custom`] and the errors of
+/// [`Value::expect_number`], are pointed at the call.
+pub trait Function {
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> Arity;
+
+    /// the result for `args`, of which there are as many as the arity
+    /// accepts. Numbers arrive in the type of the
+    /// [`EvalMode`](super::EvalMode) and a returned number is converted to
+    /// it.
+    fn call(&self, args: &[Value]) -> Result<Value, EvalError>;
+}
+
+/// A [`Function`] made from a name, an arity and a closure, for embedders
+/// that do not need a type of their own
+pub struct NativeFunction<F> {
+    name: String,
+    arity: Arity,
+    f: F,
+}
+
+impl<F> NativeFunction<F>
+where
+    F: Fn(&[Value]) -> Result<Value, EvalError>,
+{
+    pub fn new(name: &str, arity: Arity, f: F) -> Self {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            f,
+        }
+    }
+}
+
+impl<F> Function for NativeFunction<F>
+where
+    F: Fn(&[Value]) -> Result<Value, EvalError>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn call(&self, args: &[Value]) -> Result<Value, EvalError> {
+        (self.f)(args)
+    }
+}
+
+impl<F> fmt::Debug for NativeFunction<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A function defined by a program with `name(params) = body`, see
+/// [`Environment::define`](super::Environment::define)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFunction {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expr,
+}
+
+impl UserFunction {
+    pub fn arity(&self) -> Arity {
+        Arity::Exact(self.params.len())
+    }
+}
+
+impl fmt::Display for UserFunction {
+    /// prints the definition as it could be entered, `f(x, y) = x * y`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.params.join(", "),
+            self.body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Exact(1).accepts(1));
+        assert!(!Arity::Exact(1).accepts(2));
+        assert!(Arity::Between(1, 2).accepts(2));
+        assert!(!Arity::Between(1, 2).accepts(0));
+        assert!(Arity::AtLeast(1).accepts(5));
+        assert!(!Arity::AtLeast(1).accepts(0));
+        assert_eq!(Arity::Exact(1).to_string(), "1 argument");
+        assert_eq!(Arity::Exact(2).to_string(), "2 arguments");
+        assert_eq!(Arity::Between(1, 2).to_string(), "1 or 2 arguments");
+        assert_eq!(Arity::Between(1, 3).to_string(), "1 to 3 arguments");
+        assert_eq!(Arity::AtLeast(1).to_string(), "at least 1 argument");
+    }
+}
@@ -0,0 +1,54 @@
+// Values the evaluator computes with
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use super::fraction::Fraction;
+
+/// The value of an expression. Integer arithmetic stays exact and only
+/// becomes floating point when a decimal literal is involved or a result has
+/// no integer value, such as `1 / 2`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Number {
+    Int(i64),
+    /// an integer of any size, used by [`EvalMode::BigInt`](super::EvalMode::BigInt)
+    BigInt(BigInt),
+    /// an exact fraction, used by [`EvalMode::Rational`](super::EvalMode::Rational)
+    Rational(Fraction),
+    Float(f64),
+}
+
+impl Number {
+    /// the value as an f64, rounding integers too large to be exact and
+    /// infinite for integers beyond the range of an f64
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(r) => r.to_f64(),
+            Number::Float(x) => *x,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(n) => *n == 0,
+            Number::BigInt(n) => n.is_zero(),
+            Number::Rational(r) => r.numer().is_zero(),
+            Number::Float(x) => *x == 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::BigInt(n) => write!(f, "{}", n),
+            Number::Rational(r) => write!(f, "{}", r),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
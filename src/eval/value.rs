@@ -0,0 +1,117 @@
+// Values expressions evaluate to and functions take and return
+use std::fmt;
+
+use super::number::Number;
+use super::EvalError;
+use crate::lex::Span;
+
+/// The value of an expression. Arithmetic only works on numbers and `&&`,
+/// `||` and `!` only on booleans, which comparisons give. Strings exist to
+/// be passed to and returned from functions, as in `fx(amount, "EUR")`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Value {
+    Number(Number),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    /// the name of the value's type, as used in [`EvalError::TypeMismatch`]
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "boolean",
+            Value::Str(_) => "string",
+        }
+    }
+
+    pub fn as_number(&self) -> Option<&Number> {
+        match self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// the number, or a [`EvalError::TypeMismatch`] for a function to return.
+    /// The evaluator points the error at the call.
+    pub fn expect_number(&self) -> Result<&Number, EvalError> {
+        self.as_number().ok_or_else(|| self.mismatch("number"))
+    }
+
+    /// the boolean, or a [`EvalError::TypeMismatch`] for a function to
+    /// return. The evaluator points the error at the call.
+    pub fn expect_bool(&self) -> Result<bool, EvalError> {
+        self.as_bool().ok_or_else(|| self.mismatch("boolean"))
+    }
+
+    /// the string, or a [`EvalError::TypeMismatch`] for a function to return.
+    /// The evaluator points the error at the call.
+    pub fn expect_str(&self) -> Result<&str, EvalError> {
+        self.as_str().ok_or_else(|| self.mismatch("string"))
+    }
+
+    fn mismatch(&self, expected: &'static str) -> EvalError {
+        EvalError::TypeMismatch {
+            expected,
+            found: self.type_name(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(n: Number) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl PartialEq<Number> for Value {
+    fn eq(&self, other: &Number) -> bool {
+        self.as_number() == Some(other)
+    }
+}
+
+impl fmt::Display for Value {
+    /// numbers and booleans print as they do on their own, strings in
+    /// quotes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", crate::lex::quote(s)),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+// Feed random input through the lexers, parser and evaluator, none of which
+// may panic however malformed the input is.
+use std::panic;
+
+use crate::ast::Program;
+use crate::cfg::mathparser::MathParser;
+use crate::cfg::ParseOptions;
+use crate::diagnostics::{Diagnostic, Style};
+use crate::eval::{evaluate_program, Environment, EvalMode, EvalOptions, Number};
+use crate::lex::{lex_multi_digit, simple};
+
+const ITERATIONS: usize = 2000;
+
+/// xorshift64, so that a failing input can be reproduced from its seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// runs every stage over `s`, rendering any error as a diagnostic
+fn run_all_stages(s: &str) {
+    if let Err(e) = simple::lexer(s) {
+        let _ = Diagnostic::from(&e).render(s, Style::Ansi);
+    }
+
+    let my_lex = match lex_multi_digit::lexer(s) {
+        Ok(my_lex) => my_lex,
+        Err(e) => {
+            let _ = Diagnostic::from(&e).render(s, Style::Plain);
+            return;
+        }
+    };
+    for implicit_multiplication in [false, true] {
+        let parse_options = ParseOptions::new().implicit_multiplication(implicit_multiplication);
+        let mut math_parser = MathParser::new(s, my_lex.get_tokens(), parse_options);
+        match math_parser.parse() {
+            Ok(parse_node) => {
+                let _ = parse_node.to_string();
+                let _ = parse_node.free_variables();
+                let program =
+                    Program::try_from(&parse_node).expect("Expected a parsed tree to lower");
+                let _ = program.to_string();
+                let env = Environment::new()
+                    .with("e", Number::Float(std::f64::consts::E))
+                    .with("x", Number::Int(i64::MAX))
+                    .with("b", Number::Int(0));
+                for mode in [EvalMode::Standard, EvalMode::BigInt, EvalMode::Rational] {
+                    let options = EvalOptions::new().mode(mode);
+                    if let Err(e) = evaluate_program(&program, &env, &options) {
+                        let _ = Diagnostic::from(&e).render(s, Style::Plain);
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = Diagnostic::from(&e).render(s, Style::Plain);
+            }
+        }
+    }
+}
+
+fn assert_no_panic(s: &str) {
+    let result = panic::catch_unwind(|| run_all_stages(s));
+    assert!(result.is_ok(), "panicked on input {:?}", s);
+}
+
+#[test]
+fn test_fuzz_random_bytes() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..ITERATIONS {
+        let len = rng.below(32);
+        let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        assert_no_panic(&String::from_utf8_lossy(&bytes));
+    }
+}
+
+#[test]
+fn test_fuzz_grammar_characters() {
+    // mostly valid characters, so that the input gets past the lexer and
+    // exercises the parser and evaluator
+    let alphabet: Vec<char> = "0123456789.e_xob+-*/%^()[]{},;=<>!&|?:\"\\  \t\n\r"
+        .chars()
+        .collect();
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..ITERATIONS {
+        let len = rng.below(24);
+        let s: String = (0..len)
+            .map(|_| alphabet[rng.below(alphabet.len())])
+            .collect();
+        assert_no_panic(&s);
+    }
+}
+
+#[test]
+fn test_fuzz_edge_cases() {
+    let cases = [
+        "",
+        " ",
+        "\n",
+        "(",
+        ")",
+        "()",
+        "+",
+        "1+",
+        "(1))",
+        "((1)",
+        "4294967295",
+        "4294967296",
+        "99999999999999999999999",
+        "4294967295 * 4294967295",
+        "1 / 0",
+        "0 - 1",
+        "-",
+        "--",
+        "1 * -",
+        "-(-(-1))",
+        "-(65536 * 65536 * 65536 * 32768)",
+        "^",
+        "2^",
+        "***",
+        "2 ** ** 2",
+        "2 ^ 4294967295",
+        "(-1) ^ 4294967295",
+        "2 ^ -1",
+        "%",
+        "1 % 0",
+        "1 // 0",
+        "///",
+        "-(2 ^ 62) * 2 // -1",
+        ".",
+        "1.",
+        ".e1",
+        "1e",
+        "1e+",
+        "1e999",
+        "1e-999",
+        "0.0 / 0.0",
+        "(-1) ^ 0.5",
+        "1.5 % 0",
+        "0x",
+        "0x_",
+        "0b2",
+        "0xffffffffffffffffffff",
+        "0o7_7_",
+        "1__2",
+        "_",
+        "9^9^9^9",
+        "(0-2)^(2^62)",
+        "2^(2^20) // 0",
+        "\u{0}",
+        "١٢٣",
+        "1 ＋ 2",
+        "x",
+        "x + 1",
+        "-x - 2",
+        "e ^ x",
+        "1 / b",
+        "b ^ -1",
+        "2e",
+        "_1",
+        "unbound * also_unbound",
+        ";",
+        ";;1;;",
+        "\n\n1\n\n",
+        "a =",
+        "= 1",
+        "a = a",
+        "a = 1; a = a * a; a",
+        "(a\n=\n1)",
+        "x = x + 1; x",
+        "f(",
+        "f()",
+        "f(,)",
+        "sqrt(",
+        "sqrt(-1)",
+        "sqrt(x)",
+        "min()",
+        "max(1, 2,",
+        "log(0, 0)",
+        "exp(x)",
+        "abs(-x - 1)",
+        "round(1e308 * 10)",
+        "\"",
+        "\"\\",
+        "\"\\\"",
+        "\"\\x\"",
+        "\"\u{e9}\" + 1",
+        "-\"\"",
+        "s = \"a\"; s",
+        "sqrt(\"4\")",
+        "\"a\"(1)",
+        "x(b) = x(b); x(1)",
+        "x(b) = 1 + (2 * (3 - (4 / (5 + x(b))))); x(1)",
+        "x(b) = b(x); x(1)",
+        "x(b, b) = b",
+        "x() = ",
+        "x(b) = e\nx(1) + x",
+        "b(x) = x; b = b(b(1)); b(b)",
+        "==",
+        "1 ==",
+        "= = 1",
+        "x = = 1",
+        "!",
+        "!!",
+        "1 !",
+        "!1",
+        "1 < 2 < 3",
+        "(1 < 2) < 3",
+        "1 < (2 < 3)",
+        "true",
+        "true = 1",
+        "true(1)",
+        "-true",
+        "true + 1",
+        "false && x / b > 0",
+        "true || true && false",
+        "& |",
+        "a &&& b",
+        "a ||| b",
+        "x == x && b != b || !(e >= e)",
+        "\"a\" < \"b\"",
+        "sqrt(1 < 2)",
+        "x(b) = b > 0 && x(b - 1); x(3)",
+        "?",
+        ":",
+        "? :",
+        "1 ? 2",
+        "true ? 1 :",
+        "true ? : 1",
+        "b ? 1 : 2",
+        "b == 0 ? 0 : 1 / b",
+        "b != 0 ? 1 / b : 1 / b",
+        "true ? 1 : 2 : 3",
+        "if",
+        "if true",
+        "if true then",
+        "if true then 1 else",
+        "if then else",
+        "if = 1",
+        "if(1)",
+        "1 + if true then 1 else 2",
+        "x(b) = b > 0 ? b * x(b - 1) : 1; x(20)",
+        "x(b) = if b > 0 then x(b + 1) else 0; x(1)",
+        "!!!",
+        "1!!!!",
+        "x!",
+        "b!",
+        "(-x)!",
+        "e!",
+        "170.0!",
+        "0.5!",
+        "1e300!",
+        "99999!",
+        "(2^62)!",
+        "5!=120",
+        "5! = 1",
+        "%%",
+        "1%%%",
+        "% 1",
+        "1 % % 1",
+        "50% -",
+        "50% then",
+        "x%\n-1",
+        "(x%\n-1)",
+        "\"a\"!",
+        "true%",
+        "2^-3!",
+        "2x",
+        "2 3",
+        "2x3",
+        "(1)(2)(3)",
+        "x(1)(2)",
+        "1/2b",
+        "2b^-1",
+        "-2x!",
+        "2(",
+        "(b)b",
+        "e e e",
+        "2e",
+        "2 e3",
+        "3sqrt(4)",
+        "2\"a\"",
+        "2 true",
+        "f(x) = 2x; f(3)f(4)",
+        "[",
+        "}",
+        "[)",
+        "(]",
+        "{[(1)]}",
+        "{[(1])}",
+        "|",
+        "||",
+        "|||",
+        "| |",
+        "|x",
+        "x|",
+        "|-x|",
+        "||x||",
+        "|||x|||",
+        "|x||y|",
+        "|x| |y|",
+        "2|x|",
+        "|(x|)",
+        "(|x)|",
+        "|b| == 0 || |x| > 1",
+        "|\"a\"|",
+        "|x|!",
+        "|x%|",
+        "|-(2^62) * 2|",
+        "f[1]",
+        "f(x] = 1",
+        "x = [1\n]",
+        "|1\n|",
+    ];
+    for s in cases {
+        assert_no_panic(s);
+    }
+}
+
+#[test]
+fn test_fuzz_deep_nesting() {
+    for depth in [10, 100, 1_000, 10_000] {
+        let s = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_no_panic(&s);
+        let unclosed = "(".repeat(depth);
+        assert_no_panic(&unclosed);
+    }
+}
+
+#[test]
+fn test_fuzz_long_operator_chains() {
+    for len in [10, 100, 1_000, 10_000] {
+        let sum = vec!["1"; len].join(" + ");
+        assert_no_panic(&sum);
+        let quotient = vec!["2"; len].join("/");
+        assert_no_panic(&quotient);
+        let negations = format!("{}1", "-".repeat(len));
+        assert_no_panic(&negations);
+        let powers = vec!["2"; len].join("**");
+        assert_no_panic(&powers);
+        let ternaries = format!("{}1", "true ? 1 : ".repeat(len));
+        assert_no_panic(&ternaries);
+        let ifs = format!("{}1", "if true then 1 else ".repeat(len));
+        assert_no_panic(&ifs);
+        let bars = format!("{}1{}", "|-".repeat(len), "|".repeat(len));
+        assert_no_panic(&bars);
+    }
+}
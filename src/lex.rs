@@ -1,2 +1,100 @@
 pub(crate) mod lex_multi_digit;
+pub(crate) mod number;
 pub(crate) mod simple;
+
+use std::fmt;
+
+use simple::LexToken;
+
+/// Location of a token (or parse node) in the input string. `start` and `end`
+/// are byte offsets with `end` exclusive, `line` and `column` are 1-based and
+/// point at the first character of the span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// returns a span starting where `self` starts and ending where `other`
+    /// ends
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+/// A lex token together with the part of the input it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: LexToken,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: LexToken, span: Span) -> Self {
+        Token { kind, span }
+    }
+}
+
+/// Errors found while splitting the input into lex tokens
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum LexError {
+    InvalidCharacter {
+        c: char,
+        span: Span,
+    },
+    /// a malformed number literal, such as `0x` or `1__000`
+    InvalidNumber {
+        reason: String,
+        span: Span,
+    },
+    /// a string literal with no closing quote or an unknown escape
+    InvalidString {
+        reason: String,
+        span: Span,
+    },
+}
+
+impl LexError {
+    /// the part of the input where the error was found
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidCharacter { span, .. }
+            | LexError::InvalidNumber { span, .. }
+            | LexError::InvalidString { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::InvalidCharacter { c, .. } => write!(f, "Invalid character found: {}", c),
+            LexError::InvalidNumber { reason, .. } => write!(f, "Invalid number: {}", reason),
+            LexError::InvalidString { reason, .. } => write!(f, "Invalid string: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// `s` as a string literal the lexer reads back as `s`: in double quotes,
+/// with '"' and '\' escaped by a '\'
+pub(crate) fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
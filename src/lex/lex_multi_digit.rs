@@ -1,112 +1,497 @@
+use super::number::{NumLiteral, Radix};
 use super::simple::LexToken;
-use std::error;
-use std::num::ParseIntError;
+use super::{LexError, Span, Token};
 
 #[derive(Debug)]
 pub(crate) struct Lexer {
     s: String,
     input_chars: Vec<char>,
-    tokens: Vec<LexToken>,
+    // (byte offset, line, column) of every input character, followed by the
+    // position just past the end of the input
+    char_positions: Vec<(usize, usize, usize)>,
+    tokens: Vec<Token>,
+    // whether the last '|' came right after an operand. Only the parser
+    // knows whether it opens or closes an absolute value, one after an
+    // operand is taken to close one when lexing a '!', '%' or line break
+    // after it.
+    bar_after_operand: bool,
 }
 
 impl Lexer {
     pub(crate) fn new(s: &str) -> Self {
+        let mut char_positions = Vec::new();
+        let (mut line, mut column) = (1, 1);
+        for (offset, c) in s.char_indices() {
+            char_positions.push((offset, line, column));
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        char_positions.push((s.len(), line, column));
+
         Lexer {
             s: s.to_string(),
             input_chars: s.chars().collect(),
+            char_positions,
             tokens: vec![],
+            bar_after_operand: false,
         }
     }
 
-    pub(crate) fn get_tokens(&self) -> &[LexToken] {
-        return self.tokens.as_slice();
+    pub(crate) fn get_tokens(&self) -> &[Token] {
+        self.tokens.as_slice()
+    }
+
+    /// span covering the input characters from `start_pos` up to (but not
+    /// including) `end_pos`
+    fn span(&self, start_pos: usize, end_pos: usize) -> Span {
+        let (start, line, column) = self.char_positions[start_pos];
+        let (end, _, _) = self.char_positions[end_pos];
+        Span::new(start, end, line, column)
+    }
+
+    fn push_token(&mut self, kind: LexToken, start_pos: usize, end_pos: usize) {
+        let span = self.span(start_pos, end_pos);
+        self.tokens.push(Token::new(kind, span));
+    }
+
+    fn invalid_number(&self, start_pos: usize, end_pos: usize, reason: String) -> LexError {
+        LexError::InvalidNumber {
+            reason,
+            span: self.span(start_pos, end_pos),
+        }
     }
 
-    // Parse the sequence of digits starting from `pos` and return a lex token.
-    fn get_number(&mut self, pos: usize) -> Result<(LexToken, usize), ParseIntError> {
-        println!("=> get number from position {pos}");
+    /// Skips the run of digits (in `radix`) starting at `pos` and returns the
+    /// position just past it. A '_' may separate two digits, anywhere else it
+    /// is an error in the number starting at `start_pos`.
+    fn skip_digits(&self, start_pos: usize, pos: usize, radix: u32) -> Result<usize, LexError> {
+        let is_digit = |pos: usize| self.input_chars.get(pos).is_some_and(|c| c.is_digit(radix));
         let mut curr_pos = pos;
-        let mut num_vec: Vec<char> = vec![];
+        while is_digit(curr_pos) {
+            curr_pos += 1;
+            if self.input_chars.get(curr_pos) == Some(&'_') {
+                if !is_digit(curr_pos + 1) {
+                    return Err(self.invalid_number(
+                        start_pos,
+                        curr_pos + 1,
+                        String::from("a '_' separator must be between two digits"),
+                    ));
+                }
+                curr_pos += 1;
+            }
+        }
+        Ok(curr_pos)
+    }
+
+    /// Reads a `0x`, `0o` or `0b` literal starting at `pos`. Every letter,
+    /// digit and '_' up to the next operator belongs to the literal, so that
+    /// `0b102` is reported as a bad binary number rather than split in two.
+    fn get_radix_number(&self, pos: usize, radix: Radix) -> Result<usize, LexError> {
+        let digits_pos = pos + 2;
+        let mut end_pos = digits_pos;
+        while self
+            .input_chars
+            .get(end_pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            end_pos += 1;
+        }
+        if end_pos == digits_pos {
+            return Err(self.invalid_number(
+                pos,
+                end_pos,
+                format!("missing digits after '{}'", radix.prefix()),
+            ));
+        }
+        if let Some(c) = self.input_chars[digits_pos..end_pos]
+            .iter()
+            .find(|c| **c != '_' && !c.is_digit(radix.base()))
+        {
+            return Err(self.invalid_number(
+                pos,
+                end_pos,
+                format!("invalid {} digit '{}'", radix.name(), c),
+            ));
+        }
+        if self.skip_digits(pos, digits_pos, radix.base()).ok() != Some(end_pos) {
+            return Err(self.invalid_number(
+                pos,
+                end_pos,
+                String::from("a '_' separator must be between two digits"),
+            ));
+        }
+        Ok(end_pos)
+    }
+
+    /// Reads the number literal starting at `pos`: digits with an optional
+    /// fraction (`3.14`, `.5`, `10.`) and exponent (`1e-9`, `6.02E23`), or an
+    /// integer with a `0x`, `0o` or `0b` prefix. Digits may be grouped with
+    /// '_' separators (`1_000_000`). Returns the token and the position just
+    /// past the literal.
+    fn get_number(&mut self, pos: usize) -> Result<(LexToken, usize), LexError> {
+        trace!("=> get number from position {pos}");
+        let radix = match (self.input_chars.get(pos), self.input_chars.get(pos + 1)) {
+            (Some('0'), Some(c)) => Radix::from_prefix_char(*c),
+            _ => None,
+        };
+        let curr_pos = match radix {
+            Some(radix) => self.get_radix_number(pos, radix)?,
+            None => self.get_decimal_number(pos)?,
+        };
+        trace!("* end of a number sequence, pos: {curr_pos} *");
+
+        let text: String = self.input_chars[pos..curr_pos].iter().collect();
+        Ok((LexToken::Num(NumLiteral::new(&text)), curr_pos))
+    }
+
+    /// Reads a decimal literal starting at `pos`, see `get_number`
+    fn get_decimal_number(&self, pos: usize) -> Result<usize, LexError> {
+        let is_digit = |pos: usize| self.input_chars.get(pos).is_some_and(char::is_ascii_digit);
+        let mut curr_pos = self.skip_digits(pos, pos, 10)?;
+        if self.input_chars.get(curr_pos) == Some(&'.') {
+            curr_pos = self.skip_digits(pos, curr_pos + 1, 10)?;
+        }
+        // an 'e' only starts an exponent when digits follow it, possibly
+        // after a sign, otherwise the number ends before the 'e'
+        if matches!(self.input_chars.get(curr_pos), Some('e' | 'E')) {
+            let digits_pos = match self.input_chars.get(curr_pos + 1) {
+                Some('+' | '-') => curr_pos + 2,
+                _ => curr_pos + 1,
+            };
+            if is_digit(digits_pos) {
+                curr_pos = self.skip_digits(pos, digits_pos, 10)?;
+            }
+        }
+        Ok(curr_pos)
+    }
+
+    /// Reads the identifier starting at `pos`: a letter or '_' followed by
+    /// letters, digits and '_'. Returns the position just past it.
+    fn get_identifier(&self, pos: usize) -> usize {
+        let mut end_pos = pos + 1;
+        while self
+            .input_chars
+            .get(end_pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            end_pos += 1;
+        }
+        end_pos
+    }
+
+    /// Reads the string literal whose opening '"' is at `pos`. Inside it
+    /// `\"` stands for a quote and `\\` for a backslash, and the literal
+    /// must end on the line it starts on. Returns the token and the position
+    /// just past the closing quote.
+    fn get_string(&self, pos: usize) -> Result<(LexToken, usize), LexError> {
+        let mut value = String::new();
+        let mut curr_pos = pos + 1;
         loop {
-            if let Some(c) = self.input_chars.get(curr_pos) {
-                match *c {
-                    '0'..='9' => {
-                        num_vec.push(*c);
-                        curr_pos += 1;
+            match self.input_chars.get(curr_pos) {
+                Some('"') => return Ok((LexToken::Str(value), curr_pos + 1)),
+                Some('\\') => match self.input_chars.get(curr_pos + 1) {
+                    Some(c @ ('"' | '\\')) => {
+                        value.push(*c);
+                        curr_pos += 2;
                     }
-                    _ => {
-                        // not a number character
-                        println!("* end of a number sequence, pos: {curr_pos} *");
-                        break;
+                    Some(c) if *c != '\n' => {
+                        return Err(LexError::InvalidString {
+                            reason: format!("unknown escape '\\{}'", c),
+                            span: self.span(curr_pos, curr_pos + 2),
+                        });
                     }
+                    _ => curr_pos += 1,
+                },
+                Some(c) if *c != '\n' => {
+                    value.push(*c);
+                    curr_pos += 1;
+                }
+                _ => {
+                    return Err(LexError::InvalidString {
+                        reason: String::from("missing closing '\"'"),
+                        span: self.span(pos, curr_pos),
+                    });
                 }
-            } else {
-                // no input characters left, possibly end of input?
-                println!("* no input character left, pos: {curr_pos} *");
-                break;
             }
+        }
+    }
 
-            if (curr_pos + 1) > self.input_chars.len() {
-                println!("* eos: {curr_pos}");
-                break;
-            }
+    /// whether the last token ends an operand, so that a '!' or '%' after
+    /// it is a postfix operator
+    fn ends_operand(&self) -> bool {
+        match self.tokens.last().map(|t| &t.kind) {
+            Some(LexToken::Bar(_)) => self.bar_after_operand,
+            tok => matches!(
+                tok,
+                Some(
+                    LexToken::Num(_)
+                        | LexToken::Ident(_)
+                        | LexToken::Str(_)
+                        | LexToken::Bool(_)
+                        | LexToken::RightParen(_)
+                        | LexToken::Factorial(_)
+                        | LexToken::Percent(_)
+                )
+            ),
         }
+    }
+
+    /// A line break only separates statements outside of brackets and
+    /// right after an operand, so that a long expression can continue on the
+    /// next line after an operator. Blank lines are not separators either.
+    /// Inside bars, which the lexer leaves to the parser, the parser reads
+    /// one as whitespace.
+    fn ends_statement(&self, open_brackets: &[char]) -> bool {
+        open_brackets.is_empty() && self.ends_operand()
+    }
 
-        let num_vec_s: Vec<String> = num_vec.iter().map(|c| c.to_string()).collect();
-        let num_s: String = num_vec_s.join("");
-        let num: u32 = num_s.parse::<u32>()?;
+    /// Whether the '%' at `pos` is a postfix percent rather than the
+    /// remainder operator. It is the remainder when an operand follows it,
+    /// including one with a prefix '-', '+' or '!' written right against it,
+    /// so `7 % -2` is a remainder and `50% - 10` a percent.
+    fn is_percent(&self, pos: usize, open_brackets: &[char]) -> bool {
+        if !self.ends_operand() {
+            return false;
+        }
+        // a line break outside of brackets ends the statement
+        let next_pos = (pos + 1..self.input_chars.len()).find(|p| {
+            let c = self.input_chars[*p];
+            !c.is_whitespace() || (c == '\n' && open_brackets.is_empty())
+        });
+        !next_pos.is_some_and(|p| self.starts_operand(p))
+    }
 
-        return Ok((LexToken::Num(num), curr_pos));
+    /// Whether an operand starts at `pos`, the first character after a '%'
+    /// that is not whitespace
+    fn starts_operand(&self, pos: usize) -> bool {
+        let after = self.input_chars.get(pos + 1);
+        match self.input_chars[pos] {
+            // 'then' and 'else' follow a whole operand rather than start one
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let end_pos = self.get_identifier(pos);
+                let word: String = self.input_chars[pos..end_pos].iter().collect();
+                !matches!(word.as_str(), "then" | "else")
+            }
+            '.' | '(' | '[' | '{' | '"' => true,
+            // '||' follows a whole operand. Whether another '|' opens an
+            // absolute value is up to the parser, it is taken to here when
+            // an operand starts after it on the same line, so `|7 % |-2||`
+            // is a remainder and `|x%| - |y|` a percent.
+            '|' if after == Some(&'|') => false,
+            '|' => (pos + 1..self.input_chars.len())
+                .find(|p| {
+                    let c = self.input_chars[*p];
+                    !c.is_whitespace() || c == '\n'
+                })
+                .is_some_and(|p| self.input_chars[p] == '|' || self.starts_operand(p)),
+            '-' | '+' => after.is_some_and(|c| !c.is_whitespace()),
+            '!' => after.is_some_and(|c| !c.is_whitespace() && *c != '='),
+            _ => false,
+        }
     }
 
-    pub(crate) fn tokenise(&mut self) -> Result<(), Box<dyn error::Error>> {
+    pub(crate) fn tokenise(&mut self) -> Result<(), LexError> {
+        // the opening brackets not closed yet, innermost last
+        let mut open_brackets: Vec<char> = Vec::new();
         let mut next_pos = 0;
-        println!("=> tokenising string {} from pos: {next_pos}", self.s);
+        trace!("=> tokenising string {} from pos: {next_pos}", self.s);
         loop {
             if let Some(c) = self.input_chars.get(next_pos) {
                 match *c {
-                    '0'..='9' => {
+                    // a '.' starts a number when a digit follows, as in '.5'
+                    '0'..='9' | '.'
+                        if c.is_ascii_digit()
+                            || self
+                                .input_chars
+                                .get(next_pos + 1)
+                                .is_some_and(char::is_ascii_digit) =>
+                    {
                         let (n, pos) = self.get_number(next_pos)?;
-                        println!("n: {n}");
-                        self.tokens.push(n);
+                        trace!("n: {n}");
+                        self.push_token(n, next_pos, pos);
                         // get_number fn already has moved the pointer
                         next_pos = pos;
-                        println!("next_pos: {next_pos}");
+                        trace!("next_pos: {next_pos}");
                     }
                     '+' => {
-                        self.tokens.push(LexToken::Add('+'));
+                        self.push_token(LexToken::Add('+'), next_pos, next_pos + 1);
                         next_pos += 1;
                     }
                     '-' => {
-                        self.tokens.push(LexToken::Subtract('-'));
+                        self.push_token(LexToken::Subtract('-'), next_pos, next_pos + 1);
                         next_pos += 1;
                     }
                     '*' => {
-                        self.tokens.push(LexToken::Multi('*'));
+                        // '**' is the power operator, a single '*' multiplies
+                        if self.input_chars.get(next_pos + 1) == Some(&'*') {
+                            self.push_token(LexToken::Power("**"), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else {
+                            self.push_token(LexToken::Multi('*'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    '^' => {
+                        self.push_token(LexToken::Power("^"), next_pos, next_pos + 1);
                         next_pos += 1;
                     }
                     '/' => {
-                        self.tokens.push(LexToken::Div('/'));
+                        // '//' is integer division, a single '/' divides
+                        if self.input_chars.get(next_pos + 1) == Some(&'/') {
+                            self.push_token(LexToken::IntDiv("//"), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else {
+                            self.push_token(LexToken::Div('/'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    '%' => {
+                        let token = if self.is_percent(next_pos, &open_brackets) {
+                            LexToken::Percent('%')
+                        } else {
+                            LexToken::Modulo('%')
+                        };
+                        self.push_token(token, next_pos, next_pos + 1);
                         next_pos += 1;
                     }
-                    '(' => {
-                        self.tokens.push(LexToken::LeftParen('('));
+                    bracket @ ('(' | '[' | '{') => {
+                        self.push_token(LexToken::LeftParen(bracket), next_pos, next_pos + 1);
+                        open_brackets.push(bracket);
                         next_pos += 1;
                     }
-                    ')' => {
-                        self.tokens.push(LexToken::RightParen(')'));
+                    bracket @ (')' | ']' | '}') => {
+                        self.push_token(LexToken::RightParen(bracket), next_pos, next_pos + 1);
+                        // a bracket of another kind is left open, the parser
+                        // reports the mismatch
+                        let opening = match bracket {
+                            ')' => '(',
+                            ']' => '[',
+                            _ => '{',
+                        };
+                        if open_brackets.last() == Some(&opening) {
+                            open_brackets.pop();
+                        }
                         next_pos += 1;
                     }
-                    c if c.is_whitespace() => {
-                        println!("whitespace -- ignore");
+                    // '||' is a logical or after an operand, the parser
+                    // reads it as two '|' when it closes an absolute value,
+                    // as in `|x - |y||`
+                    '|' if self.input_chars.get(next_pos + 1) == Some(&'|')
+                        && self.ends_operand() =>
+                    {
+                        self.push_token(LexToken::Or("||"), next_pos, next_pos + 2);
+                        next_pos += 2;
+                    }
+                    '|' => {
+                        self.bar_after_operand = self.ends_operand();
+                        self.push_token(LexToken::Bar('|'), next_pos, next_pos + 1);
+                        next_pos += 1;
+                    }
+                    '=' => {
+                        // '==' compares, a single '=' assigns
+                        if self.input_chars.get(next_pos + 1) == Some(&'=') {
+                            self.push_token(LexToken::Equal("=="), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else {
+                            self.push_token(LexToken::Assign('='), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    '!' => {
+                        // '!' after an operand is a factorial, but `5!=120`
+                        // compares 5 with 120
+                        if self.input_chars.get(next_pos + 1) == Some(&'=') {
+                            self.push_token(LexToken::NotEqual("!="), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else if self.ends_operand() {
+                            self.push_token(LexToken::Factorial('!'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        } else {
+                            self.push_token(LexToken::Not('!'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    '<' => {
+                        if self.input_chars.get(next_pos + 1) == Some(&'=') {
+                            self.push_token(LexToken::LessEqual("<="), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else {
+                            self.push_token(LexToken::Less('<'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    '>' => {
+                        if self.input_chars.get(next_pos + 1) == Some(&'=') {
+                            self.push_token(LexToken::GreaterEqual(">="), next_pos, next_pos + 2);
+                            next_pos += 2;
+                        } else {
+                            self.push_token(LexToken::Greater('>'), next_pos, next_pos + 1);
+                            next_pos += 1;
+                        }
+                    }
+                    // '&&' has no single character form
+                    '&' if self.input_chars.get(next_pos + 1) == Some(&'&') => {
+                        self.push_token(LexToken::And("&&"), next_pos, next_pos + 2);
+                        next_pos += 2;
+                    }
+                    '?' => {
+                        self.push_token(LexToken::Question('?'), next_pos, next_pos + 1);
+                        next_pos += 1;
+                    }
+                    ':' => {
+                        self.push_token(LexToken::Colon(':'), next_pos, next_pos + 1);
                         next_pos += 1;
                     }
-                    '\n' | '\r' => {
-                        println!("newline");
-                        self.tokens.push(LexToken::Newline);
+                    ';' => {
+                        self.push_token(LexToken::Semicolon(';'), next_pos, next_pos + 1);
+                        next_pos += 1;
+                    }
+                    ',' => {
+                        self.push_token(LexToken::Comma(','), next_pos, next_pos + 1);
+                        next_pos += 1;
+                    }
+                    c if c.is_ascii_alphabetic() || c == '_' => {
+                        let end_pos = self.get_identifier(next_pos);
+                        let name: String = self.input_chars[next_pos..end_pos].iter().collect();
+                        trace!("identifier: {name}");
+                        let token = match name.as_str() {
+                            "true" => LexToken::Bool(true),
+                            "false" => LexToken::Bool(false),
+                            "if" => LexToken::If,
+                            "then" => LexToken::Then,
+                            "else" => LexToken::Else,
+                            _ => LexToken::Ident(name),
+                        };
+                        self.push_token(token, next_pos, end_pos);
+                        next_pos = end_pos;
+                    }
+                    '"' => {
+                        let (s, pos) = self.get_string(next_pos)?;
+                        trace!("string: {s}");
+                        self.push_token(s, next_pos, pos);
+                        next_pos = pos;
+                    }
+                    '\n' => {
+                        trace!("newline");
+                        if self.ends_statement(&open_brackets) {
+                            self.push_token(LexToken::Newline, next_pos, next_pos + 1);
+                        }
+                        next_pos += 1;
+                    }
+                    c if c.is_whitespace() => {
+                        trace!("whitespace -- ignore");
+                        next_pos += 1;
                     }
-                    _ => {
-                        todo!()
+                    c => {
+                        trace!("=> invalid character found: {}", c);
+                        return Err(LexError::InvalidCharacter {
+                            c,
+                            span: self.span(next_pos, next_pos + 1),
+                        });
                     }
                 }
             }
@@ -119,27 +504,31 @@ impl Lexer {
     }
 }
 
-pub(crate) fn lexer(s: &str) -> Result<Lexer, Box<dyn error::Error>> {
+pub(crate) fn lexer(s: &str) -> Result<Lexer, LexError> {
     let mut my_lexer = Lexer::new(s);
-    let _ = my_lexer.tokenise()?;
+    my_lexer.tokenise()?;
 
-    return Ok(my_lexer);
+    Ok(my_lexer)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn num(text: &str) -> LexToken {
+        LexToken::Num(NumLiteral::new(text))
+    }
+
     #[test]
     fn test_lexer_add_expr() {
         let s = "(12+34)";
         let my_lex = lexer(s).unwrap();
         let tokens = my_lex.get_tokens();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(12));
-        assert_eq!(tokens[2], LexToken::Add('+'));
-        assert_eq!(tokens[3], LexToken::Num(34));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("12"));
+        assert_eq!(tokens[2].kind, LexToken::Add('+'));
+        assert_eq!(tokens[3].kind, num("34"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
     }
 
     #[test]
@@ -147,11 +536,11 @@ mod tests {
         let s = "(12 -345)";
         let my_lex = lexer(s).unwrap();
         let tokens = my_lex.get_tokens();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(12));
-        assert_eq!(tokens[2], LexToken::Subtract('-'));
-        assert_eq!(tokens[3], LexToken::Num(345));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("12"));
+        assert_eq!(tokens[2].kind, LexToken::Subtract('-'));
+        assert_eq!(tokens[3].kind, num("345"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
     }
 
     #[test]
@@ -159,14 +548,542 @@ mod tests {
         let s = "12 -345* (555 / 678) ";
         let my_lex = lexer(s).unwrap();
         let tokens = my_lex.get_tokens();
-        assert_eq!(tokens[0], LexToken::Num(12));
-        assert_eq!(tokens[1], LexToken::Subtract('-'));
-        assert_eq!(tokens[2], LexToken::Num(345));
-        assert_eq!(tokens[3], LexToken::Multi('*'));
-        assert_eq!(tokens[4], LexToken::LeftParen('('));
-        assert_eq!(tokens[5], LexToken::Num(555));
-        assert_eq!(tokens[6], LexToken::Div('/'));
-        assert_eq!(tokens[7], LexToken::Num(678));
-        assert_eq!(tokens[8], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, num("12"));
+        assert_eq!(tokens[1].kind, LexToken::Subtract('-'));
+        assert_eq!(tokens[2].kind, num("345"));
+        assert_eq!(tokens[3].kind, LexToken::Multi('*'));
+        assert_eq!(tokens[4].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[5].kind, num("555"));
+        assert_eq!(tokens[6].kind, LexToken::Div('/'));
+        assert_eq!(tokens[7].kind, num("678"));
+        assert_eq!(tokens[8].kind, LexToken::RightParen(')'));
+    }
+
+    #[test]
+    fn test_lexer_unary_signs() {
+        // signs are never folded into the number, the parser decides whether
+        // they are unary or binary
+        let s = "--3 * -+4";
+        let my_lex = lexer(s).unwrap();
+        let kinds: Vec<&LexToken> = my_lex.get_tokens().iter().map(|t| &t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &LexToken::Subtract('-'),
+                &LexToken::Subtract('-'),
+                &num("3"),
+                &LexToken::Multi('*'),
+                &LexToken::Subtract('-'),
+                &LexToken::Add('+'),
+                &num("4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_power() {
+        let s = "2**3 ^ 4 * *5";
+        let my_lex = lexer(s).unwrap();
+        let tokens = my_lex.get_tokens();
+        assert_eq!(tokens[1].kind, LexToken::Power("**"));
+        assert_eq!(tokens[1].span, Span::new(1, 3, 1, 2));
+        assert_eq!(tokens[3].kind, LexToken::Power("^"));
+        assert_eq!(tokens[3].span, Span::new(5, 6, 1, 6));
+        // separated by a space these are two multiplications
+        assert_eq!(tokens[5].kind, LexToken::Multi('*'));
+        assert_eq!(tokens[6].kind, LexToken::Multi('*'));
+        assert_eq!(tokens[7].kind, num("5"));
+    }
+
+    #[test]
+    fn test_lexer_modulo_and_int_div() {
+        let s = "7 // 2 % 3 / 4";
+        let my_lex = lexer(s).unwrap();
+        let tokens = my_lex.get_tokens();
+        assert_eq!(tokens[1].kind, LexToken::IntDiv("//"));
+        assert_eq!(tokens[1].span, Span::new(2, 4, 1, 3));
+        assert_eq!(tokens[3].kind, LexToken::Modulo('%'));
+        assert_eq!(tokens[3].span, Span::new(7, 8, 1, 8));
+        assert_eq!(tokens[5].kind, LexToken::Div('/'));
+    }
+
+    #[test]
+    fn test_lexer_token_spans() {
+        let s = "12 -345* (555 / 678) ";
+        let my_lex = lexer(s).unwrap();
+        let tokens = my_lex.get_tokens();
+        assert_eq!(tokens[0].span, Span::new(0, 2, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(3, 4, 1, 4));
+        assert_eq!(tokens[2].span, Span::new(4, 7, 1, 5));
+        assert_eq!(tokens[3].span, Span::new(7, 8, 1, 8));
+        assert_eq!(tokens[4].span, Span::new(9, 10, 1, 10));
+        assert_eq!(tokens[8].span, Span::new(19, 20, 1, 20));
+        assert_eq!(&s[tokens[5].span.start..tokens[5].span.end], "555");
+    }
+
+    #[test]
+    fn test_lexer_multi_line_spans() {
+        let s = "1 +\n  23";
+        let my_lex = lexer(s).unwrap();
+        let tokens = my_lex.get_tokens();
+        assert_eq!(tokens[0].span, Span::new(0, 1, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(2, 3, 1, 3));
+        assert_eq!(tokens[2].span, Span::new(6, 8, 2, 3));
+    }
+
+    #[test]
+    fn test_lexer_statement_separators() {
+        let s = "a = 1; b = (a\n + 2)\n\nb *\n 3\n";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        let kinds: Vec<&LexToken> = tokens.iter().map(|t| &t.kind).collect();
+        let ident = |name: &str| LexToken::Ident(String::from(name));
+        assert_eq!(
+            kinds,
+            vec![
+                &ident("a"),
+                &LexToken::Assign('='),
+                &num("1"),
+                &LexToken::Semicolon(';'),
+                &ident("b"),
+                &LexToken::Assign('='),
+                &LexToken::LeftParen('('),
+                &ident("a"),
+                &LexToken::Add('+'),
+                &num("2"),
+                &LexToken::RightParen(')'),
+                &LexToken::Newline,
+                &ident("b"),
+                &LexToken::Multi('*'),
+                &num("3"),
+                &LexToken::Newline,
+            ]
+        );
+        assert_eq!(tokens[11].span, Span::new(19, 20, 2, 6));
+        assert_eq!(tokens[15].span, Span::new(27, 28, 5, 3));
+        // a carriage return is whitespace, "\r\n" is a single separator
+        let tokens = lexer("1\r\n2").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[1].kind, LexToken::Newline);
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    fn test_lexer_function_call() {
+        let s = "max(1,\n x)";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        let kinds: Vec<&LexToken> = tokens.iter().map(|t| &t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &LexToken::Ident(String::from("max")),
+                &LexToken::LeftParen('('),
+                &num("1"),
+                &LexToken::Comma(','),
+                &LexToken::Ident(String::from("x")),
+                &LexToken::RightParen(')'),
+            ]
+        );
+        assert_eq!(tokens[3].span, Span::new(5, 6, 1, 6));
+    }
+
+    #[test]
+    fn test_lexer_spans_are_byte_offsets() {
+        // the non-breaking space is two bytes long but a single column
+        let s = "1\u{a0}+ 2";
+        let my_lex = lexer(s).unwrap();
+        let tokens = my_lex.get_tokens();
+        assert_eq!(tokens[1].span, Span::new(3, 4, 1, 3));
+        assert_eq!(tokens[2].span, Span::new(5, 6, 1, 5));
+    }
+
+    #[test]
+    fn test_lexer_invalid_character() {
+        let s = "12 + $";
+        let err = lexer(s).unwrap_err();
+        assert_eq!(
+            err,
+            LexError::InvalidCharacter {
+                c: '$',
+                span: Span::new(5, 6, 1, 6)
+            }
+        );
+    }
+
+    #[test]
+    fn test_lexer_identifiers() {
+        let s = "price * qty_2 + _tax";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, LexToken::Ident(String::from("price")));
+        assert_eq!(tokens[0].span, Span::new(0, 5, 1, 1));
+        assert_eq!(tokens[2].kind, LexToken::Ident(String::from("qty_2")));
+        assert_eq!(tokens[2].span, Span::new(8, 13, 1, 9));
+        assert_eq!(tokens[4].kind, LexToken::Ident(String::from("_tax")));
+
+        // a number ends where the letters start
+        let tokens = lexer("2x").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, num("2"));
+        assert_eq!(tokens[1].kind, LexToken::Ident(String::from("x")));
+        assert!(matches!(
+            lexer("x \u{e9}").unwrap_err(),
+            LexError::InvalidCharacter { c: '\u{e9}', .. }
+        ));
+    }
+
+    #[test]
+    fn test_lexer_strings() {
+        let s = "fx(10, \"EUR\") + \"say \\\"hi\\\" \\\\ \u{e9}\"";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[4].kind, LexToken::Str(String::from("EUR")));
+        assert_eq!(tokens[4].span, Span::new(7, 12, 1, 8));
+        assert_eq!(
+            tokens[7].kind,
+            LexToken::Str(String::from("say \"hi\" \\ \u{e9}"))
+        );
+        assert_eq!(tokens[7].kind.to_string(), "\"say \\\"hi\\\" \\\\ \u{e9}\"");
+
+        // a string ends a statement like any other operand
+        let kinds: Vec<LexToken> = lexer("c = \"\"\n1")
+            .unwrap()
+            .get_tokens()
+            .iter()
+            .map(|t| t.kind.clone())
+            .collect();
+        assert_eq!(kinds[2], LexToken::Str(String::new()));
+        assert_eq!(kinds[3], LexToken::Newline);
+
+        let err = lexer("fx(1, \"EUR)\n").unwrap_err();
+        assert!(
+            matches!(&err, LexError::InvalidString { reason, .. } if reason == "missing closing '\"'")
+        );
+        assert_eq!(err.span(), Span::new(6, 11, 1, 7));
+        let err = lexer(r#""a\nb""#).unwrap_err();
+        assert!(
+            matches!(&err, LexError::InvalidString { reason, .. } if reason == "unknown escape '\\n'")
+        );
+        assert_eq!(err.span(), Span::new(2, 4, 1, 3));
+        assert!(matches!(
+            lexer("\"trailing \\").unwrap_err(),
+            LexError::InvalidString { .. }
+        ));
+    }
+
+    #[test]
+    fn test_lexer_comparisons_and_logic() {
+        let s = "a == 1 != b<=c < d >= e>f && !true || false = x";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        let kinds: Vec<&LexToken> = tokens.iter().map(|t| &t.kind).collect();
+        let ident = |name: &str| LexToken::Ident(String::from(name));
+        assert_eq!(
+            kinds,
+            vec![
+                &ident("a"),
+                &LexToken::Equal("=="),
+                &num("1"),
+                &LexToken::NotEqual("!="),
+                &ident("b"),
+                &LexToken::LessEqual("<="),
+                &ident("c"),
+                &LexToken::Less('<'),
+                &ident("d"),
+                &LexToken::GreaterEqual(">="),
+                &ident("e"),
+                &LexToken::Greater('>'),
+                &ident("f"),
+                &LexToken::And("&&"),
+                &LexToken::Not('!'),
+                &LexToken::Bool(true),
+                &LexToken::Or("||"),
+                &LexToken::Bool(false),
+                &LexToken::Assign('='),
+                &ident("x"),
+            ]
+        );
+        assert_eq!(tokens[1].span, Span::new(2, 4, 1, 3));
+        assert_eq!(tokens[15].span, Span::new(30, 34, 1, 31));
+        // keywords are whole identifiers only
+        let tokens = lexer("trueish").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, ident("trueish"));
+        assert_eq!(
+            lexer("a & b").unwrap_err(),
+            LexError::InvalidCharacter {
+                c: '&',
+                span: Span::new(2, 3, 1, 3)
+            }
+        );
+    }
+
+    #[test]
+    fn test_lexer_conditionals() {
+        let s = "c ? 1 : if d then x else y";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        let kinds: Vec<&LexToken> = tokens.iter().map(|t| &t.kind).collect();
+        let ident = |name: &str| LexToken::Ident(String::from(name));
+        assert_eq!(
+            kinds,
+            vec![
+                &ident("c"),
+                &LexToken::Question('?'),
+                &num("1"),
+                &LexToken::Colon(':'),
+                &LexToken::If,
+                &ident("d"),
+                &LexToken::Then,
+                &ident("x"),
+                &LexToken::Else,
+                &ident("y"),
+            ]
+        );
+        assert_eq!(tokens[6].span, Span::new(13, 17, 1, 14));
+        let tokens = lexer("iffy + elsewhere").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, ident("iffy"));
+        assert_eq!(tokens[2].kind, ident("elsewhere"));
+    }
+
+    #[test]
+    fn test_lexer_postfix_operators() {
+        let kinds = |s: &str| -> Vec<LexToken> {
+            lexer(s)
+                .unwrap()
+                .get_tokens()
+                .iter()
+                .map(|t| t.kind.clone())
+                .collect()
+        };
+        let percent = LexToken::Percent('%');
+        let modulo = LexToken::Modulo('%');
+        let cases = [
+            ("50% * 200", 1, &percent),
+            ("50%", 1, &percent),
+            ("(x)% + 1", 3, &percent),
+            ("50% - 10", 1, &percent),
+            ("c ? 5% : 1", 3, &percent),
+            ("if c then 5% else 1", 4, &percent),
+            ("7 % 2", 1, &modulo),
+            ("7%x", 1, &modulo),
+            ("7 % -2", 1, &modulo),
+            ("7 % (2)", 1, &modulo),
+            ("7 % .5", 1, &modulo),
+            ("7 % !b", 1, &modulo),
+            ("7 % then1", 1, &modulo),
+            ("% 2", 0, &modulo),
+        ];
+        for (s, index, expected) in cases {
+            assert_eq!(&kinds(s)[index], expected, "{}", s);
+        }
+        // a line break ends the statement outside of parentheses only
+        assert_eq!(kinds("a = 5%\nb")[3], percent);
+        assert_eq!(kinds("(7 %\n2)")[2], modulo);
+
+        assert_eq!(
+            kinds("3!! + (1)! != !b"),
+            vec![
+                num("3"),
+                LexToken::Factorial('!'),
+                LexToken::Factorial('!'),
+                LexToken::Add('+'),
+                LexToken::LeftParen('('),
+                num("1"),
+                LexToken::RightParen(')'),
+                LexToken::Factorial('!'),
+                LexToken::NotEqual("!="),
+                LexToken::Not('!'),
+                LexToken::Ident(String::from("b")),
+            ]
+        );
+        assert_eq!(kinds("5!=120")[1], LexToken::NotEqual("!="));
+        // a postfix operator ends an operand, so a line break after it
+        // separates statements
+        assert_eq!(kinds("3!\n4%\n5")[2], LexToken::Newline);
+        assert_eq!(kinds("3!\n4%\n5")[5], LexToken::Newline);
+    }
+
+    #[test]
+    fn test_lexer_brackets_and_bars() {
+        let kinds = |s: &str| -> Vec<LexToken> {
+            lexer(s)
+                .unwrap()
+                .get_tokens()
+                .iter()
+                .map(|t| t.kind.clone())
+                .collect()
+        };
+        assert_eq!(
+            kinds("[1]{2}"),
+            vec![
+                LexToken::LeftParen('['),
+                num("1"),
+                LexToken::RightParen(']'),
+                LexToken::LeftParen('{'),
+                num("2"),
+                LexToken::RightParen('}'),
+            ]
+        );
+
+        // whether a '|' opens or closes an absolute value is up to the parser
+        let bar = LexToken::Bar('|');
+        assert_eq!(
+            kinds("|x - |y| |"),
+            vec![
+                bar.clone(),
+                LexToken::Ident(String::from("x")),
+                LexToken::Subtract('-'),
+                bar.clone(),
+                LexToken::Ident(String::from("y")),
+                bar.clone(),
+                bar.clone(),
+            ]
+        );
+        assert_eq!(kinds("||x| - 1|")[..2], [bar.clone(), bar.clone()]);
+        // '||' after an operand is a logical or
+        assert_eq!(kinds("a || b")[1], LexToken::Or("||"));
+        assert_eq!(kinds("|a||")[2], LexToken::Or("||"));
+        // a '!' or line break after a '|' after an operand follows an operand
+        assert_eq!(kinds("|x|!")[3], LexToken::Factorial('!'));
+        assert_eq!(kinds("|!x|")[1], LexToken::Not('!'));
+        assert_eq!(kinds("|x|\n1")[3], LexToken::Newline);
+
+        // a line break inside brackets does not end the statement
+        assert_eq!(kinds("[1\n]").len(), 3);
+        assert_eq!(kinds("7 % [2]")[1], LexToken::Modulo('%'));
+        assert_eq!(kinds("7 % |x|")[1], LexToken::Modulo('%'));
+        // a '%' before a '|' is a remainder when an operand starts after the
+        // '|', the parser takes it for one as well when no bar is open
+        assert_eq!(kinds("|7 % |x||")[2], LexToken::Modulo('%'));
+        assert_eq!(kinds("|7 % |-2||")[2], LexToken::Modulo('%'));
+        assert_eq!(kinds("|x%|")[2], LexToken::Percent('%'));
+        assert_eq!(kinds("|x%| - |y|")[2], LexToken::Percent('%'));
+        assert_eq!(kinds("|x%| - 1")[2], LexToken::Percent('%'));
+        assert_eq!(kinds("7 % | - 2|")[1], LexToken::Percent('%'));
+        // a stray '|' is left for the parser to report
+        assert!(lexer("a | b").is_ok());
+    }
+
+    #[test]
+    fn test_lexer_large_number_keeps_text() {
+        // range checks are left to the evaluator
+        let s = "1 + 99999999999999999999";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[2].kind, num("99999999999999999999"));
+        assert_eq!(tokens[2].span, Span::new(4, 24, 1, 5));
+    }
+
+    #[test]
+    fn test_lexer_decimal_numbers() {
+        let s = "3.14 + .5 * 10. - 1e-9 / 6.02E23";
+        let my_lex = lexer(s).unwrap();
+        let kinds: Vec<&LexToken> = my_lex.get_tokens().iter().map(|t| &t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &num("3.14"),
+                &LexToken::Add('+'),
+                &num(".5"),
+                &LexToken::Multi('*'),
+                &num("10."),
+                &LexToken::Subtract('-'),
+                &num("1e-9"),
+                &LexToken::Div('/'),
+                &num("6.02E23"),
+            ]
+        );
+        assert_eq!(my_lex.get_tokens()[2].span, Span::new(7, 9, 1, 8));
+    }
+
+    #[test]
+    fn test_lexer_radix_numbers() {
+        let s = "0xFF + 0o17*0b1010 - 1_000_000";
+        let tokens = lexer(s).unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, num("0xFF"));
+        assert_eq!(tokens[0].span, Span::new(0, 4, 1, 1));
+        assert_eq!(tokens[2].kind, num("0o17"));
+        assert_eq!(tokens[4].kind, num("0b1010"));
+        assert_eq!(tokens[6].kind, num("1_000_000"));
+        assert_eq!(tokens[6].span, Span::new(21, 30, 1, 22));
+        let LexToken::Num(n) = &tokens[0].kind else {
+            panic!("Expected a number");
+        };
+        assert_eq!(n.radix(), Radix::Hexadecimal);
+    }
+
+    #[test]
+    fn test_lexer_invalid_radix_numbers() {
+        let cases = [
+            ("0x", "missing digits after '0x'", Span::new(0, 2, 1, 1)),
+            (
+                "1 + 0b102",
+                "invalid binary digit '2'",
+                Span::new(4, 9, 1, 5),
+            ),
+            ("0o8", "invalid octal digit '8'", Span::new(0, 3, 1, 1)),
+            (
+                "0xFG",
+                "invalid hexadecimal digit 'G'",
+                Span::new(0, 4, 1, 1),
+            ),
+            (
+                "0x_F",
+                "a '_' separator must be between two digits",
+                Span::new(0, 4, 1, 1),
+            ),
+            (
+                "0xF__F",
+                "a '_' separator must be between two digits",
+                Span::new(0, 6, 1, 1),
+            ),
+        ];
+        for (s, reason, span) in cases {
+            assert_eq!(
+                lexer(s).unwrap_err(),
+                LexError::InvalidNumber {
+                    reason: reason.to_string(),
+                    span
+                },
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_lexer_digit_separators() {
+        let tokens = lexer("1_000.000_1e1_0").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].kind, num("1_000.000_1e1_0"));
+
+        for s in ["12_ + 1", "12__0", "12_.5"] {
+            assert_eq!(
+                lexer(s).unwrap_err(),
+                LexError::InvalidNumber {
+                    reason: String::from("a '_' separator must be between two digits"),
+                    span: Span::new(0, 3, 1, 1)
+                },
+                "{}",
+                s
+            );
+        }
+        // a '_' that does not follow a digit is not part of the number
+        let tokens = lexer("1._5").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, num("1."));
+        assert_eq!(tokens[1].kind, LexToken::Ident(String::from("_5")));
+    }
+
+    #[test]
+    fn test_lexer_number_edge_cases() {
+        // an 'e' without exponent digits is not part of the number
+        let tokens = lexer("2e").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, num("2"));
+        assert_eq!(tokens[1].kind, LexToken::Ident(String::from("e")));
+        assert_eq!(tokens[1].span, Span::new(1, 2, 1, 2));
+        let tokens = lexer("1e+").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[1].kind, LexToken::Ident(String::from("e")));
+        assert_eq!(tokens[2].kind, LexToken::Add('+'));
+        // a '.' without digits after it is not a number
+        assert!(matches!(
+            lexer("1 + .").unwrap_err(),
+            LexError::InvalidCharacter { c: '.', .. }
+        ));
+        // a second '.' starts the next number
+        let tokens = lexer("1.2.3").unwrap().get_tokens().to_vec();
+        assert_eq!(tokens[0].kind, num("1.2"));
+        assert_eq!(tokens[1].kind, num(".3"));
     }
 }
@@ -0,0 +1,290 @@
+// Number literals, kept as the text they were written as
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Pow};
+
+/// Largest power of ten, either way, that `NumLiteral::to_big_rational`
+/// expands. 10^100000 already takes over 300000 bits.
+const MAX_EXACT_EXPONENT: u32 = 100_000;
+
+/// The base a number literal is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    pub fn base(self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        }
+    }
+
+    /// the prefix a literal in this base starts with, empty for decimal
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Radix::Binary => "binary",
+            Radix::Octal => "octal",
+            Radix::Decimal => "decimal",
+            Radix::Hexadecimal => "hexadecimal",
+        }
+    }
+
+    /// the base selected by the letter after a leading '0', e.g. 'x' in `0xFF`
+    pub(crate) fn from_prefix_char(c: char) -> Option<Radix> {
+        match c {
+            'b' | 'B' => Some(Radix::Binary),
+            'o' | 'O' => Some(Radix::Octal),
+            'x' | 'X' => Some(Radix::Hexadecimal),
+            _ => None,
+        }
+    }
+
+    /// writes `value` as a literal in this base, so that a result can be
+    /// printed the way its operands were written: `-0xff`, `0b1010`
+    pub fn format(self, value: i64) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        let magnitude = value.unsigned_abs();
+        match self {
+            Radix::Binary => format!("{}0b{:b}", sign, magnitude),
+            Radix::Octal => format!("{}0o{:o}", sign, magnitude),
+            Radix::Decimal => format!("{}{}", sign, magnitude),
+            Radix::Hexadecimal => format!("{}0x{:x}", sign, magnitude),
+        }
+    }
+}
+
+/// A number literal such as `42`, `3.14`, `.5`, `6.02E23`, `0xFF` or
+/// `1_000_000`. The original text is kept so that an evaluator can read it
+/// as whichever numeric type it works in, without a lossy conversion in
+/// between, and a pretty-printer can re-emit it as it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumLiteral {
+    text: String,
+    radix: Radix,
+}
+
+impl NumLiteral {
+    /// `text` must be a literal the lexer accepted, its radix is read from
+    /// the prefix
+    pub(crate) fn new(text: &str) -> Self {
+        let mut chars = text.chars();
+        let radix = match (chars.next(), chars.next()) {
+            (Some('0'), Some(c)) => Radix::from_prefix_char(c).unwrap_or(Radix::Decimal),
+            _ => Radix::Decimal,
+        };
+        NumLiteral {
+            text: text.to_string(),
+            radix,
+        }
+    }
+
+    /// the literal exactly as it was written
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// the base the literal was written in
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// the digits of the literal without its prefix and '_' separators
+    pub fn digits(&self) -> String {
+        self.text[self.radix.prefix().len()..]
+            .chars()
+            .filter(|c| *c != '_')
+            .collect()
+    }
+
+    /// true unless the literal has a fractional part or an exponent
+    pub fn is_integer(&self) -> bool {
+        self.radix != Radix::Decimal || !self.text.contains(['.', 'e', 'E'])
+    }
+
+    /// the value of an integer literal, `None` for a decimal literal or an
+    /// integer too large for an i64
+    pub fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() {
+            return None;
+        }
+        i64::from_str_radix(&self.digits(), self.radix.base()).ok()
+    }
+
+    /// the value of an integer literal of any size, `None` for a decimal
+    /// literal
+    pub fn to_big_int(&self) -> Option<BigInt> {
+        if !self.is_integer() {
+            return None;
+        }
+        BigInt::parse_bytes(self.digits().as_bytes(), self.radix.base())
+    }
+
+    /// the exact value of the literal, so `0.1` is 1/10. `None` when the
+    /// exponent is beyond ±100000 and the value too large to expand
+    pub fn to_big_rational(&self) -> Option<BigRational> {
+        if self.is_integer() {
+            return self.to_big_int().map(BigRational::from_integer);
+        }
+        let digits = self.digits();
+        let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+            None => (digits.as_str(), 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        // every fractional digit moves the decimal point one place left
+        let exponent = exponent.checked_sub(i64::try_from(frac_part.len()).ok()?)?;
+        let scale = u32::try_from(exponent.unsigned_abs())
+            .ok()
+            .filter(|scale| *scale <= MAX_EXACT_EXPONENT)?;
+        let numer: BigInt = format!("{}{}", int_part, frac_part).parse().ok()?;
+        let power = BigInt::from(10).pow(scale);
+        if exponent < 0 {
+            Some(BigRational::new(numer, power))
+        } else {
+            Some(BigRational::new(numer * power, BigInt::one()))
+        }
+    }
+
+    /// the nearest f64 to the literal, infinite if it is too large
+    pub fn to_f64(&self) -> f64 {
+        if self.radix == Radix::Decimal {
+            // every decimal literal the lexer accepts is also valid f64
+            // syntax once the separators are gone
+            return self.digits().parse().unwrap_or(f64::NAN);
+        }
+        let base = self.radix.base();
+        self.digits().chars().fold(0.0, |value, c| {
+            value * f64::from(base) + f64::from(c.to_digit(base).unwrap_or(0))
+        })
+    }
+}
+
+impl fmt::Display for NumLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_literal() {
+        let n = NumLiteral::new("42");
+        assert!(n.is_integer());
+        assert_eq!(n.to_i64(), Some(42));
+        assert_eq!(n.to_f64(), 42.0);
+        assert_eq!(NumLiteral::new("99999999999999999999").to_i64(), None);
+        assert_eq!(
+            NumLiteral::new("99999999999999999999").to_big_int(),
+            Some(BigInt::from(99999999999999999999u128))
+        );
+        assert_eq!(NumLiteral::new("1.5").to_big_int(), None);
+    }
+
+    #[test]
+    fn test_decimal_literals() {
+        let cases = [
+            ("2.75", 2.75),
+            (".5", 0.5),
+            ("10.", 10.0),
+            ("1e-9", 1e-9),
+            ("6.02E23", 6.02e23),
+            ("2e+3", 2000.0),
+        ];
+        for (text, value) in cases {
+            let n = NumLiteral::new(text);
+            assert!(!n.is_integer(), "{}", text);
+            assert_eq!(n.to_i64(), None);
+            assert_eq!(n.to_f64(), value);
+            assert_eq!(n.to_string(), text);
+        }
+        assert!(NumLiteral::new("1e999").to_f64().is_infinite());
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let cases = [
+            ("0xFF", Radix::Hexadecimal, 255),
+            ("0XfE", Radix::Hexadecimal, 254),
+            ("0o17", Radix::Octal, 15),
+            ("0b1010", Radix::Binary, 10),
+            ("1_000_000", Radix::Decimal, 1_000_000),
+            ("0", Radix::Decimal, 0),
+        ];
+        for (text, radix, value) in cases {
+            let n = NumLiteral::new(text);
+            assert_eq!(n.radix(), radix, "{}", text);
+            assert!(n.is_integer());
+            assert_eq!(n.to_i64(), Some(value));
+            assert_eq!(n.to_f64(), value as f64);
+            assert_eq!(n.to_string(), text);
+        }
+        assert_eq!(NumLiteral::new("0xFF_FF").digits(), "FFFF");
+        assert_eq!(NumLiteral::new("1_000.000_5").to_f64(), 1000.0005);
+
+        let huge = NumLiteral::new("0x1_0000_0000_0000_0000");
+        assert_eq!(huge.to_i64(), None);
+        assert_eq!(huge.to_big_int(), Some(BigInt::from(1u128 << 64)));
+        assert_eq!(huge.to_f64(), 2f64.powi(64));
+    }
+
+    #[test]
+    fn test_exact_literals() {
+        let ratio = |numer: i64, denom: i64| {
+            Some(BigRational::new(BigInt::from(numer), BigInt::from(denom)))
+        };
+        assert_eq!(NumLiteral::new("0.1").to_big_rational(), ratio(1, 10));
+        assert_eq!(NumLiteral::new("2.75").to_big_rational(), ratio(11, 4));
+        assert_eq!(NumLiteral::new(".5").to_big_rational(), ratio(1, 2));
+        assert_eq!(NumLiteral::new("10.").to_big_rational(), ratio(10, 1));
+        assert_eq!(
+            NumLiteral::new("1e-9").to_big_rational(),
+            ratio(1, 1_000_000_000)
+        );
+        assert_eq!(NumLiteral::new("1.5E3").to_big_rational(), ratio(1500, 1));
+        assert_eq!(
+            NumLiteral::new("1_000.000_1").to_big_rational(),
+            ratio(10_000_001, 10_000)
+        );
+        assert_eq!(NumLiteral::new("0x10").to_big_rational(), ratio(16, 1));
+        assert!(NumLiteral::new("1e100000").to_big_rational().is_some());
+        assert_eq!(NumLiteral::new("1e100001").to_big_rational(), None);
+        assert_eq!(
+            NumLiteral::new("1e99999999999999999999").to_big_rational(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_radix_format() {
+        assert_eq!(Radix::Hexadecimal.format(255), "0xff");
+        assert_eq!(Radix::Hexadecimal.format(-255), "-0xff");
+        assert_eq!(Radix::Octal.format(8), "0o10");
+        assert_eq!(Radix::Binary.format(10), "0b1010");
+        assert_eq!(Radix::Decimal.format(-42), "-42");
+        assert_eq!(
+            Radix::Binary.format(i64::MIN),
+            format!("-0b1{}", "0".repeat(63))
+        );
+    }
+}
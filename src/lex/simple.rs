@@ -1,84 +1,204 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+use super::number::NumLiteral;
+use super::{LexError, Span, Token};
+
+/// The tokens the input is split into before parsing
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
 pub enum LexToken {
-    Num(u32),
+    Num(NumLiteral),
     Add(char),
     Subtract(char),
     Div(char),
     Multi(char),
+    Modulo(char),
+    /// `//`
+    IntDiv(&'static str),
+    /// `^` or `**`
+    Power(&'static str),
+    /// a variable name
+    Ident(String),
+    /// a string literal, without its quotes and with escapes resolved
+    Str(String),
+    /// `true` or `false`
+    Bool(bool),
+    /// `=`, binding a variable
+    Assign(char),
+    /// `==`
+    Equal(&'static str),
+    /// `!=`
+    NotEqual(&'static str),
+    Less(char),
+    /// `<=`
+    LessEqual(&'static str),
+    Greater(char),
+    /// `>=`
+    GreaterEqual(&'static str),
+    /// `&&`
+    And(&'static str),
+    /// `||`
+    Or(&'static str),
+    /// a prefix `!`, negating a boolean
+    Not(char),
+    /// a postfix `!`, the factorial of the operand before it
+    Factorial(char),
+    /// a postfix `%`, the operand before it divided by 100
+    Percent(char),
+    /// `?`, between the condition and the first branch of a conditional
+    Question(char),
+    /// `:`, between the branches of a conditional
+    Colon(char),
+    /// the `if` keyword
+    If,
+    /// the `then` keyword
+    Then,
+    /// the `else` keyword
+    Else,
+    /// `(`, `[` or `{`
     LeftParen(char),
+    /// `)`, `]` or `}`
     RightParen(char),
+    /// `|`, opening or closing an absolute value, which the parser tells
+    /// apart
+    Bar(char),
+    /// `,`, separating the arguments of a function call
+    Comma(char),
+    /// `;`, separating two statements
+    Semicolon(char),
+    /// a line break that separates two statements
     Newline,
 }
 
 impl fmt::Display for LexToken {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             LexToken::Num(n) => {
                 write!(f, "{}", n)
             }
-            LexToken::Add(c) | LexToken::Subtract(c) | LexToken::Div(c) | LexToken::Multi(c) => {
+            LexToken::Ident(name) => {
+                write!(f, "{}", name)
+            }
+            LexToken::Str(s) => {
+                write!(f, "{}", super::quote(s))
+            }
+            LexToken::Bool(b) => {
+                write!(f, "{}", b)
+            }
+            LexToken::Add(c)
+            | LexToken::Subtract(c)
+            | LexToken::Div(c)
+            | LexToken::Multi(c)
+            | LexToken::Modulo(c)
+            | LexToken::Assign(c)
+            | LexToken::Less(c)
+            | LexToken::Greater(c)
+            | LexToken::Not(c)
+            | LexToken::Factorial(c)
+            | LexToken::Percent(c)
+            | LexToken::Question(c)
+            | LexToken::Colon(c) => {
                 write!(f, " {} ", c)
             }
-            LexToken::LeftParen(c) | LexToken::RightParen(c) => {
+            LexToken::If => {
+                write!(f, "if")
+            }
+            LexToken::Then => {
+                write!(f, "then")
+            }
+            LexToken::Else => {
+                write!(f, "else")
+            }
+            LexToken::Power(s)
+            | LexToken::IntDiv(s)
+            | LexToken::Equal(s)
+            | LexToken::NotEqual(s)
+            | LexToken::LessEqual(s)
+            | LexToken::GreaterEqual(s)
+            | LexToken::And(s)
+            | LexToken::Or(s) => {
+                write!(f, " {} ", s)
+            }
+            LexToken::LeftParen(c)
+            | LexToken::RightParen(c)
+            | LexToken::Bar(c)
+            | LexToken::Comma(c)
+            | LexToken::Semicolon(c) => {
                 write!(f, "{}", c)
             }
             LexToken::Newline => {
-                write!(f, "{}", "NL")
+                write!(f, "newline")
             }
         }
     }
 }
 
-/// Handles single digit only, converts a char to a single digit
-fn get_number_from_char(c: char) -> u32 {
-    let n = c.to_string().parse::<u32>().expect("Expected a digit!");
-    return n;
+/// Handles single digit only, converts a char to a single digit literal
+fn get_number_from_char(c: char, span: Span) -> Result<NumLiteral, LexError> {
+    if !c.is_ascii_digit() {
+        return Err(LexError::InvalidCharacter { c, span });
+    }
+    Ok(NumLiteral::new(&c.to_string()))
 }
 
 /// Takes an input string, parses and returns a result containing
-/// a vector of lex tokens. On error, returns an error message
-pub(crate) fn lexer(s: &str) -> Result<Vec<LexToken>, String> {
-    let mut tokens: Vec<LexToken> = Vec::new();
+/// a vector of lex tokens. On error, returns the invalid character
+#[allow(dead_code)]
+pub(crate) fn lexer(s: &str) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
 
-    let mut tok_list = s.chars().peekable();
-    while let Some(&c) = tok_list.peek() {
+    // every token is a single character and newlines are rejected, so the
+    // line is always 1 and the column advances once per character
+    let mut column = 1;
+    let mut tok_list = s.char_indices().peekable();
+    while let Some(&(offset, c)) = tok_list.peek() {
+        let span = Span::new(offset, offset + c.len_utf8(), 1, column);
+        column += 1;
         match c {
             '0'..='9' => {
                 tok_list.next();
-                let n = get_number_from_char(c);
-                tokens.push(LexToken::Num(n));
-                println!("number: {}", c);
+                let n = get_number_from_char(c, span)?;
+                tokens.push(Token::new(LexToken::Num(n), span));
+                trace!("number: {}", c);
             }
             '+' => {
-                println!("plus: {}", c);
-                tokens.push(LexToken::Add(c));
+                trace!("plus: {}", c);
+                tokens.push(Token::new(LexToken::Add(c), span));
                 tok_list.next();
             }
             '-' => {
-                println!("minus: {}", c);
-                tokens.push(LexToken::Subtract(c));
+                trace!("minus: {}", c);
+                tokens.push(Token::new(LexToken::Subtract(c), span));
                 tok_list.next();
             }
             '/' => {
-                println!("div: {}", c);
-                tokens.push(LexToken::Div(c));
+                trace!("div: {}", c);
+                tokens.push(Token::new(LexToken::Div(c), span));
                 tok_list.next();
             }
             '*' => {
-                println!("multi: {}", c);
-                tokens.push(LexToken::Multi(c));
+                trace!("multi: {}", c);
+                tokens.push(Token::new(LexToken::Multi(c), span));
+                tok_list.next();
+            }
+            '%' => {
+                trace!("modulo: {}", c);
+                tokens.push(Token::new(LexToken::Modulo(c), span));
                 tok_list.next();
             }
-            '(' => {
-                println!("left bracket: {}", c);
-                tokens.push(LexToken::LeftParen(c));
+            '^' => {
+                trace!("power: {}", c);
+                tokens.push(Token::new(LexToken::Power("^"), span));
                 tok_list.next();
             }
-            ')' => {
-                println!("right bracket: {}", c);
-                tokens.push(LexToken::RightParen(c));
+            '(' | '[' | '{' => {
+                trace!("left bracket: {}", c);
+                tokens.push(Token::new(LexToken::LeftParen(c), span));
+                tok_list.next();
+            }
+            ')' | ']' | '}' => {
+                trace!("right bracket: {}", c);
+                tokens.push(Token::new(LexToken::RightParen(c), span));
                 tok_list.next();
             }
             ' ' => {
@@ -86,8 +206,8 @@ pub(crate) fn lexer(s: &str) -> Result<Vec<LexToken>, String> {
                 tok_list.next();
             }
             _ => {
-                println!("=> invalid character found: {}", c);
-                return Err(format!("Invalid character found: {}", c));
+                trace!("=> invalid character found: {}", c);
+                return Err(LexError::InvalidCharacter { c, span });
             }
         }
     }
@@ -99,53 +219,80 @@ pub(crate) fn lexer(s: &str) -> Result<Vec<LexToken>, String> {
 mod tests {
     use super::*;
 
+    fn num(text: &str) -> LexToken {
+        LexToken::Num(NumLiteral::new(text))
+    }
+
     #[test]
     fn test_lexer_div_expr() {
         let s = "(2/3)";
         let tokens = lexer(s).unwrap();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(2));
-        assert_eq!(tokens[2], LexToken::Div('/'));
-        assert_eq!(tokens[3], LexToken::Num(3));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("2"));
+        assert_eq!(tokens[2].kind, LexToken::Div('/'));
+        assert_eq!(tokens[3].kind, num("3"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
     }
 
     #[test]
     fn test_lexer_add_expr() {
         let s = "(2+3)";
         let tokens = lexer(s).unwrap();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(2));
-        assert_eq!(tokens[2], LexToken::Add('+'));
-        assert_eq!(tokens[3], LexToken::Num(3));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("2"));
+        assert_eq!(tokens[2].kind, LexToken::Add('+'));
+        assert_eq!(tokens[3].kind, num("3"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
     }
 
     #[test]
     fn test_lexer_subtraction_expr() {
         let s = "(2-3)";
         let tokens = lexer(s).unwrap();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(2));
-        assert_eq!(tokens[2], LexToken::Subtract('-'));
-        assert_eq!(tokens[3], LexToken::Num(3));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("2"));
+        assert_eq!(tokens[2].kind, LexToken::Subtract('-'));
+        assert_eq!(tokens[3].kind, num("3"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
     }
 
     #[test]
     fn test_lexer_div_multi_add_subtraction_expr() {
         let s = "(2/3)*4+5-6";
         let tokens = lexer(s).unwrap();
-        assert_eq!(tokens[0], LexToken::LeftParen('('));
-        assert_eq!(tokens[1], LexToken::Num(2));
-        assert_eq!(tokens[2], LexToken::Div('/'));
-        assert_eq!(tokens[3], LexToken::Num(3));
-        assert_eq!(tokens[4], LexToken::RightParen(')'));
-        assert_eq!(tokens[5], LexToken::Multi('*'));
-        assert_eq!(tokens[6], LexToken::Num(4));
-        assert_eq!(tokens[7], LexToken::Add('+'));
-        assert_eq!(tokens[8], LexToken::Num(5));
-        assert_eq!(tokens[9], LexToken::Subtract('-'));
-        assert_eq!(tokens[10], LexToken::Num(6));
+        assert_eq!(tokens[0].kind, LexToken::LeftParen('('));
+        assert_eq!(tokens[1].kind, num("2"));
+        assert_eq!(tokens[2].kind, LexToken::Div('/'));
+        assert_eq!(tokens[3].kind, num("3"));
+        assert_eq!(tokens[4].kind, LexToken::RightParen(')'));
+        assert_eq!(tokens[5].kind, LexToken::Multi('*'));
+        assert_eq!(tokens[6].kind, num("4"));
+        assert_eq!(tokens[7].kind, LexToken::Add('+'));
+        assert_eq!(tokens[8].kind, num("5"));
+        assert_eq!(tokens[9].kind, LexToken::Subtract('-'));
+        assert_eq!(tokens[10].kind, num("6"));
+    }
+
+    #[test]
+    fn test_lexer_token_spans() {
+        let s = "(2 / 3)";
+        let tokens = lexer(s).unwrap();
+        assert_eq!(tokens[0].span, Span::new(0, 1, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(1, 2, 1, 2));
+        assert_eq!(tokens[2].span, Span::new(3, 4, 1, 4));
+        assert_eq!(tokens[3].span, Span::new(5, 6, 1, 6));
+        assert_eq!(tokens[4].span, Span::new(6, 7, 1, 7));
+    }
+
+    #[test]
+    fn test_lexer_invalid_character() {
+        let s = "(2 $ 3)";
+        assert_eq!(
+            lexer(s),
+            Err(LexError::InvalidCharacter {
+                c: '$',
+                span: Span::new(3, 4, 1, 4)
+            })
+        );
     }
 }
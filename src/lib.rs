@@ -0,0 +1,660 @@
+//! A recursive descent parser and evaluator for arithmetic expressions.
+//!
+//! ```
+//! let expr = math_parser::parse_expr("8 - 2 * (1 + 2)").unwrap();
+//! assert_eq!(expr.to_string(), "8 - 2 * (1 + 2)");
+//! assert_eq!(math_parser::evaluate(&expr).unwrap(), math_parser::Number::Int(2));
+//! ```
+//!
+//! The input is lexed into [`Token`]s, parsed into a concrete [`ParseNode`]
+//! tree following the grammar below, and lowered into an [`Expr`] (or a
+//! [`Program`] of several statements) which the evaluator works on.
+//!
+//! Grammar rules:
+//!
+//! ```text
+//! start_rule: statement (SEPARATOR statement)*
+//! statement: VARIABLE '=' conditional_expr
+//!     | VARIABLE '(' params? ')' '=' conditional_expr | conditional_expr
+//! params: VARIABLE (',' VARIABLE)*
+//! conditional_expr: or_expr '?' conditional_expr ':' conditional_expr
+//!     | 'if' conditional_expr 'then' conditional_expr 'else' conditional_expr
+//!     | or_expr
+//! or_expr: or_expr '||' and_expr | and_expr
+//! and_expr: and_expr '&&' comparison_expr | comparison_expr
+//! comparison_expr: expr COMPARISON expr | expr
+//! expr: expr '+' multi_div_expr | expr '-' multi_div_expr | multi_div_expr
+//! multi_div_expr: multi_div_expr '*' implicit_expr | multi_div_expr '/' implicit_expr
+//!     | multi_div_expr '%' implicit_expr | multi_div_expr '//' implicit_expr
+//!     | implicit_expr
+//! implicit_expr: implicit_expr power_expr | unary_expr
+//! unary_expr: '-' unary_expr | '+' unary_expr | '!' unary_expr | power_expr
+//! power_expr: postfix_expr '^' unary_expr | postfix_expr
+//! postfix_expr: postfix_expr '!' | postfix_expr '%' | term
+//! term: NUMBER | STRING | BOOLEAN | VARIABLE | call | ( conditional_expr )
+//!     | [ conditional_expr ] | { conditional_expr } | '|' conditional_expr '|'
+//! call: VARIABLE '(' ')'
+//!     | VARIABLE '(' conditional_expr (',' conditional_expr)* ')'
+//! ```
+//!
+//! `**` is accepted as another spelling of `^`. A NUMBER is an integer, a
+//! decimal literal such as `3.14`, `.5`, `10.` or `6.02E23`, or an integer
+//! with a `0x`, `0o` or `0b` prefix. Digits may be grouped with '_'
+//! (`1_000_000`). Integer arithmetic is exact and only turns into floating
+//! point when a decimal literal is involved or a division has no integer
+//! result.
+//!
+//! Square brackets and braces group like parentheses, and each must be
+//! closed by its own kind, so `(1 + 2]` is an error pointing at both
+//! brackets. Bars around an expression take its absolute value. A `|` right
+//! after an operand closes the innermost open bar, any other `|` opens one,
+//! and `||` is only a logical or where it could not close a bar:
+//!
+//! ```
+//! use math_parser::{eval, Error, Number, ParseError};
+//!
+//! assert_eq!(eval("[2 * {3 - 5}] ^ 2").unwrap(), Number::Int(16));
+//! assert_eq!(eval("|1 - |2 - 7||").unwrap(), Number::Int(4));
+//! assert!(matches!(
+//!     eval("(1 + 2]"),
+//!     Err(Error::Parse(ParseError::MismatchedBracket { open: '(', close: ']', .. }))
+//! ));
+//! ```
+//!
+//! A postfix `!` is the factorial and a postfix `%` divides by 100, so
+//! `50% * 200` is 100. Both bind tighter than `^`. A `%` is the remainder
+//! instead when an operand follows it, as in `7 % 2` or `7 % -2`, where the
+//! sign is written against the operand. `!=` is always a comparison, so
+//! `5!=120` is true. The factorial of a negative or fractional number is an
+//! error:
+//!
+//! ```
+//! use math_parser::{eval, Error, EvalError, Number, Value};
+//!
+//! assert_eq!(eval("50% * 200").unwrap(), Number::Float(100.0));
+//! assert_eq!(eval("2^3! - 7 % 4").unwrap(), Number::Int(61));
+//! assert_eq!(eval("7 % -2").unwrap(), Number::Int(-1));
+//! assert_eq!(eval("50% - 10").unwrap(), Number::Float(-9.5));
+//! assert_eq!(eval("5!=120").unwrap(), Value::Bool(true));
+//! assert!(matches!(eval("(-3)!"), Err(Error::Eval(EvalError::InvalidFactorial { .. }))));
+//! assert!(matches!(eval("21!"), Err(Error::Eval(EvalError::Overflow { .. }))));
+//! ```
+//!
+//! A VARIABLE is a name made of ASCII letters, digits and '_' that does not
+//! start with a digit. Its value comes from the [`Environment`] the
+//! expression is evaluated in, and [`Expr::free_variables`] lists the names
+//! to bind beforehand:
+//!
+//! ```
+//! use math_parser::{eval_in, parse_expr, Environment, EvalOptions, Number};
+//!
+//! let s = "price * qty + shipping";
+//! assert_eq!(parse_expr(s).unwrap().free_variables(), ["price", "qty", "shipping"]);
+//! let env = Environment::new()
+//!     .with("price", Number::Int(12))
+//!     .with("qty", Number::Int(3))
+//!     .with("shipping", Number::Int(5));
+//! assert_eq!(eval_in(s, &env, &EvalOptions::new()).unwrap(), Number::Int(41));
+//! ```
+//!
+//! A call runs one of the built-in functions `sqrt`, `abs`, `floor`, `ceil`,
+//! `round`, `min`, `max`, `ln`, `log` (base 10, or `log(x, base)`), `exp`,
+//! `sin`, `cos`, `tan`, `asin`, `acos` and `atan`. Integer arguments give
+//! exact results where there is one, so `sqrt(16)` is 4. Calling an unknown
+//! function or passing the wrong number of arguments is an [`EvalError`].
+//!
+//! Applications add functions of their own by implementing [`Function`], or
+//! wrapping a closure in a [`NativeFunction`], and registering it in the
+//! [`Environment`]. Functions take and return [`Value`]s, which besides
+//! numbers can be strings. A STRING is written in double quotes, with `\"`
+//! and `\\` for a quote and a backslash, and is only useful as an argument
+//! or a variable's value: arithmetic on it is a type error.
+//!
+//! ```
+//! use math_parser::{eval_in, Arity, Environment, EvalError, EvalOptions};
+//! use math_parser::{NativeFunction, Number, Value};
+//!
+//! let fx = NativeFunction::new("fx", Arity::Exact(2), |args: &[Value]| {
+//!     let amount = args[0].expect_number()?.to_f64();
+//!     let rate = match args[1].expect_str()? {
+//!         "EUR" => 0.5,
+//!         currency => return Err(EvalError::custom(format!("no rate for {}", currency))),
+//!     };
+//!     Ok(Value::Number(Number::Float(amount * rate)))
+//! });
+//! let env = Environment::new().with_function(fx);
+//! let options = EvalOptions::new();
+//! assert_eq!(eval_in("fx(10, \"EUR\") + 1", &env, &options).unwrap(), Number::Float(6.0));
+//! assert!(eval_in("fx(10, \"GBP\")", &env, &options).is_err());
+//! ```
+//!
+//! A COMPARISON is one of `==`, `!=`, `<`, `<=`, `>` and `>=`, and gives a
+//! BOOLEAN, `true` or `false`. Comparisons bind looser than arithmetic and
+//! do not chain, so `0 < x < 10` is written `0 < x && x < 10`. `&&`, `||`
+//! and `!` only take booleans, and `&&` and `||` skip their right operand
+//! when the left one decides the result. `==` and `!=` compare two values
+//! of the same type and the other comparisons two numbers, anything else is
+//! a type error, as is `3 + true`:
+//!
+//! ```
+//! use math_parser::{eval_in, Environment, EvalOptions, Number, Value};
+//!
+//! let rule = "qty > 0 && price <= 1000";
+//! let env = Environment::new()
+//!     .with("qty", Number::Int(3))
+//!     .with("price", Number::Float(250.0));
+//! assert_eq!(eval_in(rule, &env, &EvalOptions::new()).unwrap(), Value::Bool(true));
+//! assert!(eval_in("qty + true", &env, &EvalOptions::new()).is_err());
+//! ```
+//!
+//! A conditional picks one of two values by a boolean condition, written
+//! `cond ? a : b` or `if cond then a else b`. It binds looser than every
+//! operator, so it needs parentheses inside a larger expression, and only
+//! the chosen branch is evaluated:
+//!
+//! ```
+//! use math_parser::{eval_in, Environment, EvalOptions, Number};
+//!
+//! let env = Environment::new().with("x", Number::Int(0));
+//! let options = EvalOptions::new();
+//! assert_eq!(eval_in("x == 0 ? 0 : 1 / x", &env, &options).unwrap(), Number::Int(0));
+//! let s = "if x > 100 then 0.9 else 1";
+//! assert_eq!(eval_in(s, &env, &options).unwrap(), Number::Int(1));
+//! ```
+//!
+//! The first rule of implicit_expr, multiplying factors written side by
+//! side, only applies with [`ParseOptions::implicit_multiplication`] set.
+//! Such a product binds tighter than `*` and `/`, so `1/2x` is `1/(2x)`,
+//! and looser than `^`, so `2x^2` is `2(x^2)`. A name followed by `(` is
+//! still a call, `2e3` is still a number and `2 3` is still an error.
+//! [`eval_in_with`] and [`eval_program_with`] evaluate with parse options:
+//!
+//! ```
+//! use math_parser::{eval_in_with, parse_expr_with, Environment, EvalOptions, Number, ParseOptions};
+//!
+//! let options = ParseOptions::new().implicit_multiplication(true);
+//! let expr = parse_expr_with("1/2x + (a+b)(a-b)", &options).unwrap();
+//! assert_eq!(expr.to_string(), "1 / 2x + (a + b)(a - b)");
+//! assert!(math_parser::parse_expr("3x").is_err());
+//!
+//! let env = Environment::new().with("x", Number::Int(5));
+//! let value = eval_in_with("3x", &env, &options, &EvalOptions::new()).unwrap();
+//! assert_eq!(value, Number::Int(15));
+//! ```
+//!
+//! A SEPARATOR is a `;` or a line break. A line break inside parentheses or
+//! after an operator does not end the statement, and empty statements are
+//! skipped. An assignment binds its variable for the statements after it,
+//! and a program's value is the value of its last statement:
+//!
+//! ```
+//! use math_parser::{eval_program, Environment, EvalOptions, Number};
+//!
+//! let result = eval_program("a = 3; b = a * 2\nb + 1", &Environment::new(), &EvalOptions::new())
+//!     .unwrap();
+//! assert_eq!(result.value.unwrap(), Number::Int(7));
+//! assert_eq!(result.env.get("b").unwrap(), &Number::Int(6));
+//! ```
+//!
+//! A statement `name(params) = body` defines a function, which is kept in
+//! the environment next to the variables. Its body is evaluated on each
+//! call with the parameters bound to the arguments, and may call the
+//! function itself up to [`EvalOptions::max_call_depth`] calls deep:
+//!
+//! ```
+//! use math_parser::{eval_program, Environment, EvalOptions, Number};
+//!
+//! let s = "f(x) = x^2 + 1\nhyp(a, b) = sqrt(f(a) + b^2 - 1)\nhyp(3, 4)";
+//! let result = eval_program(s, &Environment::new(), &EvalOptions::new()).unwrap();
+//! assert_eq!(result.value.unwrap(), Number::Int(5));
+//! assert_eq!(result.env.definition("f").unwrap().to_string(), "f(x) = x ^ 2 + 1");
+//! ```
+//!
+//! [`EvalOptions`] selects how `//` and `%` round negative quotients and
+//! whether integers are i64 or unbounded ([`EvalMode::BigInt`]):
+//!
+//! ```
+//! use math_parser::{eval_with, EvalMode, EvalOptions};
+//!
+//! let options = EvalOptions::new().mode(EvalMode::BigInt);
+//! let value = eval_with("99999999999999999999 * 3", &options).unwrap();
+//! assert_eq!(value.to_string(), "299999999999999999997");
+//! ```
+//!
+//! [`EvalMode::Rational`] computes with exact fractions instead, so decimal
+//! literals and division lose nothing:
+//!
+//! ```
+//! use math_parser::{eval_with, EvalMode, EvalOptions, Number, Value};
+//!
+//! let options = EvalOptions::new().mode(EvalMode::Rational);
+//! assert_eq!(eval_with("1/3 * 3", &options).unwrap().to_string(), "1");
+//! let Value::Number(Number::Rational(value)) = eval_with("0.1 + 1.2", &options).unwrap() else {
+//!     unreachable!()
+//! };
+//! assert_eq!(value.to_string(), "13/10");
+//! assert_eq!(value.to_mixed(), "1 3/10");
+//! assert_eq!(value.to_decimal(3), "1.300");
+//! ```
+
+/// Debug tracing of the lexer and parser, only printed when the crate is
+/// built with the `trace` feature
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "trace") {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod ast;
+mod cfg;
+mod diagnostics;
+mod eval;
+#[cfg(test)]
+mod fuzz_tests;
+mod lex;
+
+use std::fmt;
+
+use cfg::mathparser::MathParser;
+use lex::lex_multi_digit;
+
+pub use ast::{BinOp, CompareOp, Expr, LogicOp, LowerError, PostfixOp, Program, Statement};
+pub use cfg::{CfgTerm, ParseError, ParseNode, ParseOptions, DEFAULT_MAX_NESTING_DEPTH};
+pub use diagnostics::{Diagnostic, Style};
+pub use eval::{
+    evaluate, evaluate_in, evaluate_program, evaluate_with, Arity, DivisionRounding, Environment,
+    EvalError, EvalMode, EvalOptions, Fraction, Function, NativeFunction, Number, ProgramResult,
+    UserFunction, Value, DEFAULT_MAX_CALL_DEPTH, MAX_BIG_INT_BITS,
+};
+pub use lex::number::{NumLiteral, Radix};
+pub use lex::simple::LexToken;
+pub use lex::{LexError, Span, Token};
+
+/// Errors returned by [`tokenize`], the `parse` functions such as
+/// [`parse_expr`], and the `eval` functions such as [`eval_in`] and
+/// [`eval_program`]
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Lower(LowerError),
+    Eval(EvalError),
+}
+
+impl Error {
+    /// the part of the input where the error was found
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Lex(e) => e.span(),
+            Error::Parse(e) => e.span(),
+            Error::Lower(e) => e.span,
+            Error::Eval(e) => e.span(),
+        }
+    }
+
+    /// the error as a diagnostic that can be rendered against the input
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Error::Lex(e) => Diagnostic::from(e),
+            Error::Parse(e) => Diagnostic::from(e),
+            Error::Lower(e) => Diagnostic::from(e),
+            Error::Eval(e) => Diagnostic::from(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Lex(e) => write!(f, "{}", e),
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::Lower(e) => write!(f, "{}", e),
+            Error::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Lex(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Lower(e) => Some(e),
+            Error::Eval(e) => Some(e),
+        }
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Self {
+        Error::Lex(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<LowerError> for Error {
+    fn from(e: LowerError) -> Self {
+        Error::Lower(e)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Self {
+        Error::Eval(e)
+    }
+}
+
+/// Splits the input into lex tokens
+pub fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let my_lexer = lex_multi_digit::lexer(s)?;
+    Ok(my_lexer.get_tokens().to_vec())
+}
+
+/// Parses the input and returns the root of its parse tree
+pub fn parse(s: &str) -> Result<ParseNode, Error> {
+    parse_with(s, &ParseOptions::default())
+}
+
+/// Parses the input with the given options and returns the root of its
+/// parse tree
+pub fn parse_with(s: &str, options: &ParseOptions) -> Result<ParseNode, Error> {
+    let my_lexer = lex_multi_digit::lexer(s)?;
+    let mut math_parser = MathParser::new(s, my_lexer.get_tokens(), options.clone());
+    Ok(math_parser.parse()?)
+}
+
+/// Parses the input, which must be a single expression, and lowers its
+/// parse tree into an expression
+pub fn parse_expr(s: &str) -> Result<Expr, Error> {
+    parse_expr_with(s, &ParseOptions::default())
+}
+
+/// Parses the input, which must be a single expression, with the given
+/// options and lowers its parse tree into an expression
+pub fn parse_expr_with(s: &str, options: &ParseOptions) -> Result<Expr, Error> {
+    let parse_node = parse_with(s, options)?;
+    Ok(Expr::try_from(&parse_node)?)
+}
+
+/// Parses the input and lowers its parse tree into a program
+pub fn parse_program(s: &str) -> Result<Program, Error> {
+    parse_program_with(s, &ParseOptions::default())
+}
+
+/// Parses the input with the given options and lowers its parse tree into
+/// a program
+pub fn parse_program_with(s: &str, options: &ParseOptions) -> Result<Program, Error> {
+    let parse_node = parse_with(s, options)?;
+    Ok(Program::try_from(&parse_node)?)
+}
+
+/// Parses and evaluates the input
+pub fn eval(s: &str) -> Result<Value, Error> {
+    eval_with(s, &EvalOptions::default())
+}
+
+/// Parses and evaluates the input with the given options
+pub fn eval_with(s: &str, options: &EvalOptions) -> Result<Value, Error> {
+    eval_in(s, &Environment::new(), options)
+}
+
+/// Parses and evaluates the input, looking its variables up in `env`. The
+/// value of a program is the value of its last statement, and one that ends
+/// with a function definition has none.
+pub fn eval_in(s: &str, env: &Environment, options: &EvalOptions) -> Result<Value, Error> {
+    eval_in_with(s, env, &ParseOptions::default(), options)
+}
+
+/// Parses the input with `parse_options` and evaluates it as [`eval_in`]
+/// does
+pub fn eval_in_with(
+    s: &str,
+    env: &Environment,
+    parse_options: &ParseOptions,
+    options: &EvalOptions,
+) -> Result<Value, Error> {
+    let program = parse_program_with(s, parse_options)?;
+    let result = evaluate_program(&program, env, options)?;
+    let span = program
+        .statements
+        .last()
+        .map(Statement::span)
+        .unwrap_or_default();
+    Ok(result.value.ok_or(EvalError::NoValue { span })?)
+}
+
+/// Parses and runs the program, returning the value of its last statement
+/// and `env` with the assignments and function definitions applied
+pub fn eval_program(
+    s: &str,
+    env: &Environment,
+    options: &EvalOptions,
+) -> Result<ProgramResult, Error> {
+    eval_program_with(s, env, &ParseOptions::default(), options)
+}
+
+/// Parses the input with `parse_options` and runs it as [`eval_program`]
+/// does
+pub fn eval_program_with(
+    s: &str,
+    env: &Environment,
+    parse_options: &ParseOptions,
+    options: &EvalOptions,
+) -> Result<ProgramResult, Error> {
+    let program = parse_program_with(s, parse_options)?;
+    Ok(evaluate_program(&program, env, options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("(1 + 23)").unwrap();
+        let kinds: Vec<&LexToken> = tokens.iter().map(|t| &t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &LexToken::LeftParen('('),
+                &LexToken::Num(NumLiteral::new("1")),
+                &LexToken::Add('+'),
+                &LexToken::Num(NumLiteral::new("23")),
+                &LexToken::RightParen(')'),
+            ]
+        );
+        assert_eq!(tokens[3].span, Span::new(5, 7, 1, 6));
+    }
+
+    #[test]
+    fn test_parse_accessors() {
+        let parse_node = parse("2 * 3").unwrap();
+        assert_eq!(parse_node.kind(), &CfgTerm::NonTermStartRule);
+        assert_eq!(parse_node.depth(), 0);
+        assert_eq!(parse_node.span(), Span::new(0, 5, 1, 1));
+
+        let expr_node = &parse_node.children()[0];
+        assert_eq!(expr_node.kind(), &CfgTerm::NonTermExpr);
+        let mde_node = &expr_node.children()[0];
+        let kinds: Vec<&CfgTerm> = mde_node.children().iter().map(|n| n.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &CfgTerm::NonTermMultiDivExpr,
+                &CfgTerm::TermMultiply,
+                &CfgTerm::TermNumber(NumLiteral::new("3"))
+            ]
+        );
+        assert_eq!(mde_node.children()[1].span(), Span::new(2, 3, 1, 3));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = parse("1 + $").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Lex(LexError::InvalidCharacter { c: '$', .. })
+        ));
+        assert_eq!(err.span(), Span::new(4, 5, 1, 5));
+
+        let err = parse("1 +").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Parse(ParseError::UnexpectedEndOfInput { .. })
+        ));
+        assert_eq!(err.diagnostic().code, "E0102");
+        assert_eq!(
+            err.to_string(),
+            "Unexpected end of input, expected number, variable, function call, string, boolean, '(', '[', '{', '|', '-', '+' or '!' after '+'"
+        );
+
+        let err = eval("1 / (1 - 1)").unwrap_err();
+        assert!(matches!(err, Error::Eval(EvalError::DivisionByZero { .. })));
+        assert_eq!(err.span(), Span::new(5, 10, 1, 6));
+        assert_eq!(err.diagnostic().code, "E0201");
+
+        let err = eval("2 * \"EUR\"").unwrap_err();
+        assert_eq!(err.diagnostic().code, "E0207");
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected a number, found a string"
+        );
+        assert_eq!(tokenize("\"EUR").unwrap_err().diagnostic().code, "E0003");
+
+        let err = eval("f(x) = 2 * x").unwrap_err();
+        assert_eq!(
+            err,
+            Error::Eval(EvalError::NoValue {
+                span: Span::new(0, 12, 1, 1)
+            })
+        );
+        assert_eq!(err.diagnostic().code, "E0210");
+        assert_eq!(parse("f(x, x) = x").unwrap_err().diagnostic().code, "E0106");
+
+        let err = parse("0 < x < 10").unwrap_err();
+        assert_eq!(err.span(), Span::new(6, 7, 1, 7));
+        assert_eq!(err.diagnostic().code, "E0107");
+        assert_eq!(err.to_string(), "Comparison operators cannot be chained");
+        let err = parse("{1 + 2)").unwrap_err();
+        assert_eq!(err.span(), Span::new(6, 7, 1, 7));
+        assert_eq!(err.diagnostic().code, "E0108");
+        assert_eq!(
+            err.diagnostic().secondary,
+            Some((Span::new(0, 1, 1, 1), String::from("to close this '{'")))
+        );
+        assert_eq!(
+            err.to_string(),
+            "Mismatched brackets: '{' is closed by ')' instead of '}'"
+        );
+        assert_eq!(
+            parse("[1").unwrap_err().to_string(),
+            "Unbalanced parenthesis: '[' is never closed"
+        );
+        assert_eq!(
+            parse("1]").unwrap_err().to_string(),
+            "Unbalanced parenthesis: ']' has no matching '['"
+        );
+        let err = eval("2 * (0 - 3)!").unwrap_err();
+        assert_eq!(err.span(), Span::new(5, 10, 1, 6));
+        assert_eq!(err.diagnostic().code, "E0211");
+        assert_eq!(
+            err.to_string(),
+            "Factorial of a negative or fractional number"
+        );
+        let err = eval("3 + true").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected a number, found a boolean"
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("2 * 3"), Ok(Value::Number(Number::Int(6))));
+        assert_eq!(eval("8 - 2 - 1"), Ok(Value::Number(Number::Int(5))));
+        assert_eq!(eval("-(1 + 2) * -2"), Ok(Value::Number(Number::Int(6))));
+        let options = EvalOptions::new().division_rounding(DivisionRounding::Truncate);
+        assert_eq!(
+            eval_with("-7 // 2", &options),
+            Ok(Value::Number(Number::Int(-3)))
+        );
+        assert_eq!(eval("-7 // 2"), Ok(Value::Number(Number::Int(-4))));
+    }
+
+    #[test]
+    fn test_eval_program() {
+        let options = EvalOptions::new();
+        let env = Environment::new().with("price", Number::Int(10));
+        let result = eval_program("qty = 3;\n\ntotal = price * qty\n", &env, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(30))));
+        let bindings: Vec<(&str, String)> = result
+            .env
+            .iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                ("price", String::from("10")),
+                ("qty", String::from("3")),
+                ("total", String::from("30")),
+            ]
+        );
+        assert_eq!(
+            eval_in("a = 2; a ^ 10", &env, &options),
+            Ok(Value::Number(Number::Int(1024)))
+        );
+        // a single expression parses, a program does not
+        assert!(matches!(parse_expr("a = 1"), Err(Error::Lower(_))));
+        assert_eq!(
+            parse_program("a = 1;b=a").unwrap().to_string(),
+            "a = 1; b = a"
+        );
+    }
+
+    #[test]
+    fn test_eval_in() {
+        let env = Environment::new()
+            .with("price", Number::Int(12))
+            .with("qty", Number::Int(3))
+            .with("shipping", Number::Float(4.5));
+        let options = EvalOptions::new();
+        assert_eq!(
+            eval_in("price * qty + shipping", &env, &options),
+            Ok(Value::Number(Number::Float(40.5)))
+        );
+        assert_eq!(
+            eval("price * 2"),
+            Err(Error::Eval(EvalError::UnboundVariable {
+                names: vec![String::from("price")],
+                span: Span::new(0, 5, 1, 1)
+            }))
+        );
+    }
+
+    #[test]
+    fn test_eval_with_parse_options() {
+        let env = Environment::new().with("x", Number::Int(4));
+        let parse_options = ParseOptions::new().implicit_multiplication(true);
+        let options = EvalOptions::new();
+        assert_eq!(
+            eval_in_with("2x + 1", &env, &parse_options, &options),
+            Ok(Value::Number(Number::Int(9)))
+        );
+        assert!(matches!(
+            eval_in("2x + 1", &env, &options),
+            Err(Error::Parse(ParseError::TrailingInput { .. }))
+        ));
+        let result = eval_program_with("y = 3x\n2y", &env, &parse_options, &options).unwrap();
+        assert_eq!(result.value, Some(Value::Number(Number::Int(24))));
+        assert_eq!(result.env.get("y"), Some(&Value::Number(Number::Int(12))));
+    }
+}
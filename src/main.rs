@@ -1,36 +1,139 @@
-mod cfg;
-mod lex;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 
-use cfg::mathparser::MathParser;
-use lex::lex_multi_digit;
+use math_parser::{
+    eval_in_with, evaluate_program, parse_with, tokenize, Diagnostic, Environment, EvalMode,
+    EvalOptions, Number, ParseOptions, Program, Statement, Style, Value,
+};
 
-// grammar rules
-// start_rule: expr
-// expr: multi_div_expr + expr | multi_div_expr '-' expr | multi_div_expr
-// multi_div_expr: div_expr * multi_div_expr | div_expr
-// div_expr: term / div_expr | term
-// term: NUMBER | ( expr )
+/// digits after the point when a fraction is also shown as a decimal
+const DECIMAL_DIGITS: usize = 10;
 
-fn main() -> io::Result<()> {
+/// reads a line from stdin after printing `prompt`, `None` at the end of the
+/// input
+fn read_line(prompt: &str) -> io::Result<Option<String>> {
     let mut s = String::new();
-    print!("Enter math expression to parse:\n>>");
+    print!("{}", prompt);
     io::stdout().flush()?;
-    io::stdin().read_line(&mut s)?;
-
-    let _lexer =
-        lex_multi_digit::lexer(s.as_str()).expect("Failed to create a lexer with input {s}");
-    let lex_tokens = _lexer.get_tokens();
-    println!("lex tokens: {:?}", lex_tokens);
-    let mut math_parser = MathParser::new(lex_tokens);
-    let _ = math_parser.parse();
-    match math_parser.parsed_node {
-        Some(parse_node) => {
-            println!("\nparse node:\n\n{}", parse_node);
+    if io::stdin().read_line(&mut s)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(s))
+}
+
+/// parses and runs one line of input, keeping its assignments in `env`
+fn run(
+    s: &str,
+    env: &mut Environment,
+    parse_options: &ParseOptions,
+    options: &EvalOptions,
+    style: Style,
+) -> io::Result<()> {
+    let lex_tokens = match tokenize(s) {
+        Ok(lex_tokens) => lex_tokens,
+        Err(e) => {
+            print!("\n{}", e.diagnostic().render(s, style));
+            return Ok(());
+        }
+    };
+    let lex_token_kinds: Vec<_> = lex_tokens.iter().map(|t| &t.kind).collect();
+    println!("lex tokens: {:?}", lex_token_kinds);
+    let parse_node = match parse_with(s, parse_options) {
+        Ok(parse_node) => parse_node,
+        Err(e) => {
+            print!("\n{}", e.diagnostic().render(s, style));
+            return Ok(());
+        }
+    };
+    println!("\nparse node:\n\n{}", parse_node);
+    let program = match Program::try_from(&parse_node) {
+        Ok(program) => program,
+        Err(e) => {
+            print!("\n{}", Diagnostic::from(&e).render(s, style));
+            return Ok(());
+        }
+    };
+    println!("\nprogram: {}", program);
+
+    // ask for a value for every variable that is not bound yet
+    for name in program.free_variables() {
+        if env.get(name).is_some() {
+            continue;
+        }
+        let Some(input) = read_line(&format!("{} = ", name))? else {
+            return Ok(());
+        };
+        match eval_in_with(&input, &Environment::new(), parse_options, options) {
+            Ok(value) => env.set(name, value),
+            Err(e) => {
+                print!("\n{}", e.diagnostic().render(&input, style));
+                return Ok(());
+            }
+        };
+    }
+
+    match evaluate_program(&program, env, options) {
+        Ok(result) => {
+            match &result.value {
+                Some(Value::Number(Number::Rational(value))) if !value.is_integer() => println!(
+                    "\nresult: {} = {} ~ {}",
+                    value,
+                    value.to_mixed(),
+                    value.to_decimal(DECIMAL_DIGITS)
+                ),
+                Some(value) => println!("\nresult: {}", value),
+                // the line ends with a function definition
+                None => {
+                    if let Some(Statement::Define { name, .. }) = program.statements.last() {
+                        if let Some(function) = result.env.definition(name) {
+                            println!("\ndefined: {}", function);
+                        }
+                    }
+                }
+            }
+            *env = result.env;
+        }
+        Err(e) => print!("\n{}", Diagnostic::from(&e).render(s, style)),
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    // --bigint evaluates integers exactly, however large they get, and
+    // --rational keeps every result an exact fraction, and --implicit-mul
+    // reads `2x` and `(a+b)(a-b)` as products
+    let mut parse_options = ParseOptions::new();
+    let mut options = EvalOptions::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--implicit-mul" => parse_options = parse_options.implicit_multiplication(true),
+            "--bigint" => options = options.mode(EvalMode::BigInt),
+            "--rational" => options = options.mode(EvalMode::Rational),
+            _ => {}
         }
-        _ => {
-            println!("Unknown error occurred!")
+    }
+
+    let style = if io::stdout().is_terminal() {
+        Style::Ansi
+    } else {
+        Style::Plain
+    };
+
+    // variables assigned and functions defined on one line stay for the
+    // lines after it
+    let mut env = Environment::new();
+    println!("Enter math expressions to parse, one per line (:defs lists the defined functions):");
+    while let Some(s) = read_line(">>")? {
+        match s.trim() {
+            "" => continue,
+            ":defs" => {
+                for function in env.definitions() {
+                    println!("{}", function);
+                }
+                continue;
+            }
+            _ => {}
         }
+        run(&s, &mut env, &parse_options, &options, style)?;
     }
 
     Ok(())